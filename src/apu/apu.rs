@@ -0,0 +1,300 @@
+//! The audio processing unit.
+
+use std::mem;
+
+use crate::apu::dmc::Dmc;
+use crate::apu::frame_counter::{FrameClock, FrameCounter};
+use crate::apu::mixer::{Channel, Mixer};
+use crate::apu::noise::Noise;
+use crate::apu::pulse::Pulse;
+use crate::apu::resampler::Resampler;
+use crate::apu::triangle::Triangle;
+use crate::region::Region;
+
+#[derive(Debug, Clone, Default)]
+pub struct Apu {
+    region: Region,
+
+    /// 0x4000-0x4003
+    pulse1: Pulse,
+    /// 0x4004-0x4007
+    pulse2: Pulse,
+    /// 0x4008-0x400B
+    triangle: Triangle,
+    /// 0x400C-0x400F
+    noise: Noise,
+    /// 0x4010-0x4013
+    dmc: Dmc,
+
+    /// 0x4017
+    frame_counter: FrameCounter,
+
+    /// The pulse timers are clocked every other CPU cycle
+    odd_cycle: bool,
+
+    mixer: Mixer,
+    /// Audio output is off until a sample rate is set
+    resampler: Option<Resampler>,
+    /// One per channel, indexed by `Channel::index`, when each channel's
+    /// output is wanted separately
+    channel_resamplers: Vec<Resampler>,
+    /// Samples from `channel_resamplers` that haven't been taken yet
+    channel_samples: Vec<Vec<f32>>,
+    /// CPU cycles since the samples were last taken
+    clock: u32,
+    /// The level of the cartridge's sound chip, if it has one
+    expansion: f32,
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Self {
+            pulse1: Pulse::new(1),
+            pulse2: Pulse::new(2),
+            ..Self::default()
+        }
+    }
+
+    /// Noise and DMC periods depend on the region, so this should be set
+    /// before any sound is played.
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        // the CPU clock changed, so the resampling ratio did too
+        if let Some(resampler) = &self.resampler {
+            self.set_sample_rate(resampler.sample_rate());
+        }
+    }
+
+    /// Start producing audio samples at `sample_rate` Hz.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler = Some(Resampler::new(self.region.cpu_clock_hz(), sample_rate));
+        if !self.channel_resamplers.is_empty() {
+            self.set_channel_samples_enabled(true);
+        }
+        self.clock = 0;
+    }
+
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.resampler.as_ref().map(Resampler::sample_rate)
+    }
+
+    /// Also produce samples for each channel on its own, to be read with
+    /// `take_channel_samples`.  Needs a sample rate to be set first.
+    pub fn set_channel_samples_enabled(&mut self, enabled: bool) {
+        self.channel_resamplers.clear();
+        self.channel_samples.clear();
+        if let (true, Some(sample_rate)) = (enabled, self.sample_rate()) {
+            for _ in Channel::ALL.iter() {
+                let resampler = Resampler::new(self.region.cpu_clock_hz(), sample_rate);
+                self.channel_resamplers.push(resampler);
+                self.channel_samples.push(Vec::new());
+            }
+        }
+    }
+
+    /// Go back to `saved`, keeping the current mixer and audio output.
+    pub fn load_state(&mut self, saved: &Apu) {
+        let mut apu = saved.clone();
+        mem::swap(&mut apu.mixer, &mut self.mixer);
+        mem::swap(&mut apu.resampler, &mut self.resampler);
+        mem::swap(&mut apu.channel_resamplers, &mut self.channel_resamplers);
+        mem::swap(&mut apu.channel_samples, &mut self.channel_samples);
+        apu.clock = self.clock;
+        *self = apu;
+    }
+
+    /// Channel mutes and volumes.
+    pub fn mixer_mut(&mut self) -> &mut Mixer {
+        &mut self.mixer
+    }
+
+    /// Append the samples produced since the last call to `out`, as floats
+    /// between -1.0 and 1.0.  Nothing is produced until `set_sample_rate` is
+    /// called.
+    pub fn take_samples(&mut self, out: &mut Vec<f32>) {
+        if let Some(resampler) = &mut self.resampler {
+            resampler.end_frame(self.clock);
+            resampler.read_samples(out);
+        }
+        for (resampler, samples) in self
+            .channel_resamplers
+            .iter_mut()
+            .zip(&mut self.channel_samples)
+        {
+            resampler.end_frame(self.clock);
+            resampler.read_samples(samples);
+        }
+        self.clock = 0;
+    }
+
+    /// Append one channel's samples to `out`.  They're produced alongside the
+    /// mixed ones, so this only has new samples after `take_samples`.
+    pub fn take_channel_samples(&mut self, channel: Channel, out: &mut Vec<f32>) {
+        if let Some(samples) = self.channel_samples.get_mut(channel.index()) {
+            out.append(samples);
+        }
+    }
+
+    /// Run the APU for one CPU cycle.
+    pub fn step(&mut self) {
+        if self.odd_cycle {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.odd_cycle = !self.odd_cycle;
+
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+
+        match self.frame_counter.step(self.region.frame_counter_steps()) {
+            FrameClock::None => {}
+            FrameClock::Quarter => self.clock_quarter_frame(),
+            FrameClock::Half => self.clock_half_frame(),
+        }
+
+        if let Some(resampler) = &mut self.resampler {
+            let level = self.mixer.mix(
+                self.pulse1.output(),
+                self.pulse2.output(),
+                self.triangle.output(),
+                self.noise.output(),
+                self.dmc.output(),
+                self.expansion,
+            );
+            resampler.set_level(self.clock, level);
+
+            if !self.channel_resamplers.is_empty() {
+                let outputs = [
+                    self.pulse1.output() as f32,
+                    self.pulse2.output() as f32,
+                    self.triangle.output() as f32,
+                    self.noise.output() as f32,
+                    self.dmc.output() as f32,
+                    self.expansion,
+                ];
+                for (channel, resampler) in Channel::ALL.iter().zip(&mut self.channel_resamplers) {
+                    let level = self.mixer.mix_channel(*channel, outputs[channel.index()]);
+                    resampler.set_level(self.clock, level);
+                }
+            }
+            self.clock += 1;
+        }
+    }
+
+    /// Clocked by the frame counter every quarter frame.
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.clock_quarter_frame();
+        self.pulse2.clock_quarter_frame();
+        self.triangle.clock_quarter_frame();
+        self.noise.clock_quarter_frame();
+    }
+
+    /// Clocked by the frame counter every half frame.
+    fn clock_half_frame(&mut self) {
+        self.clock_quarter_frame();
+        self.pulse1.clock_half_frame();
+        self.pulse2.clock_half_frame();
+        self.triangle.clock_half_frame();
+        self.noise.clock_half_frame();
+    }
+
+    /// Set the level of the cartridge's sound chip, relative to
+    /// `mixer::PULSE_STEP`.  Mixed in from the next cycle on.
+    pub fn set_expansion_output(&mut self, level: f32) {
+        self.expansion = level;
+    }
+
+    /// The address the DMC wants to read a sample byte from, if any.
+    pub fn dmc_dma_address(&self) -> Option<u16> {
+        self.dmc.dma_address()
+    }
+
+    /// Give the DMC the byte it asked for with `dmc_dma_address`.
+    pub fn finish_dmc_dma(&mut self, value: u8) {
+        self.dmc.finish_dma(value);
+    }
+
+    /// Whether the APU is asserting the CPU's IRQ line.
+    pub fn irq(&self) -> bool {
+        self.dmc.irq || self.frame_counter.irq
+    }
+
+    pub fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            // all the channel registers are write only
+            0x4000..=0x4013 => 0,
+
+            // misc
+            0x4015 => {
+                let status = (self.pulse1.length.active() as u8)
+                    | (self.pulse2.length.active() as u8) << 1
+                    | (self.triangle.length.active() as u8) << 2
+                    | (self.noise.length.active() as u8) << 3
+                    | (self.dmc.active() as u8) << 4
+                    | (self.frame_counter.irq as u8) << 6
+                    | (self.dmc.irq as u8) << 7;
+                // reading acknowledges the frame IRQ, but not the DMC's
+                self.frame_counter.irq = false;
+                status
+            }
+            _ => {
+                error!(
+                    "Tried to read from address 0x{:X}, but that's an invalid address for the APU",
+                    addr
+                );
+                0
+            }
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            // channel 1
+            0x4000 => self.pulse1.write_control(val),
+            0x4001 => self.pulse1.write_sweep(val),
+            0x4002 => self.pulse1.write_timer_lo(val),
+            0x4003 => self.pulse1.write_timer_hi(val),
+
+            // channel 2
+            0x4004 => self.pulse2.write_control(val),
+            0x4005 => self.pulse2.write_sweep(val),
+            0x4006 => self.pulse2.write_timer_lo(val),
+            0x4007 => self.pulse2.write_timer_hi(val),
+
+            // channel 3: triangle
+            0x4008 => self.triangle.write_control(val),
+            0x4009 => { /* unused */ }
+            0x400A => self.triangle.write_timer_lo(val),
+            0x400B => self.triangle.write_timer_hi(val),
+
+            // channel 4: noise
+            0x400C => self.noise.write_control(val),
+            0x400D => { /* unused */ }
+            0x400E => self.noise.write_period(val, self.region.noise_periods()),
+            0x400F => self.noise.write_length(val),
+
+            // channel 5: dmc
+            0x4010 => self.dmc.write_control(val, self.region.dmc_rates()),
+            0x4011 => self.dmc.write_output_level(val),
+            0x4012 => self.dmc.write_sample_address(val),
+            0x4013 => self.dmc.write_sample_length(val),
+
+            // misc
+            0x4015 => {
+                self.pulse1.length.set_enabled(val & 0b0_0001 != 0);
+                self.pulse2.length.set_enabled(val & 0b0_0010 != 0);
+                self.triangle.length.set_enabled(val & 0b0_0100 != 0);
+                self.noise.length.set_enabled(val & 0b0_1000 != 0);
+                self.dmc.set_enabled(val & 0b1_0000 != 0);
+            }
+            0x4017 => self.frame_counter.write(val, self.odd_cycle),
+            _ => {
+                error!(
+                    "Tried to write value {} to address 0x{:X}, but that's an invalid address for the APU",
+                    val, addr
+                );
+            }
+        }
+    }
+}
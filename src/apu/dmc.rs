@@ -0,0 +1,247 @@
+//! The delta modulation channel, $4010-$4013, which plays 1 bit delta
+//! encoded samples read from CPU memory.
+
+#[derive(Debug, Clone, Default)]
+pub struct Dmc {
+    irq_enabled: bool,
+    looping: bool,
+    /// Set when a non-looping sample finishes with IRQs enabled
+    pub irq: bool,
+
+    /// Timer period in CPU cycles
+    timer_period: u16,
+    timer: u16,
+
+    /// 7 bit output level
+    output_level: u8,
+    shift_register: u8,
+    bits_remaining: u8,
+    silenced: bool,
+
+    /// The next byte to play, filled by DMA
+    sample_buffer: Option<u8>,
+
+    /// $4012, where the sample starts
+    sample_address: u16,
+    /// $4013, how many bytes the sample is
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+}
+
+impl Dmc {
+    /// $4010: IRQ enable, loop and rate, `rates` is the region's rate table.
+    pub fn write_control(&mut self, value: u8, rates: &[u16; 16]) {
+        self.irq_enabled = value & 0b1000_0000 != 0;
+        if !self.irq_enabled {
+            self.irq = false;
+        }
+        self.looping = value & 0b0100_0000 != 0;
+        self.timer_period = rates[value as usize & 0xF];
+    }
+
+    /// $4011: load the output level directly.
+    pub fn write_output_level(&mut self, value: u8) {
+        self.output_level = value & 0x7F;
+    }
+
+    /// $4012: sample address is %11AAAAAA.AA000000.
+    pub fn write_sample_address(&mut self, value: u8) {
+        self.sample_address = 0xC000 | ((value as u16) << 6);
+    }
+
+    /// $4013: sample length is %LLLL.LLLL0001 bytes.
+    pub fn write_sample_length(&mut self, value: u8) {
+        self.sample_length = ((value as u16) << 4) | 1;
+    }
+
+    /// Bit 4 of $4015 starts the sample if it isn't playing or stops it.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    /// Whether there are bytes left to play, reported in $4015.
+    pub fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    /// The address the memory reader wants to fetch from, if it needs a byte.
+    pub fn dma_address(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_address)
+        } else {
+            None
+        }
+    }
+
+    /// Called with the byte read by the DMA requested by `dma_address`.
+    pub fn finish_dma(&mut self, value: u8) {
+        self.sample_buffer = Some(value);
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period.saturating_sub(1);
+
+        if !self.silenced {
+            if self.shift_register & 1 != 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        if self.bits_remaining > 0 {
+            self.bits_remaining -= 1;
+        }
+        if self.bits_remaining == 0 {
+            // start a new output cycle
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.silenced = false;
+                    self.shift_register = sample;
+                }
+                None => self.silenced = true,
+            }
+        }
+    }
+
+    /// The current output level, 0-127.
+    pub fn output(&self) -> u8 {
+        self.output_level
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A rate table where the output unit is clocked every CPU cycle.
+    const RATES: [u16; 16] = [1; 16];
+
+    /// A DMC set up to play a `length` byte sample from $C000.
+    fn dmc(control: u8, length: u8) -> Dmc {
+        let mut dmc = Dmc::default();
+        dmc.write_control(control, &RATES);
+        dmc.write_sample_address(0x00);
+        dmc.write_sample_length(length);
+        dmc.set_enabled(true);
+        dmc
+    }
+
+    #[test]
+    fn fetches_each_byte_once() {
+        let mut dmc = dmc(0, 0);
+        assert_eq!(dmc.dma_address(), Some(0xC000));
+        dmc.finish_dma(0);
+        assert_eq!(dmc.dma_address(), None);
+        assert!(!dmc.active());
+    }
+
+    #[test]
+    fn fetches_wait_for_the_buffer_to_empty() {
+        let mut dmc = dmc(0, 1);
+        dmc.finish_dma(0);
+        assert_eq!(dmc.dma_address(), None);
+        // the first clock starts an output cycle, which empties the buffer
+        dmc.clock_timer();
+        assert_eq!(dmc.dma_address(), Some(0xC001));
+    }
+
+    #[test]
+    fn address_wraps_to_8000() {
+        let mut dmc = Dmc::default();
+        dmc.write_sample_address(0xFF);
+        dmc.write_sample_length(0x04);
+        dmc.set_enabled(true);
+        for _ in 0..64 {
+            dmc.finish_dma(0);
+        }
+        assert_eq!(dmc.current_address, 0x8000);
+    }
+
+    #[test]
+    fn irq_at_the_end_of_the_sample() {
+        let mut dmc = dmc(0b1000_0000, 0);
+        dmc.finish_dma(0);
+        assert!(dmc.irq);
+
+        dmc.write_control(0, &RATES);
+        assert!(!dmc.irq, "clearing the enable flag acknowledges the IRQ");
+    }
+
+    #[test]
+    fn looping() {
+        let mut dmc = dmc(0b1100_0000, 0);
+        dmc.finish_dma(0);
+        assert!(!dmc.irq, "looping samples never raise an IRQ");
+        assert!(dmc.active());
+        dmc.clock_timer();
+        assert_eq!(dmc.dma_address(), Some(0xC000));
+    }
+
+    #[test]
+    fn output_deltas() {
+        let mut dmc = dmc(0, 0);
+        dmc.write_output_level(64);
+        dmc.finish_dma(0b1111_0111);
+        dmc.clock_timer();
+        // +2 for each set bit and -2 for each clear bit
+        for _ in 0..8 {
+            dmc.clock_timer();
+        }
+        assert_eq!(dmc.output(), 62 + 12);
+
+        // with nothing in the buffer the output unit is silenced
+        for _ in 0..8 {
+            dmc.clock_timer();
+        }
+        assert_eq!(dmc.output(), 74);
+    }
+
+    #[test]
+    fn output_stays_in_range() {
+        let mut dmc = dmc(0, 0);
+        dmc.write_output_level(127);
+        dmc.finish_dma(0xFF);
+        for _ in 0..9 {
+            dmc.clock_timer();
+        }
+        assert_eq!(dmc.output(), 127);
+
+        dmc.write_output_level(1);
+        dmc.set_enabled(true);
+        dmc.finish_dma(0);
+        for _ in 0..9 {
+            dmc.clock_timer();
+        }
+        assert_eq!(dmc.output(), 1);
+    }
+}
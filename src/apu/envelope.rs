@@ -0,0 +1,106 @@
+//! The envelope generator used by the pulse and noise channels.
+
+#[derive(Debug, Clone, Default)]
+pub struct Envelope {
+    /// Whether `volume` is output directly instead of the decay level
+    constant_volume: bool,
+    /// Also the length counter halt flag
+    looping: bool,
+    /// The constant volume, or the divider period
+    volume: u8,
+
+    start: bool,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    /// Bits 0-5 of the channel's first register.
+    pub fn write(&mut self, value: u8) {
+        self.looping = value & 0b0010_0000 != 0;
+        self.constant_volume = value & 0b0001_0000 != 0;
+        self.volume = value & 0b1111;
+    }
+
+    /// Restart the envelope, done when the length counter is loaded.
+    pub fn restart(&mut self) {
+        self.start = true;
+    }
+
+    /// Clocked by the frame counter every quarter frame.
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+            return;
+        }
+
+        if self.divider > 0 {
+            self.divider -= 1;
+            return;
+        }
+        self.divider = self.volume;
+        if self.decay > 0 {
+            self.decay -= 1;
+        } else if self.looping {
+            self.decay = 15;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.constant_volume {
+            self.volume
+        } else {
+            self.decay
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_volume() {
+        let mut envelope = Envelope::default();
+        envelope.write(0b0001_0111);
+        envelope.restart();
+        envelope.clock();
+        envelope.clock();
+        assert_eq!(envelope.output(), 7);
+    }
+
+    #[test]
+    fn decay() {
+        let mut envelope = Envelope::default();
+        // divider period 1, so the level drops every 2 clocks
+        envelope.write(0b0000_0001);
+        envelope.restart();
+        envelope.clock();
+        assert_eq!(envelope.output(), 15);
+        envelope.clock();
+        envelope.clock();
+        assert_eq!(envelope.output(), 14);
+        for _ in 0..28 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.output(), 0);
+        envelope.clock();
+        envelope.clock();
+        assert_eq!(envelope.output(), 0, "only looping envelopes restart");
+    }
+
+    #[test]
+    fn looping_decay() {
+        let mut envelope = Envelope::default();
+        envelope.write(0b0010_0000);
+        envelope.restart();
+        for _ in 0..16 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.output(), 0);
+        envelope.clock();
+        assert_eq!(envelope.output(), 15);
+    }
+}
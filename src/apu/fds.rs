@@ -0,0 +1,265 @@
+//! The Famicom Disk System's sound: a 64 step wavetable channel with a
+//! volume envelope, and a second wavetable that modulates its pitch.
+
+use crate::apu::mixer::PULSE_STEP;
+
+/// The output divider set by $4089, as a fraction of full volume.
+const MASTER_VOLUMES: [f32; 4] = [1.0, 2.0 / 3.0, 2.0 / 4.0, 2.0 / 5.0];
+
+/// Pitch changes for each 3 bit modulation table entry, `None` resets it.
+const MOD_ADJUSTMENTS: [Option<i8>; 8] = [
+    Some(0),
+    Some(1),
+    Some(2),
+    Some(4),
+    None,
+    Some(-4),
+    Some(-2),
+    Some(-1),
+];
+
+/// The full wave at full volume is about 2.4 times as loud as an APU pulse.
+const STEP: f32 = 2.4 * 15.0 * PULSE_STEP / (63.0 * 32.0);
+
+/// The FDS's output goes through a low pass filter at about 2 kHz.
+const LOW_PASS_HZ: f32 = 2000.0;
+
+#[derive(Debug, Clone, Default)]
+struct FdsEnvelope {
+    /// The envelope is off and `gain` is set directly
+    disabled: bool,
+    increase: bool,
+    speed: u8,
+    gain: u8,
+    timer: u32,
+}
+
+impl FdsEnvelope {
+    fn write(&mut self, value: u8) {
+        self.disabled = value & 0b1000_0000 != 0;
+        self.increase = value & 0b0100_0000 != 0;
+        self.speed = value & 0b0011_1111;
+        if self.disabled {
+            self.gain = self.speed;
+        }
+        self.timer = 0;
+    }
+
+    /// Clocked every CPU cycle.
+    fn clock(&mut self, master_speed: u8) {
+        if self.disabled || master_speed == 0 {
+            return;
+        }
+        self.timer += 1;
+        if self.timer < 8 * (master_speed as u32 + 1) * (self.speed as u32 + 1) {
+            return;
+        }
+        self.timer = 0;
+        if self.increase && self.gain < 32 {
+            self.gain += 1;
+        } else if !self.increase && self.gain > 0 {
+            self.gain -= 1;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FdsAudio {
+    /// 6 bit samples
+    wave: [u8; 64],
+    /// $4089 bit 7, the wave can only be written while it's halted
+    wave_write: bool,
+    master_volume: u8,
+
+    volume: FdsEnvelope,
+    /// The volume only changes at the start of the wave
+    latched_gain: u8,
+    frequency: u16,
+    wave_halt: bool,
+    envelope_halt: bool,
+    /// 16.16 position in `wave`, the top 6 bits of the integer part used
+    wave_accumulator: u32,
+
+    modulator: FdsEnvelope,
+    mod_table: [u8; 32],
+    mod_position: u8,
+    mod_frequency: u16,
+    mod_halt: bool,
+    mod_accumulator: u32,
+    /// 7 bit signed
+    mod_counter: i8,
+
+    /// $408A
+    master_envelope_speed: u8,
+
+    /// Low passed output
+    filtered: f32,
+    filter_alpha: f32,
+}
+
+impl Default for FdsAudio {
+    fn default() -> Self {
+        let cpu_clock = 1_789_773.0;
+        Self {
+            wave: [0; 64],
+            wave_write: false,
+            master_volume: 0,
+            volume: FdsEnvelope::default(),
+            latched_gain: 0,
+            frequency: 0,
+            wave_halt: true,
+            envelope_halt: false,
+            wave_accumulator: 0,
+            modulator: FdsEnvelope::default(),
+            mod_table: [0; 32],
+            mod_position: 0,
+            mod_frequency: 0,
+            mod_halt: true,
+            mod_accumulator: 0,
+            mod_counter: 0,
+            master_envelope_speed: 0xE8,
+            filtered: 0.0,
+            filter_alpha: 1.0 - (-2.0 * std::f32::consts::PI * LOW_PASS_HZ / cpu_clock).exp(),
+        }
+    }
+}
+
+impl FdsAudio {
+    /// Read $4040-$4092.
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0x4040..=0x407F => self.wave[(addr - 0x4040) as usize],
+            0x4090 => self.volume.gain,
+            0x4092 => self.modulator.gain,
+            _ => 0,
+        }
+    }
+
+    /// Write $4040-$408A.
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4040..=0x407F if self.wave_write => {
+                self.wave[(addr - 0x4040) as usize] = value & 0b11_1111;
+            }
+            0x4080 => self.volume.write(value),
+            0x4082 => self.frequency = (self.frequency & 0xF00) | value as u16,
+            0x4083 => {
+                self.frequency = (self.frequency & 0xFF) | ((value as u16 & 0b1111) << 8);
+                self.wave_halt = value & 0b1000_0000 != 0;
+                self.envelope_halt = value & 0b0100_0000 != 0;
+                if self.wave_halt {
+                    self.wave_accumulator = 0;
+                }
+            }
+            0x4084 => self.modulator.write(value),
+            0x4085 => {
+                // sign extend the 7 bit value
+                self.mod_counter = ((value << 1) as i8) >> 1;
+            }
+            0x4086 => self.mod_frequency = (self.mod_frequency & 0xF00) | value as u16,
+            0x4087 => {
+                self.mod_frequency = (self.mod_frequency & 0xFF) | ((value as u16 & 0b1111) << 8);
+                self.mod_halt = value & 0b1000_0000 != 0;
+                if self.mod_halt {
+                    self.mod_accumulator = 0;
+                }
+            }
+            // the table can only be written while the modulator is halted,
+            // and each write fills two entries
+            0x4088 if self.mod_halt => {
+                for _ in 0..2 {
+                    self.mod_table[self.mod_position as usize] = value & 0b111;
+                    self.mod_position = (self.mod_position + 1) & 0b1_1111;
+                }
+            }
+            0x4089 => {
+                self.wave_write = value & 0b1000_0000 != 0;
+                self.master_volume = value & 0b11;
+            }
+            0x408A => self.master_envelope_speed = value,
+            _ => {}
+        }
+    }
+
+    /// Run for one CPU cycle.
+    pub fn clock(&mut self) {
+        if !self.envelope_halt && !self.wave_halt {
+            self.volume.clock(self.master_envelope_speed);
+            self.modulator.clock(self.master_envelope_speed);
+        }
+
+        if !self.mod_halt && self.mod_frequency > 0 {
+            self.mod_accumulator += self.mod_frequency as u32;
+            if self.mod_accumulator >= 0x1_0000 {
+                self.mod_accumulator &= 0xFFFF;
+                self.step_modulator();
+            }
+        }
+
+        if !self.wave_halt && !self.wave_write {
+            let before = self.wave_accumulator >> 16;
+            self.wave_accumulator =
+                (self.wave_accumulator + self.modulated_frequency()) & 0x3F_FFFF;
+            if self.wave_accumulator >> 16 < before {
+                // back at the start of the wave
+                self.latched_gain = self.volume.gain.min(32);
+            }
+        }
+
+        let target = self.raw_output();
+        self.filtered += (target - self.filtered) * self.filter_alpha;
+    }
+
+    fn step_modulator(&mut self) {
+        let entry = self.mod_table[self.mod_position as usize];
+        self.mod_position = (self.mod_position + 1) & 0b1_1111;
+        self.mod_counter = match MOD_ADJUSTMENTS[entry as usize] {
+            // wrap around within 7 bits
+            Some(change) => (((self.mod_counter as i16 + change as i16) << 9) >> 9) as i8,
+            None => 0,
+        };
+    }
+
+    /// The wave's frequency after modulation, following the hardware's
+    /// rounding.
+    fn modulated_frequency(&self) -> u32 {
+        let pitch = self.frequency as i32;
+        if self.mod_halt {
+            return pitch as u32;
+        }
+
+        let counter = self.mod_counter as i32;
+        let mut temp = counter * self.modulator.gain as i32;
+        let remainder = temp & 0xF;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if counter < 0 { -1 } else { 2 };
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+
+        temp *= pitch;
+        let remainder = temp & 0x3F;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+        (pitch + temp).max(0) as u32
+    }
+
+    fn raw_output(&self) -> f32 {
+        if self.wave_write {
+            return 0.0;
+        }
+        let sample = self.wave[(self.wave_accumulator >> 16) as usize & 0x3F];
+        let level = sample as f32 * self.latched_gain as f32;
+        level * MASTER_VOLUMES[self.master_volume as usize] * STEP
+    }
+
+    pub fn output(&self) -> f32 {
+        self.filtered
+    }
+}
@@ -0,0 +1,171 @@
+//! The frame counter ($4017), which clocks the envelopes, sweeps, length
+//! counters and linear counter at roughly 240Hz and can raise an IRQ.
+
+/// What the frame counter clocked on a given cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameClock {
+    None,
+    /// Envelopes and the triangle's linear counter
+    Quarter,
+    /// Everything clocked on a quarter frame, plus length counters and sweeps
+    Half,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FrameCounter {
+    five_step: bool,
+    irq_inhibit: bool,
+    /// Set at the end of each 4-step sequence unless inhibited
+    pub irq: bool,
+
+    /// CPU cycles since the sequence was reset
+    cycle: u32,
+    /// Writes to $4017 reset the sequence after a 3 or 4 cycle delay
+    reset_delay: u8,
+}
+
+impl FrameCounter {
+    /// $4017: mode and IRQ inhibit.  `odd_cycle` is whether the write
+    /// happened in the middle of an APU cycle.
+    pub fn write(&mut self, value: u8, odd_cycle: bool) {
+        self.five_step = value & 0b1000_0000 != 0;
+        self.irq_inhibit = value & 0b0100_0000 != 0;
+        if self.irq_inhibit {
+            self.irq = false;
+        }
+        self.reset_delay = if odd_cycle { 4 } else { 3 };
+    }
+
+    /// Run for one CPU cycle, `steps` is the region's step table.
+    pub fn step(&mut self, steps: &[u32; 5]) -> FrameClock {
+        if self.reset_delay > 0 {
+            self.reset_delay -= 1;
+            if self.reset_delay == 0 {
+                self.cycle = 0;
+                // 5-step mode clocks everything immediately
+                if self.five_step {
+                    return FrameClock::Half;
+                }
+                return FrameClock::None;
+            }
+        }
+
+        self.cycle += 1;
+        let cycle = self.cycle;
+        if cycle == steps[0] || cycle == steps[2] {
+            FrameClock::Quarter
+        } else if cycle == steps[1] {
+            FrameClock::Half
+        } else if self.five_step {
+            if cycle == steps[4] {
+                FrameClock::Half
+            } else {
+                if cycle == steps[4] + 1 {
+                    self.cycle = 0;
+                }
+                FrameClock::None
+            }
+        } else {
+            // the IRQ flag is set over 3 cycles at the end of the sequence
+            if cycle + 1 >= steps[3] && cycle <= steps[3] + 1 && !self.irq_inhibit {
+                self.irq = true;
+            }
+            if cycle == steps[3] + 1 {
+                self.cycle = 0;
+            }
+            if cycle == steps[3] {
+                FrameClock::Half
+            } else {
+                FrameClock::None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::region::Region;
+
+    /// Run for `cycles` CPU cycles, returning the cycle number of each clock.
+    fn run(counter: &mut FrameCounter, cycles: u32) -> Vec<(u32, FrameClock)> {
+        let steps = Region::Ntsc.frame_counter_steps();
+        (1..=cycles)
+            .map(|cycle| (cycle, counter.step(steps)))
+            .filter(|&(_, clock)| clock != FrameClock::None)
+            .collect()
+    }
+
+    #[test]
+    fn four_step_sequence() {
+        let mut counter = FrameCounter::default();
+        assert_eq!(
+            run(&mut counter, 29830 + 7457),
+            [
+                (7457, FrameClock::Quarter),
+                (14913, FrameClock::Half),
+                (22371, FrameClock::Quarter),
+                (29829, FrameClock::Half),
+                (29830 + 7457, FrameClock::Quarter),
+            ]
+        );
+    }
+
+    #[test]
+    fn five_step_sequence() {
+        let mut counter = FrameCounter::default();
+        counter.write(0b1000_0000, false);
+        assert_eq!(
+            run(&mut counter, 3 + 37282 + 7457),
+            [
+                (3, FrameClock::Half),
+                (3 + 7457, FrameClock::Quarter),
+                (3 + 14913, FrameClock::Half),
+                (3 + 22371, FrameClock::Quarter),
+                (3 + 37281, FrameClock::Half),
+                (3 + 37282 + 7457, FrameClock::Quarter),
+            ]
+        );
+        assert!(!counter.irq, "5-step mode never raises an IRQ");
+    }
+
+    #[test]
+    fn write_delay() {
+        let mut counter = FrameCounter::default();
+        counter.write(0, true);
+        assert_eq!(
+            run(&mut counter, 4 + 7457)[0],
+            (4 + 7457, FrameClock::Quarter)
+        );
+    }
+
+    #[test]
+    fn irq() {
+        let mut counter = FrameCounter::default();
+        run(&mut counter, 29827);
+        assert!(!counter.irq);
+        run(&mut counter, 1);
+        assert!(counter.irq);
+
+        // clearing the flag in the last 2 cycles doesn't stick
+        counter.irq = false;
+        run(&mut counter, 2);
+        assert!(counter.irq);
+        counter.irq = false;
+        run(&mut counter, 29827);
+        assert!(!counter.irq);
+        run(&mut counter, 1);
+        assert!(counter.irq, "and it's set again in the next sequence");
+    }
+
+    #[test]
+    fn irq_inhibit() {
+        let mut counter = FrameCounter::default();
+        run(&mut counter, 29829);
+        assert!(counter.irq);
+        counter.write(0b0100_0000, false);
+        assert!(!counter.irq, "setting the inhibit flag clears the IRQ");
+        run(&mut counter, 2 * 29830);
+        assert!(!counter.irq);
+    }
+}
@@ -0,0 +1,81 @@
+//! The length counter that silences a channel after a set time.
+
+/// Lengths loaded by the top 5 bits of a channel's last register.
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, //
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct LengthCounter {
+    /// Set by $4015, the counter is held at 0 while disabled
+    enabled: bool,
+    halt: bool,
+    counter: u8,
+}
+
+impl LengthCounter {
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    pub fn set_halt(&mut self, halt: bool) {
+        self.halt = halt;
+    }
+
+    /// Load the counter from the 5 bit index written to the channel.
+    pub fn load(&mut self, index: u8) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[index as usize & 0x1F];
+        }
+    }
+
+    /// Clocked by the frame counter every half frame.
+    pub fn clock(&mut self) {
+        if !self.halt && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    /// Whether the channel is still playing.
+    pub fn active(&self) -> bool {
+        self.counter > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_and_count_down() {
+        let mut length = LengthCounter::default();
+        length.load(3);
+        assert!(!length.active(), "a disabled counter stays at 0");
+
+        length.set_enabled(true);
+        length.load(3);
+        length.clock();
+        assert!(length.active());
+        length.clock();
+        assert!(!length.active());
+    }
+
+    #[test]
+    fn halt_and_disable() {
+        let mut length = LengthCounter::default();
+        length.set_enabled(true);
+        length.set_halt(true);
+        length.load(3);
+        for _ in 0..10 {
+            length.clock();
+        }
+        assert!(length.active());
+
+        length.set_enabled(false);
+        assert!(!length.active());
+    }
+}
@@ -0,0 +1,253 @@
+//! The NES's nonlinear mixer, which combines the channel outputs into a
+//! single level.  The pulse channels and the triangle/noise/DMC channels are
+//! mixed through separate resistor networks, approximated here with the
+//! formulas from the nesdev wiki.
+//!
+//! Cartridge sound chips are mixed in linearly after the APU's channels, on
+//! a scale where `PULSE_STEP` is one volume step of an APU pulse channel.
+//!
+//! Channels can also be muted or have their volume changed.  This only
+//! affects what's heard, never the emulation itself.
+
+use std::str::FromStr;
+
+/// How much one volume step of a lone APU pulse channel at full volume adds
+/// to the mix.  Expansion audio levels are given relative to this.
+pub const PULSE_STEP: f32 = 95.88 / (8128.0 / 15.0 + 100.0) / 15.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+    /// Audio from a sound chip on the cartridge
+    Expansion,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 6] = [
+        Channel::Pulse1,
+        Channel::Pulse2,
+        Channel::Triangle,
+        Channel::Noise,
+        Channel::Dmc,
+        Channel::Expansion,
+    ];
+
+    /// The channel's bit in an enable mask, and its index in `ALL`.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Channel::Pulse1 => "pulse1",
+            Channel::Pulse2 => "pulse2",
+            Channel::Triangle => "triangle",
+            Channel::Noise => "noise",
+            Channel::Dmc => "dmc",
+            Channel::Expansion => "expansion",
+        }
+    }
+}
+
+impl FromStr for Channel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_ascii_lowercase();
+        Channel::ALL
+            .iter()
+            .copied()
+            .find(|channel| channel.name() == name)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown channel {:?}, expected pulse1, pulse2, triangle, noise, dmc or expansion",
+                    s
+                )
+            })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Mixer {
+    /// Bit n enables `Channel::ALL[n]`
+    enabled: u8,
+    /// Indexed by `Channel::index`
+    volumes: [f32; 6],
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mixer {
+    /// Every channel enabled at full volume.
+    pub const ALL_CHANNELS: u8 = 0b11_1111;
+
+    pub fn new() -> Self {
+        Self {
+            enabled: Self::ALL_CHANNELS,
+            volumes: [1.0; 6],
+        }
+    }
+
+    pub fn set_enabled(&mut self, channel: Channel, enabled: bool) {
+        let bit = 1 << channel.index();
+        if enabled {
+            self.enabled |= bit;
+        } else {
+            self.enabled &= !bit;
+        }
+    }
+
+    /// Mute every channel except `channel`.
+    pub fn solo(&mut self, channel: Channel) {
+        self.enabled = 1 << channel.index();
+    }
+
+    /// Scale a channel's level before it's mixed, 1.0 is normal volume.
+    pub fn set_volume(&mut self, channel: Channel, volume: f32) {
+        self.volumes[channel.index()] = volume.max(0.0);
+    }
+
+    /// The level a channel contributes to the mix.
+    fn level(&self, channel: Channel, output: f32) -> f32 {
+        if self.enabled & (1 << channel.index()) == 0 {
+            0.0
+        } else {
+            output * self.volumes[channel.index()]
+        }
+    }
+
+    /// Mix the channel levels into a single level, about 0.0 to 1.0 at
+    /// normal volume.  `expansion` is already mixed, see `PULSE_STEP`.
+    pub fn mix(
+        &self,
+        pulse1: u8,
+        pulse2: u8,
+        triangle: u8,
+        noise: u8,
+        dmc: u8,
+        expansion: f32,
+    ) -> f32 {
+        Self::mix_levels([
+            self.level(Channel::Pulse1, pulse1 as f32),
+            self.level(Channel::Pulse2, pulse2 as f32),
+            self.level(Channel::Triangle, triangle as f32),
+            self.level(Channel::Noise, noise as f32),
+            self.level(Channel::Dmc, dmc as f32),
+            self.level(Channel::Expansion, expansion),
+        ])
+    }
+
+    /// The level of one channel as if it were the only one playing,
+    /// ignoring whether it's muted.
+    pub fn mix_channel(&self, channel: Channel, output: f32) -> f32 {
+        let mut levels = [0.0; 6];
+        levels[channel.index()] = output * self.volumes[channel.index()];
+        Self::mix_levels(levels)
+    }
+
+    /// `levels` is indexed by `Channel::index`.
+    fn mix_levels(levels: [f32; 6]) -> f32 {
+        let [pulse1, pulse2, triangle, noise, dmc, expansion] = levels;
+
+        let pulse = pulse1 + pulse2;
+        let pulse = if pulse == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulse + 100.0)
+        };
+
+        let tnd = triangle / 8227.0 + noise / 12241.0 + dmc / 22638.0;
+        let tnd = if tnd == 0.0 {
+            0.0
+        } else {
+            159.79 / (1.0 / tnd + 100.0)
+        };
+
+        pulse + tnd + expansion
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(level: f32, expected: f32) {
+        assert!((level - expected).abs() < 1e-4, "{} != {}", level, expected);
+    }
+
+    #[test]
+    fn nonlinear_levels() {
+        let mixer = Mixer::new();
+        assert_eq!(mixer.mix(0, 0, 0, 0, 0, 0.0), 0.0);
+        assert_near(mixer.mix(15, 15, 0, 0, 0, 0.0), 0.2585);
+        assert_near(mixer.mix(0, 0, 15, 15, 127, 0.0), 0.7415);
+        assert_near(mixer.mix(1, 0, 0, 0, 0, 0.0), 0.01165);
+
+        // two channels together are quieter than the sum of each alone
+        let pulse = mixer.mix(15, 0, 0, 0, 0, 0.0);
+        assert!(mixer.mix(15, 15, 0, 0, 0, 0.0) < 2.0 * pulse);
+        let triangle = mixer.mix(0, 0, 15, 0, 0, 0.0);
+        let noise = mixer.mix(0, 0, 0, 15, 0, 0.0);
+        assert!(mixer.mix(0, 0, 15, 15, 0, 0.0) < triangle + noise);
+    }
+
+    #[test]
+    fn pulse_step() {
+        let mixer = Mixer::new();
+        let full = mixer.mix(15, 0, 0, 0, 0, 0.0);
+        assert_near(full / 15.0, PULSE_STEP);
+        assert_near(mixer.mix(0, 0, 0, 0, 0, PULSE_STEP), PULSE_STEP);
+    }
+
+    #[test]
+    fn mute_and_solo() {
+        let mut mixer = Mixer::new();
+        mixer.set_enabled(Channel::Pulse1, false);
+        assert_eq!(mixer.mix(15, 0, 0, 0, 0, 0.0), 0.0);
+        assert_eq!(
+            mixer.mix(15, 15, 0, 0, 0, 0.0),
+            mixer.mix(0, 15, 0, 0, 0, 0.0)
+        );
+
+        mixer.solo(Channel::Triangle);
+        assert_eq!(mixer.mix(15, 15, 0, 15, 127, 1.0), 0.0);
+        assert!(mixer.mix(0, 0, 15, 0, 0, 0.0) > 0.0);
+
+        mixer.set_enabled(Channel::Pulse1, true);
+        assert!(mixer.mix(15, 0, 0, 0, 0, 0.0) > 0.0);
+    }
+
+    #[test]
+    fn volume() {
+        let mut mixer = Mixer::new();
+        mixer.set_volume(Channel::Expansion, 0.5);
+        assert_near(mixer.mix(0, 0, 0, 0, 0, 0.2), 0.1);
+        mixer.set_volume(Channel::Noise, -1.0);
+        assert_eq!(mixer.mix(0, 0, 0, 15, 0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn mix_channel_ignores_mutes() {
+        let mut mixer = Mixer::new();
+        mixer.set_enabled(Channel::Dmc, false);
+        let unmuted = Mixer::new().mix(0, 0, 0, 0, 64, 0.0);
+        assert_eq!(mixer.mix_channel(Channel::Dmc, 64.0), unmuted);
+    }
+
+    #[test]
+    fn channel_names() {
+        for &channel in Channel::ALL.iter() {
+            assert_eq!(channel.name().parse(), Ok(channel));
+        }
+        assert_eq!("DMC".parse(), Ok(Channel::Dmc));
+        assert!("square".parse::<Channel>().is_err());
+    }
+}
@@ -0,0 +1,130 @@
+//! The MMC5's sound: two more pulse channels like the APU's and an 8 bit PCM
+//! channel.
+
+use crate::apu::mixer::PULSE_STEP;
+use crate::apu::pulse::Pulse;
+
+/// The MMC5 clocks its envelopes and length counters at a fixed 240 Hz
+/// instead of using the APU's frame counter.
+const FRAME_PERIOD: u16 = 7457;
+
+#[derive(Debug, Clone)]
+pub struct Mmc5Audio {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    /// $5010 bit 0, reading $8000-$BFFF plays the byte read
+    pcm_read_mode: bool,
+    pcm_irq_enabled: bool,
+    pcm_irq: bool,
+    pcm: u8,
+
+    frame_timer: u16,
+    odd_cycle: bool,
+}
+
+impl Default for Mmc5Audio {
+    fn default() -> Self {
+        Self {
+            pulse1: Pulse::without_sweep(),
+            pulse2: Pulse::without_sweep(),
+            pcm_read_mode: false,
+            pcm_irq_enabled: false,
+            pcm_irq: false,
+            pcm: 0,
+            frame_timer: 0,
+            odd_cycle: false,
+        }
+    }
+}
+
+impl Mmc5Audio {
+    /// Read $5010 or $5015.
+    pub fn read(&mut self, addr: u16) -> u8 {
+        let value = self.peek(addr);
+        if addr == 0x5010 {
+            self.pcm_irq = false;
+        }
+        value
+    }
+
+    /// Read $5010 or $5015 without acknowledging the IRQ.
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x5010 => (self.pcm_irq as u8) << 7 | self.pcm_read_mode as u8,
+            0x5015 => {
+                (self.pulse1.length.active() as u8) | (self.pulse2.length.active() as u8) << 1
+            }
+            _ => 0,
+        }
+    }
+
+    /// Write one of the registers at $5000-$5015.
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x5000 => self.pulse1.write_control(value),
+            0x5002 => self.pulse1.write_timer_lo(value),
+            0x5003 => self.pulse1.write_timer_hi(value),
+            0x5004 => self.pulse2.write_control(value),
+            0x5006 => self.pulse2.write_timer_lo(value),
+            0x5007 => self.pulse2.write_timer_hi(value),
+            0x5010 => {
+                self.pcm_read_mode = value & 0b0000_0001 != 0;
+                self.pcm_irq_enabled = value & 0b1000_0000 != 0;
+            }
+            0x5011 if !self.pcm_read_mode => self.write_pcm(value),
+            0x5015 => {
+                self.pulse1.length.set_enabled(value & 0b01 != 0);
+                self.pulse2.length.set_enabled(value & 0b10 != 0);
+            }
+            // $5001 and $5005 would be the sweep units, which the MMC5 lacks
+            _ => {}
+        }
+    }
+
+    /// In read mode, the CPU reading $8000-$BFFF plays the byte.
+    pub fn cpu_read(&mut self, addr: u16, value: u8) {
+        if self.pcm_read_mode && (0x8000..=0xBFFF).contains(&addr) {
+            self.write_pcm(value);
+        }
+    }
+
+    fn write_pcm(&mut self, value: u8) {
+        // 0 can't be played, it raises an IRQ instead
+        if value == 0 {
+            self.pcm_irq = true;
+        } else {
+            self.pcm = value;
+        }
+    }
+
+    pub fn irq(&self) -> bool {
+        self.pcm_irq && self.pcm_irq_enabled
+    }
+
+    /// Run for one CPU cycle.
+    pub fn clock(&mut self) {
+        if self.odd_cycle {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.odd_cycle = !self.odd_cycle;
+
+        self.frame_timer += 1;
+        if self.frame_timer >= FRAME_PERIOD {
+            self.frame_timer = 0;
+            // the quarter frame clocks are also half frame clocks here
+            self.pulse1.clock_half_frame();
+            self.pulse1.clock_quarter_frame();
+            self.pulse2.clock_half_frame();
+            self.pulse2.clock_quarter_frame();
+        }
+    }
+
+    /// The pulses match the APU's, and full scale PCM is about as loud as
+    /// both of them at full volume.
+    pub fn output(&self) -> f32 {
+        let pulses = (self.pulse1.output() + self.pulse2.output()) as f32 * PULSE_STEP;
+        let pcm = self.pcm as f32 / 255.0 * 30.0 * PULSE_STEP;
+        pulses + pcm
+    }
+}
@@ -0,0 +1,15 @@
+pub mod apu;
+pub mod dmc;
+pub mod envelope;
+pub mod fds;
+pub mod frame_counter;
+pub mod length_counter;
+pub mod mixer;
+pub mod mmc5;
+pub mod namco163;
+pub mod noise;
+pub mod pulse;
+pub mod resampler;
+pub mod sunsoft5b;
+pub mod triangle;
+pub mod vrc6;
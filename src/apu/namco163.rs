@@ -0,0 +1,120 @@
+//! The Namco 163's sound: up to 8 wavetable channels playing 4 bit samples
+//! from 128 bytes of internal RAM, which also holds their registers.
+
+use crate::apu::mixer::PULSE_STEP;
+
+/// CPU cycles each channel gets before the chip moves on to the next one.
+const CYCLES_PER_CHANNEL: u8 = 15;
+
+/// How loud one step of one channel is.  This varies a lot between boards,
+/// this puts a lone channel at full volume about 4 times as loud as an APU
+/// pulse.
+const STEP: f32 = 4.0 * 15.0 * PULSE_STEP / 225.0;
+
+#[derive(Debug, Clone)]
+pub struct Namco163Audio {
+    ram: [u8; 0x80],
+    /// Set by $F800, bit 7 auto increments
+    address: u8,
+
+    /// The channel being updated, channels count down from 7
+    channel: u8,
+    cycles: u8,
+    /// The last output of each channel, signed
+    outputs: [i16; 8],
+}
+
+impl Default for Namco163Audio {
+    fn default() -> Self {
+        Self {
+            ram: [0; 0x80],
+            address: 0,
+            channel: 7,
+            cycles: 0,
+            outputs: [0; 8],
+        }
+    }
+}
+
+impl Namco163Audio {
+    /// $F800-$FFFF: set the RAM address.
+    pub fn write_address(&mut self, value: u8) {
+        self.address = value;
+    }
+
+    /// $4800-$4FFF: read RAM.
+    pub fn read_data(&mut self) -> u8 {
+        let value = self.peek_data();
+        self.increment_address();
+        value
+    }
+
+    /// Read RAM without auto incrementing the address.
+    pub fn peek_data(&self) -> u8 {
+        self.ram[(self.address & 0x7F) as usize]
+    }
+
+    /// $4800-$4FFF: write RAM.
+    pub fn write_data(&mut self, value: u8) {
+        self.ram[(self.address & 0x7F) as usize] = value;
+        self.increment_address();
+    }
+
+    fn increment_address(&mut self) {
+        if self.address & 0x80 != 0 {
+            self.address = 0x80 | (self.address.wrapping_add(1) & 0x7F);
+        }
+    }
+
+    /// How many channels are enabled, 1-8.  They're the highest numbered.
+    fn channel_count(&self) -> u8 {
+        ((self.ram[0x7F] >> 4) & 0b111) + 1
+    }
+
+    /// Run for one CPU cycle.
+    pub fn clock(&mut self) {
+        self.cycles += 1;
+        if self.cycles < CYCLES_PER_CHANNEL {
+            return;
+        }
+        self.cycles = 0;
+
+        self.update_channel(self.channel);
+        self.channel = if self.channel <= 8 - self.channel_count() {
+            7
+        } else {
+            self.channel - 1
+        };
+    }
+
+    fn update_channel(&mut self, channel: u8) {
+        let base = 0x40 + channel as usize * 8;
+        let regs = &mut self.ram[base..base + 8];
+
+        let frequency = regs[0] as u32 | (regs[2] as u32) << 8 | (regs[4] as u32 & 0b11) << 16;
+        let length = (256 - (regs[4] & 0xFC) as u32) << 16;
+        let mut phase = regs[1] as u32 | (regs[3] as u32) << 8 | (regs[5] as u32) << 16;
+        phase = (phase + frequency) % length;
+        regs[1] = phase as u8;
+        regs[3] = (phase >> 8) as u8;
+        regs[5] = (phase >> 16) as u8;
+
+        let sample_addr = (regs[6] as u32 + (phase >> 16)) as u8;
+        let volume = (regs[7] & 0b1111) as i16;
+        let byte = self.ram[(sample_addr >> 1) as usize & 0x7F];
+        let sample = if sample_addr & 1 == 0 {
+            byte & 0b1111
+        } else {
+            byte >> 4
+        };
+        self.outputs[channel as usize] = (sample as i16 - 8) * volume;
+    }
+
+    /// The hardware plays the channels one after another, which averages
+    /// out to the mean of the enabled channels.
+    pub fn output(&self) -> f32 {
+        let count = self.channel_count();
+        let sum: i16 = self.outputs[8 - count as usize..].iter().sum();
+        sum as f32 / count as f32 * STEP
+    }
+}
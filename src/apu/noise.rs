@@ -0,0 +1,126 @@
+//! The pseudo-random noise channel, $400C-$400F.
+
+use crate::apu::envelope::Envelope;
+use crate::apu::length_counter::LengthCounter;
+
+#[derive(Debug, Clone)]
+pub struct Noise {
+    /// 15 bit linear feedback shift register
+    shift_register: u16,
+    /// Short mode feeds back from bit 6 instead of bit 1
+    short_mode: bool,
+
+    /// Timer period in CPU cycles
+    timer_period: u16,
+    timer: u16,
+
+    envelope: Envelope,
+    pub length: LengthCounter,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self {
+            // the register is 1 at power on
+            shift_register: 1,
+            short_mode: false,
+            timer_period: 0,
+            timer: 0,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+        }
+    }
+}
+
+impl Noise {
+    /// $400C: length counter halt and envelope.
+    pub fn write_control(&mut self, value: u8) {
+        self.length.set_halt(value & 0b0010_0000 != 0);
+        self.envelope.write(value);
+    }
+
+    /// $400E: mode and period, `periods` is the region's period table.
+    pub fn write_period(&mut self, value: u8, periods: &[u16; 16]) {
+        self.short_mode = value & 0b1000_0000 != 0;
+        self.timer_period = periods[value as usize & 0xF];
+    }
+
+    /// $400F: length counter load.
+    pub fn write_length(&mut self, value: u8) {
+        self.length.load(value >> 3);
+        self.envelope.restart();
+    }
+
+    /// Clocked every CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period.saturating_sub(1);
+
+        let other_bit = if self.short_mode { 6 } else { 1 };
+        let feedback = (self.shift_register ^ (self.shift_register >> other_bit)) & 1;
+        self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+    }
+
+    /// Clocked by the frame counter every quarter frame.
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Clocked by the frame counter every half frame.
+    pub fn clock_half_frame(&mut self) {
+        self.length.clock();
+    }
+
+    /// The current output level, 0-15.
+    pub fn output(&self) -> u8 {
+        if !self.length.active() || self.shift_register & 1 != 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A period table where the shift register is clocked every CPU cycle.
+    const PERIODS: [u16; 16] = [1; 16];
+
+    /// How many clocks the shift register takes to get back to 1.
+    fn sequence_length(short_mode: bool) -> usize {
+        let mut noise = Noise::default();
+        noise.write_period(if short_mode { 0x80 } else { 0 }, &PERIODS);
+        let mut clocks = 0;
+        loop {
+            noise.clock_timer();
+            clocks += 1;
+            if noise.shift_register == 1 {
+                return clocks;
+            }
+        }
+    }
+
+    #[test]
+    fn sequence_lengths() {
+        assert_eq!(sequence_length(false), 32767);
+        assert_eq!(sequence_length(true), 93);
+    }
+
+    #[test]
+    fn output_follows_bit_0() {
+        let mut noise = Noise::default();
+        noise.length.set_enabled(true);
+        noise.write_control(0b0001_1001);
+        noise.write_period(0, &PERIODS);
+        noise.write_length(0b1000);
+        assert_eq!(noise.output(), 0);
+        // the power on 1 shifts out, leaving bit 0 clear
+        noise.clock_timer();
+        assert_eq!(noise.output(), 9);
+    }
+}
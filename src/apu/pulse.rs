@@ -0,0 +1,241 @@
+//! The two square wave channels, $4000-$4007.
+
+use crate::apu::envelope::Envelope;
+use crate::apu::length_counter::LengthCounter;
+
+/// The 8 step sequences for each duty cycle.
+const DUTY_SEQUENCES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0], // 12.5%
+    [0, 1, 1, 0, 0, 0, 0, 0], // 25%
+    [0, 1, 1, 1, 1, 0, 0, 0], // 50%
+    [1, 0, 0, 1, 1, 1, 1, 1], // 25% negated
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct Pulse {
+    /// Pulse 1 negates its sweep with ones' complement, pulse 2 with two's
+    ones_complement: bool,
+    /// The MMC5's copies of the pulse channels have no sweep unit
+    no_sweep: bool,
+
+    duty: u8,
+    /// Position in the duty sequence, counts down
+    sequence_step: u8,
+
+    /// 11 bit timer period, in APU cycles (2 CPU cycles)
+    timer_period: u16,
+    timer: u16,
+
+    envelope: Envelope,
+    pub length: LengthCounter,
+
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_divider: u8,
+    sweep_reload: bool,
+}
+
+impl Pulse {
+    /// `channel` is 1 or 2.
+    pub fn new(channel: u8) -> Self {
+        Self {
+            ones_complement: channel == 1,
+            ..Self::default()
+        }
+    }
+
+    /// A pulse channel without a sweep unit, as on the MMC5.  These aren't
+    /// silenced at low periods either.
+    pub fn without_sweep() -> Self {
+        Self {
+            no_sweep: true,
+            ..Self::default()
+        }
+    }
+
+    /// $4000/$4004: duty, length counter halt and envelope.
+    pub fn write_control(&mut self, value: u8) {
+        self.duty = value >> 6;
+        self.length.set_halt(value & 0b0010_0000 != 0);
+        self.envelope.write(value);
+    }
+
+    /// $4001/$4005: sweep unit.
+    pub fn write_sweep(&mut self, value: u8) {
+        self.sweep_enabled = value & 0b1000_0000 != 0;
+        self.sweep_period = (value >> 4) & 0b111;
+        self.sweep_negate = value & 0b0000_1000 != 0;
+        self.sweep_shift = value & 0b111;
+        self.sweep_reload = true;
+    }
+
+    /// $4002/$4006: low 8 bits of the timer.
+    pub fn write_timer_lo(&mut self, value: u8) {
+        self.timer_period = (self.timer_period & 0x700) | value as u16;
+    }
+
+    /// $4003/$4007: length counter load and high 3 bits of the timer.
+    pub fn write_timer_hi(&mut self, value: u8) {
+        self.timer_period = (self.timer_period & 0xFF) | ((value as u16 & 0b111) << 8);
+        self.length.load(value >> 3);
+        self.sequence_step = 0;
+        self.envelope.restart();
+    }
+
+    /// Clocked every APU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequence_step = self.sequence_step.wrapping_sub(1) & 0b111;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// Clocked by the frame counter every quarter frame.
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Clocked by the frame counter every half frame.
+    pub fn clock_half_frame(&mut self) {
+        self.length.clock();
+        if self.no_sweep {
+            return;
+        }
+
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.muted() {
+            self.timer_period = self.sweep_target();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    /// The period the sweep unit is moving towards.
+    fn sweep_target(&self) -> u16 {
+        let change = self.timer_period >> self.sweep_shift;
+        if self.sweep_negate {
+            let change = if self.ones_complement {
+                change + 1
+            } else {
+                change
+            };
+            self.timer_period.saturating_sub(change)
+        } else {
+            self.timer_period + change
+        }
+    }
+
+    /// Very low periods, and sweeps that would overflow the timer, silence
+    /// the channel even if the sweep unit is disabled.
+    fn muted(&self) -> bool {
+        if self.no_sweep {
+            return false;
+        }
+        self.timer_period < 8 || self.sweep_target() > 0x7FF
+    }
+
+    /// The current output level, 0-15.
+    pub fn output(&self) -> u8 {
+        if !self.length.active()
+            || self.muted()
+            || DUTY_SEQUENCES[self.duty as usize][self.sequence_step as usize] == 0
+        {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A playing pulse channel at constant volume 15 with `period`.
+    fn pulse(channel: u8, period: u16) -> Pulse {
+        let mut pulse = Pulse::new(channel);
+        pulse.length.set_enabled(true);
+        pulse.write_control(0b1001_1111);
+        pulse.write_timer_lo(period as u8);
+        pulse.write_timer_hi((period >> 8) as u8 | 0b1000);
+        pulse
+    }
+
+    /// The output at each of the 8 steps of the duty sequence, which is
+    /// played backwards from step 7.
+    fn sequence(pulse: &mut Pulse) -> Vec<u8> {
+        (0..8)
+            .map(|_| {
+                for _ in 0..=pulse.timer_period {
+                    pulse.clock_timer();
+                }
+                pulse.output()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn duty_cycles() {
+        let mut pulse = pulse(1, 0x100);
+        assert_eq!(sequence(&mut pulse), [0, 0, 0, 15, 15, 15, 15, 0]);
+        pulse.write_control(0b0001_1111);
+        assert_eq!(sequence(&mut pulse), [0, 0, 0, 0, 0, 0, 15, 0]);
+    }
+
+    #[test]
+    fn low_periods_are_muted() {
+        let mut pulse = pulse(1, 7);
+        assert!(sequence(&mut pulse).iter().all(|&level| level == 0));
+
+        let mut pulse = Pulse::without_sweep();
+        pulse.length.set_enabled(true);
+        pulse.write_control(0b1001_1111);
+        pulse.write_timer_lo(7);
+        pulse.write_timer_hi(0b1000);
+        assert!(sequence(&mut pulse).contains(&15));
+    }
+
+    #[test]
+    fn sweep_overflow_mutes_even_when_disabled() {
+        let mut pulse = pulse(2, 0x700);
+        pulse.write_sweep(0b0000_0001);
+        assert!(sequence(&mut pulse).iter().all(|&level| level == 0));
+    }
+
+    #[test]
+    fn sweep_negation() {
+        // pulse 1 subtracts one more than pulse 2
+        for &(channel, target) in &[(1, 0x17F), (2, 0x180)] {
+            let mut pulse = pulse(channel, 0x200);
+            pulse.write_sweep(0b1000_1010);
+            pulse.clock_half_frame();
+            assert_eq!(pulse.timer_period, target);
+        }
+    }
+
+    #[test]
+    fn sweep_up() {
+        let mut pulse = pulse(2, 0x100);
+        pulse.write_sweep(0b1000_0001);
+        pulse.clock_half_frame();
+        assert_eq!(pulse.timer_period, 0x180);
+    }
+
+    #[test]
+    fn length_counter_silences() {
+        let mut pulse = pulse(1, 0x100);
+        // length index 1 is 254 half frames
+        for _ in 0..254 {
+            pulse.clock_half_frame();
+        }
+        assert!(!pulse.length.active());
+        assert!(sequence(&mut pulse).iter().all(|&level| level == 0));
+    }
+}
@@ -0,0 +1,259 @@
+//! Band-limited resampling of the APU output from the CPU clock down to an
+//! audio sample rate.
+//!
+//! Rather than resampling a 1.79MHz signal, each change in the output level
+//! is added as a step, smoothed with a windowed sinc so it contains no
+//! frequencies above the output's Nyquist limit.  The output is the running
+//! sum of the steps, passed through the same filters as the NES's audio
+//! output.
+
+use std::f64::consts::PI;
+
+/// Output samples each step is spread across.
+const KERNEL_WIDTH: usize = 16;
+/// Fractional sample positions the kernel is precomputed for.
+const PHASES: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    sample_rate: u32,
+    /// Output samples per input clock
+    samples_per_clock: f64,
+    /// `KERNEL_WIDTH` taps for each of the `PHASES` phases, each summing to 1
+    kernel: Vec<f32>,
+
+    /// Deltas for the samples after the ones already read
+    deltas: Vec<f32>,
+    /// Where clock 0 of the current frame lands in `deltas`
+    frame_start: f64,
+    /// The level of the input signal as of the last delta
+    level: f32,
+
+    /// Running sum of the deltas
+    accumulator: f32,
+    high_pass_90: HighPass,
+    high_pass_440: HighPass,
+    low_pass_14k: LowPass,
+}
+
+impl Resampler {
+    pub fn new(clock_rate: f64, sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            samples_per_clock: sample_rate as f64 / clock_rate,
+            kernel: make_kernel(),
+            deltas: vec![0.0; KERNEL_WIDTH * 2],
+            frame_start: 0.0,
+            level: 0.0,
+            accumulator: 0.0,
+            high_pass_90: HighPass::new(90.0, sample_rate),
+            high_pass_440: HighPass::new(440.0, sample_rate),
+            low_pass_14k: LowPass::new(14_000.0, sample_rate),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Set the input level at `clock` clocks into the current frame.
+    pub fn set_level(&mut self, clock: u32, level: f32) {
+        let delta = level - self.level;
+        if delta == 0.0 {
+            return;
+        }
+        self.level = level;
+
+        let position = self.frame_start + clock as f64 * self.samples_per_clock;
+        let sample = position as usize;
+        let phase = ((position - sample as f64) * PHASES as f64) as usize;
+        if self.deltas.len() < sample + KERNEL_WIDTH {
+            self.deltas.resize(sample + KERNEL_WIDTH, 0.0);
+        }
+        let taps = &self.kernel[phase * KERNEL_WIDTH..(phase + 1) * KERNEL_WIDTH];
+        for (out, tap) in self.deltas[sample..].iter_mut().zip(taps) {
+            *out += delta * tap;
+        }
+    }
+
+    /// End the current frame after `clocks` clocks, making the samples
+    /// before that point available.
+    pub fn end_frame(&mut self, clocks: u32) {
+        self.frame_start += clocks as f64 * self.samples_per_clock;
+    }
+
+    /// Append the finished samples to `out`.
+    pub fn read_samples(&mut self, out: &mut Vec<f32>) {
+        let available = self.frame_start as usize;
+        if self.deltas.len() < available + KERNEL_WIDTH {
+            self.deltas.resize(available + KERNEL_WIDTH, 0.0);
+        }
+        out.reserve(available);
+        for delta in self.deltas.drain(..available) {
+            self.accumulator += delta;
+            let sample = self.high_pass_90.filter(self.accumulator);
+            let sample = self.high_pass_440.filter(sample);
+            out.push(self.low_pass_14k.filter(sample));
+        }
+        self.frame_start -= available as f64;
+    }
+}
+
+/// A windowed sinc impulse for every phase, low passed a little below the
+/// output's Nyquist frequency.
+fn make_kernel() -> Vec<f32> {
+    let cutoff = 0.9;
+    let half_width = KERNEL_WIDTH as f64 / 2.0;
+    let mut kernel = Vec::with_capacity(KERNEL_WIDTH * PHASES);
+    for phase in 0..PHASES {
+        let offset = phase as f64 / PHASES as f64;
+        let taps: Vec<f64> = (0..KERNEL_WIDTH)
+            .map(|tap| {
+                // centred half the kernel after the step
+                let x = tap as f64 - half_width - offset + 1.0;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (PI * cutoff * x).sin() / (PI * cutoff * x)
+                };
+                // Blackman window
+                let w = (x + half_width) / KERNEL_WIDTH as f64;
+                let window = 0.42 - 0.5 * (2.0 * PI * w).cos() + 0.08 * (4.0 * PI * w).cos();
+                sinc * window
+            })
+            .collect();
+        let sum: f64 = taps.iter().sum();
+        kernel.extend(taps.iter().map(|tap| (tap / sum) as f32));
+    }
+    kernel
+}
+
+/// First order high pass filter.
+#[derive(Debug, Clone)]
+struct HighPass {
+    alpha: f32,
+    previous_input: f32,
+    previous_output: f32,
+}
+
+impl HighPass {
+    fn new(cutoff: f32, sample_rate: u32) -> Self {
+        let rc = 1.0 / (2.0 * std::f32::consts::PI * cutoff);
+        let dt = 1.0 / sample_rate as f32;
+        Self {
+            alpha: rc / (rc + dt),
+            previous_input: 0.0,
+            previous_output: 0.0,
+        }
+    }
+
+    fn filter(&mut self, input: f32) -> f32 {
+        let output = self.alpha * (self.previous_output + input - self.previous_input);
+        self.previous_input = input;
+        self.previous_output = output;
+        output
+    }
+}
+
+/// First order low pass filter.
+#[derive(Debug, Clone)]
+struct LowPass {
+    alpha: f32,
+    previous_output: f32,
+}
+
+impl LowPass {
+    fn new(cutoff: f32, sample_rate: u32) -> Self {
+        let rc = 1.0 / (2.0 * std::f32::consts::PI * cutoff);
+        let dt = 1.0 / sample_rate as f32;
+        Self {
+            alpha: dt / (rc + dt),
+            previous_output: 0.0,
+        }
+    }
+
+    fn filter(&mut self, input: f32) -> f32 {
+        self.previous_output += self.alpha * (input - self.previous_output);
+        self.previous_output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLOCK_RATE: f64 = 1_789_773.0;
+    const FRAME_CLOCKS: u32 = 29_780;
+
+    /// Resample a square wave that changes level every `half_period` clocks,
+    /// for `frames` frames.
+    fn square_wave(half_period: u32, frames: u32) -> (Resampler, Vec<f32>) {
+        let mut resampler = Resampler::new(CLOCK_RATE, 44_100);
+        let mut samples = Vec::new();
+        let mut clock = 0;
+        for _ in 0..frames {
+            while clock < FRAME_CLOCKS {
+                let high = (clock / half_period) & 1 == 0;
+                resampler.set_level(clock, if high { 1.0 } else { 0.0 });
+                clock += half_period - clock % half_period;
+            }
+            clock -= FRAME_CLOCKS;
+            resampler.end_frame(FRAME_CLOCKS);
+            resampler.read_samples(&mut samples);
+        }
+        (resampler, samples)
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples
+            .iter()
+            .fold(0.0, |peak, sample| sample.abs().max(peak))
+    }
+
+    #[test]
+    fn kernel_phases_sum_to_1() {
+        for taps in make_kernel().chunks(KERNEL_WIDTH) {
+            let sum: f32 = taps.iter().sum();
+            assert!((sum - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn sample_count() {
+        let (_, samples) = square_wave(1000, 600);
+        let expected = 600.0 * FRAME_CLOCKS as f64 * 44_100.0 / CLOCK_RATE;
+        assert!((samples.len() as f64 - expected).abs() < 1.0);
+    }
+
+    #[test]
+    fn steps_settle_at_the_new_level() {
+        let mut resampler = Resampler::new(CLOCK_RATE, 44_100);
+        resampler.set_level(100, 0.5);
+        resampler.end_frame(FRAME_CLOCKS);
+        resampler.read_samples(&mut Vec::new());
+        assert!((resampler.accumulator - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn dc_is_filtered_out() {
+        let mut resampler = Resampler::new(CLOCK_RATE, 44_100);
+        resampler.set_level(0, 1.0);
+        let mut samples = Vec::new();
+        for _ in 0..60 {
+            resampler.end_frame(FRAME_CLOCKS);
+            resampler.read_samples(&mut samples);
+        }
+        assert!(peak(&samples[samples.len() - 100..]) < 1e-3);
+    }
+
+    #[test]
+    fn ultrasonic_tones_are_removed() {
+        // about 1kHz, and a tone far above the 22kHz Nyquist frequency
+        let (_, audible) = square_wave(895, 10);
+        let (_, ultrasonic) = square_wave(10, 10);
+        let audible = peak(&audible[1000..]);
+        let ultrasonic = peak(&ultrasonic[1000..]);
+        assert!(audible > 0.5);
+        assert!(ultrasonic < audible / 20.0);
+    }
+}
@@ -0,0 +1,214 @@
+//! The Sunsoft 5B's sound, a YM2149F with three square channels, noise and
+//! a shared envelope.  Only Gimmick! uses it.
+
+use crate::apu::mixer::PULSE_STEP;
+
+/// The tone, noise and envelope timers count every 16 CPU cycles.  The
+/// squares toggle every time their timer runs out, and the noise steps every
+/// other time it does.
+const PRESCALER: u8 = 16;
+
+#[derive(Debug, Clone)]
+pub struct Sunsoft5bAudio {
+    /// Register selected by $C000
+    selected: u8,
+
+    /// 12 bit periods for channels A, B and C
+    tone_periods: [u16; 3],
+    tone_timers: [u16; 3],
+    tone_levels: [bool; 3],
+
+    /// 5 bit period
+    noise_period: u8,
+    noise_timer: u8,
+    /// Noise steps at half the tone rate
+    noise_half: bool,
+    /// 17 bit LFSR
+    noise: u32,
+
+    /// Bits 0-2 disable tone, bits 3-5 disable noise, for channels A-C
+    disable: u8,
+    /// Bits 0-3 volume, bit 4 use the envelope instead
+    volumes: [u8; 3],
+
+    envelope_period: u16,
+    envelope_timer: u16,
+    /// Bit 0 hold, bit 1 alternate, bit 2 attack, bit 3 continue
+    envelope_shape: u8,
+    /// 0-31
+    envelope_step: u8,
+    envelope_attack: bool,
+    envelope_holding: bool,
+
+    prescaler: u8,
+    /// Amplitude of each 5 bit level
+    levels: [f32; 32],
+}
+
+impl Default for Sunsoft5bAudio {
+    fn default() -> Self {
+        // 1.5 dB per level, scaled so volume 12 is about as loud as an APU
+        // pulse at full volume, which matches recordings
+        let volume_12 = 10f32.powf((25.0 - 31.0) * 1.5 / 20.0);
+        let mut levels = [0.0; 32];
+        for (level, amplitude) in levels.iter_mut().enumerate().skip(1) {
+            *amplitude =
+                10f32.powf((level as f32 - 31.0) * 1.5 / 20.0) / volume_12 * 15.0 * PULSE_STEP;
+        }
+
+        Self {
+            selected: 0,
+            tone_periods: [0; 3],
+            tone_timers: [0; 3],
+            tone_levels: [false; 3],
+            noise_period: 0,
+            noise_timer: 0,
+            noise_half: false,
+            noise: 1,
+            disable: 0,
+            volumes: [0; 3],
+            envelope_period: 0,
+            envelope_timer: 0,
+            envelope_shape: 0,
+            envelope_step: 0,
+            envelope_attack: false,
+            envelope_holding: true,
+            prescaler: 0,
+            levels,
+        }
+    }
+}
+
+impl Sunsoft5bAudio {
+    /// $C000-$DFFF: select a register.
+    pub fn write_select(&mut self, value: u8) {
+        self.selected = value & 0b1111;
+    }
+
+    /// $E000-$FFFF: write the selected register.
+    pub fn write_data(&mut self, value: u8) {
+        match self.selected {
+            0..=5 => {
+                let channel = self.selected as usize / 2;
+                let period = &mut self.tone_periods[channel];
+                if self.selected & 1 == 0 {
+                    *period = (*period & 0xF00) | value as u16;
+                } else {
+                    *period = (*period & 0xFF) | ((value as u16 & 0b1111) << 8);
+                }
+            }
+            6 => self.noise_period = value & 0b1_1111,
+            7 => self.disable = value,
+            8..=10 => self.volumes[self.selected as usize - 8] = value & 0b1_1111,
+            11 => self.envelope_period = (self.envelope_period & 0xFF00) | value as u16,
+            12 => self.envelope_period = (self.envelope_period & 0xFF) | (value as u16) << 8,
+            13 => {
+                self.envelope_shape = value & 0b1111;
+                self.envelope_attack = value & 0b0100 != 0;
+                self.envelope_step = 0;
+                self.envelope_holding = false;
+                self.envelope_timer = 0;
+            }
+            // the I/O ports
+            _ => {}
+        }
+    }
+
+    /// Run for one CPU cycle.
+    pub fn clock(&mut self) {
+        self.prescaler += 1;
+        if self.prescaler < PRESCALER {
+            return;
+        }
+        self.prescaler = 0;
+
+        self.clock_envelope();
+
+        for channel in 0..3 {
+            self.tone_timers[channel] += 1;
+            if self.tone_timers[channel] >= self.tone_periods[channel].max(1) {
+                self.tone_timers[channel] = 0;
+                self.tone_levels[channel] = !self.tone_levels[channel];
+            }
+        }
+
+        self.noise_half = !self.noise_half;
+        if self.noise_half {
+            self.noise_timer += 1;
+            if self.noise_timer >= self.noise_period.max(1) {
+                self.noise_timer = 0;
+                let feedback = (self.noise ^ (self.noise >> 3)) & 1;
+                self.noise = (self.noise >> 1) | (feedback << 16);
+            }
+        }
+    }
+
+    fn clock_envelope(&mut self) {
+        self.envelope_timer += 1;
+        if self.envelope_timer < self.envelope_period.max(1) {
+            return;
+        }
+        self.envelope_timer = 0;
+
+        if self.envelope_holding {
+            return;
+        }
+        if self.envelope_step < 31 {
+            self.envelope_step += 1;
+            return;
+        }
+
+        // the end of a ramp
+        let hold = self.envelope_shape & 0b0001 != 0;
+        let alternate = self.envelope_shape & 0b0010 != 0;
+        let continues = self.envelope_shape & 0b1000 != 0;
+        if !continues {
+            // drop to silence and stay there
+            self.envelope_attack = false;
+            self.envelope_holding = true;
+        } else if hold {
+            if alternate {
+                self.envelope_attack = !self.envelope_attack;
+            }
+            self.envelope_holding = true;
+        } else {
+            if alternate {
+                self.envelope_attack = !self.envelope_attack;
+            }
+            self.envelope_step = 0;
+        }
+    }
+
+    /// 0-31
+    fn envelope_level(&self) -> u8 {
+        if self.envelope_holding && self.envelope_shape & 0b1000 == 0 {
+            0
+        } else if self.envelope_attack {
+            self.envelope_step
+        } else {
+            31 - self.envelope_step
+        }
+    }
+
+    pub fn output(&self) -> f32 {
+        (0..3)
+            .map(|channel| {
+                let tone = self.tone_levels[channel] || self.disable & (1 << channel) != 0;
+                let noise = self.noise & 1 != 0 || self.disable & (8 << channel) != 0;
+                if !(tone && noise) {
+                    return 0.0;
+                }
+                let volume = self.volumes[channel];
+                let level = if volume & 0b1_0000 != 0 {
+                    self.envelope_level()
+                } else if volume == 0 {
+                    0
+                } else {
+                    // volumes are on every other envelope level
+                    volume * 2 + 1
+                };
+                self.levels[level as usize]
+            })
+            .sum()
+    }
+}
@@ -0,0 +1,139 @@
+//! The triangle wave channel, $4008-$400B.
+
+use crate::apu::length_counter::LengthCounter;
+
+/// The 32 step sequence the timer walks through.
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, //
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct Triangle {
+    /// Also the length counter halt flag
+    control: bool,
+    linear_counter_period: u8,
+    linear_counter: u8,
+    linear_counter_reload: bool,
+
+    /// 11 bit timer period, in CPU cycles
+    timer_period: u16,
+    timer: u16,
+    sequence_step: u8,
+
+    pub length: LengthCounter,
+}
+
+impl Triangle {
+    /// $4008: length counter halt and linear counter period.
+    pub fn write_control(&mut self, value: u8) {
+        self.control = value & 0b1000_0000 != 0;
+        self.length.set_halt(self.control);
+        self.linear_counter_period = value & 0b0111_1111;
+    }
+
+    /// $400A: low 8 bits of the timer.
+    pub fn write_timer_lo(&mut self, value: u8) {
+        self.timer_period = (self.timer_period & 0x700) | value as u16;
+    }
+
+    /// $400B: length counter load and high 3 bits of the timer.
+    pub fn write_timer_hi(&mut self, value: u8) {
+        self.timer_period = (self.timer_period & 0xFF) | ((value as u16 & 0b111) << 8);
+        self.length.load(value >> 3);
+        self.linear_counter_reload = true;
+    }
+
+    /// Clocked every CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period;
+        // the sequencer only moves while both counters are non-zero
+        if self.linear_counter > 0 && self.length.active() {
+            self.sequence_step = (self.sequence_step + 1) & 0x1F;
+        }
+    }
+
+    /// Clocked by the frame counter every quarter frame.
+    pub fn clock_quarter_frame(&mut self) {
+        if self.linear_counter_reload {
+            self.linear_counter = self.linear_counter_period;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_counter_reload = false;
+        }
+    }
+
+    /// Clocked by the frame counter every half frame.
+    pub fn clock_half_frame(&mut self) {
+        self.length.clock();
+    }
+
+    /// The current output level, 0-15.  Silencing the channel freezes the
+    /// sequencer rather than muting it, so this is never forced to 0.
+    pub fn output(&self) -> u8 {
+        SEQUENCE[self.sequence_step as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequencer_needs_both_counters() {
+        let mut triangle = Triangle::default();
+        triangle.length.set_enabled(true);
+        triangle.write_control(2);
+        triangle.write_timer_lo(0);
+        triangle.write_timer_hi(0b1000);
+        triangle.clock_timer();
+        assert_eq!(triangle.output(), 15, "the linear counter isn't loaded yet");
+
+        triangle.clock_quarter_frame();
+        triangle.clock_timer();
+        triangle.clock_timer();
+        assert_eq!(triangle.output(), 13);
+
+        triangle.clock_quarter_frame();
+        triangle.clock_quarter_frame();
+        triangle.clock_timer();
+        assert_eq!(triangle.output(), 13, "silencing freezes the output");
+    }
+
+    #[test]
+    fn control_flag_keeps_reloading() {
+        let mut triangle = Triangle::default();
+        triangle.length.set_enabled(true);
+        triangle.write_control(0b1000_0001);
+        triangle.write_timer_hi(0b1000);
+        for _ in 0..4 {
+            triangle.clock_quarter_frame();
+        }
+        triangle.clock_timer();
+        assert_eq!(triangle.output(), 14);
+    }
+
+    #[test]
+    fn sequence_wraps() {
+        let mut triangle = Triangle::default();
+        triangle.length.set_enabled(true);
+        triangle.write_control(0x7F);
+        triangle.write_timer_hi(0b1000);
+        triangle.clock_quarter_frame();
+        let levels: Vec<u8> = (0..33)
+            .map(|_| {
+                triangle.clock_timer();
+                triangle.output()
+            })
+            .collect();
+        assert_eq!(levels[..2], [14, 13]);
+        assert_eq!(levels[14..18], [0, 0, 1, 2]);
+        assert_eq!(levels[30..], [15, 15, 14]);
+    }
+}
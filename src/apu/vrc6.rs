@@ -0,0 +1,170 @@
+//! Konami's VRC6 sound: two pulse channels with 8 duty cycles and a sawtooth.
+
+use crate::apu::mixer::PULSE_STEP;
+
+#[derive(Debug, Clone, Default)]
+struct Vrc6Pulse {
+    /// Output the volume constantly, ignoring the duty
+    ignore_duty: bool,
+    /// High for steps 0 to `duty` of 16
+    duty: u8,
+    volume: u8,
+    enabled: bool,
+
+    /// 12 bit timer period in CPU cycles
+    period: u16,
+    timer: u16,
+    /// Counts down
+    step: u8,
+}
+
+impl Vrc6Pulse {
+    fn write(&mut self, reg: u16, value: u8) {
+        match reg {
+            0 => {
+                self.ignore_duty = value & 0b1000_0000 != 0;
+                self.duty = (value >> 4) & 0b111;
+                self.volume = value & 0b1111;
+            }
+            1 => self.period = (self.period & 0xF00) | value as u16,
+            _ => {
+                self.period = (self.period & 0xFF) | ((value as u16 & 0b1111) << 8);
+                self.enabled = value & 0b1000_0000 != 0;
+                if !self.enabled {
+                    self.step = 15;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step = self.step.wrapping_sub(1) & 0b1111;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// 0-15
+    fn output(&self) -> u8 {
+        if self.enabled && (self.ignore_duty || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Vrc6Saw {
+    /// Added to the accumulator every other step
+    rate: u8,
+    enabled: bool,
+
+    /// 12 bit timer period in CPU cycles
+    period: u16,
+    timer: u16,
+    /// 0-13, the accumulator is reset at the end
+    step: u8,
+    accumulator: u8,
+}
+
+impl Vrc6Saw {
+    fn write(&mut self, reg: u16, value: u8) {
+        match reg {
+            0 => self.rate = value & 0b0011_1111,
+            1 => self.period = (self.period & 0xF00) | value as u16,
+            _ => {
+                self.period = (self.period & 0xFF) | ((value as u16 & 0b1111) << 8);
+                self.enabled = value & 0b1000_0000 != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period >> shift;
+
+        self.step += 1;
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if self.step & 1 == 0 {
+            // rates above 42 overflow, which games use for distortion
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    /// 0-31
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Vrc6Audio {
+    pulse1: Vrc6Pulse,
+    pulse2: Vrc6Pulse,
+    saw: Vrc6Saw,
+
+    /// $9003: stop every timer
+    halt: bool,
+    /// $9003: speed the timers up by 16 or 256 times
+    shift: u8,
+}
+
+impl Vrc6Audio {
+    /// Write one of the registers at $9000-$9003, $A000-$A002 and
+    /// $B000-$B002.  `addr` should already have A0 and A1 in the right place
+    /// for boards that swap them.
+    pub fn write(&mut self, addr: u16, value: u8) {
+        let reg = addr & 0b11;
+        match addr & 0xF000 {
+            0x9000 if reg == 3 => {
+                self.halt = value & 0b001 != 0;
+                self.shift = if value & 0b100 != 0 {
+                    8
+                } else if value & 0b010 != 0 {
+                    4
+                } else {
+                    0
+                };
+            }
+            0x9000 => self.pulse1.write(reg, value),
+            0xA000 if reg != 3 => self.pulse2.write(reg, value),
+            0xB000 if reg != 3 => self.saw.write(reg, value),
+            _ => {}
+        }
+    }
+
+    /// Run for one CPU cycle.
+    pub fn clock(&mut self) {
+        if self.halt {
+            return;
+        }
+        self.pulse1.clock(self.shift);
+        self.pulse2.clock(self.shift);
+        self.saw.clock(self.shift);
+    }
+
+    /// The pulses are about as loud as the APU's at the same volume, and the
+    /// saw's 5 bits are on the same scale.
+    pub fn output(&self) -> f32 {
+        let level = self.pulse1.output() + self.pulse2.output() + self.saw.output();
+        level as f32 * PULSE_STEP
+    }
+}
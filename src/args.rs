@@ -1,30 +1,597 @@
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, ArgMatches, ErrorKind, SubCommand};
+use log::LevelFilter;
+use std::fmt::Display;
 use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::apu::mixer::Channel;
+#[cfg(feature = "sdl")]
+use crate::bindings::Bindings;
+use crate::input::device::InputDevices;
+use crate::logging::LogConfig;
+use crate::region::Region;
+
+const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+#[cfg(feature = "sdl")]
+const DEFAULT_SCALE: u32 = 3;
+
+#[derive(Debug)]
+pub enum Command {
+    /// Play a ROM, or render an NSF to WAV
+    Play,
+    /// Run a ROM headlessly for a number of frames
+    Run(RunSettings),
+    /// Print what the ROM's header says and its checksums
+    Info { json: bool },
+    /// Disassemble PRG ROM or CPU memory
+    Disasm(DisasmSettings),
+    /// Assemble a program into an NROM ROM
+    Asm { source: PathBuf },
+}
+
+#[derive(Debug)]
+pub struct RunSettings {
+    pub frames: u32,
+    /// Where to save the last frame, or the base name for every Nth frame
+    pub screenshot: Option<PathBuf>,
+    /// Save every Nth frame instead of just the last one
+    pub screenshot_every: Option<u32>,
+    /// Controller input to play
+    pub input_script: Option<PathBuf>,
+}
+
+impl RunSettings {
+    fn from_matches(matches: &ArgMatches) -> Self {
+        Self {
+            frames: parsed(matches, "frames", parse_number).unwrap_or_default(),
+            screenshot: matches.value_of("screenshot").map(PathBuf::from),
+            screenshot_every: parsed(matches, "screenshot-every", parse_positive),
+            input_script: matches.value_of("input").map(PathBuf::from),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DisasmSettings {
+    /// The PRG bank to disassemble, all of them if not set
+    pub bank: Option<usize>,
+    pub bank_size: usize,
+    /// The CPU address the bank is mapped at, guessed if not set
+    pub origin: Option<u16>,
+    /// Disassemble this CPU address range, inclusive, instead of PRG banks
+    pub range: Option<(u16, u16)>,
+    /// Frames to run before disassembling `range`
+    pub frames: u32,
+    /// Name the hardware registers and interrupt handlers
+    pub labels: bool,
+}
+
+impl DisasmSettings {
+    fn from_matches(matches: &ArgMatches) -> Self {
+        Self {
+            bank: parsed(matches, "bank", parse_number),
+            bank_size: parsed(matches, "bank-size", parse_number::<usize>)
+                .map_or(0x4000, |kib| kib * 1024),
+            origin: parsed(matches, "origin", parse_address),
+            range: parsed(matches, "range", parse_address_range),
+            frames: parsed(matches, "frames", parse_number).unwrap_or_default(),
+            labels: !matches.is_present("no-labels"),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Settings {
+    pub command: Command,
     pub rom_file: PathBuf,
+    /// A `.pal` file to use instead of the built-in palette
+    pub palette_file: Option<PathBuf>,
+    /// Overrides the region from the ROM header
+    pub region: Option<Region>,
+    /// What's plugged into the controller and expansion ports, overriding
+    /// the ROM header
+    pub input_devices: Option<InputDevices>,
+    /// APU channels to silence
+    pub muted_channels: Vec<Channel>,
+    /// The only APU channel to play
+    pub solo_channel: Option<Channel>,
+    /// Per channel volume scales, 1.0 is normal
+    pub channel_volumes: Vec<(Channel, f32)>,
+    /// Audio output rate in Hz
+    pub sample_rate: u32,
+    /// Where to record the audio to
+    pub wav_file: Option<PathBuf>,
+    /// Also record each APU channel to its own file
+    pub record_channels: bool,
+    /// The NSF track to play, numbered from 1
+    pub track: Option<u8>,
+    /// How long to play an NSF track for in milliseconds, including the fade
+    pub track_length: Option<u32>,
+    /// How long to fade out an NSF track for in milliseconds
+    pub track_fade: Option<u32>,
+    pub log: LogConfig,
+    /// How many times the screen size the window starts at
+    #[cfg(feature = "sdl")]
+    pub scale: u32,
+    /// Keyboard and gamepad bindings for the window
+    #[cfg(feature = "sdl")]
+    pub bindings: Bindings,
 }
 
 impl Settings {
     pub fn new() -> Self {
-        let matches = App::new("NEruSt")
+        let app = App::new("NEruSt")
             .version("0.0.1")
             .about("An emulator for the Famicom and NES")
-            .arg(
-                Arg::with_name("rom-file")
-                    .short("f")
-                    .value_name("FILE")
-                    .help("The path to a ROM file")
-                    .takes_value(true),
+            .setting(AppSettings::ArgsNegateSubcommands)
+            // the subcommands have their own ROM argument
+            .setting(AppSettings::SubcommandsNegateReqs)
+            .args(&shared_args())
+            .args(&logging_args());
+        #[cfg(feature = "sdl")]
+        let app = app.args(&window_args());
+        let matches = app
+            .subcommand(
+                SubCommand::with_name("run")
+                    .about("Run a ROM headlessly as fast as possible, for tests and CI")
+                    .args(&shared_args())
+                    .args(&logging_args())
+                    .arg(
+                        Arg::with_name("frames")
+                            .long("frames")
+                            .value_name("FRAMES")
+                            .help("How many frames to run for")
+                            .required(true)
+                            .validator(check(parse_number::<u32>))
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("screenshot")
+                            .long("screenshot")
+                            .value_name("PNG_FILE")
+                            .help("Save the last frame as a PNG")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("screenshot-every")
+                            .long("screenshot-every")
+                            .value_name("N")
+                            .help("Save every Nth frame instead, e.g. out-000060.png")
+                            .requires("screenshot")
+                            .validator(check(parse_positive))
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("input")
+                            .long("input")
+                            .value_name("SCRIPT")
+                            .help("Controller input to play, one `FRAME CONTROLLER BUTTONS...` change per line")
+                            .takes_value(true),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("info")
+                    .about("Print a ROM's header and checksums")
+                    .arg(rom_arg())
+                    .args(&logging_args())
+                    .arg(
+                        Arg::with_name("json")
+                            .long("json")
+                            .help("Print a JSON object instead"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("disasm")
+                    .about("Disassemble a ROM's PRG banks, or CPU memory after running it")
+                    .arg(rom_arg())
+                    .args(&logging_args())
+                    .arg(
+                        Arg::with_name("bank")
+                            .long("bank")
+                            .value_name("BANK")
+                            .help("Only disassemble this PRG bank, numbered from 0")
+                            .conflicts_with("range")
+                            .validator(check(parse_number::<usize>))
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("bank-size")
+                            .long("bank-size")
+                            .value_name("KIB")
+                            .help("The PRG bank size in KiB")
+                            .possible_values(&["8", "16", "32"])
+                            .default_value("16")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("origin")
+                            .long("origin")
+                            .value_name("ADDRESS")
+                            .help("The CPU address banks are mapped at, e.g. 8000, defaults to $8000 or the end of memory for the last bank")
+                            .conflicts_with("range")
+                            .validator(check(parse_address))
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("range")
+                            .long("range")
+                            .value_name("START-END")
+                            .help("Disassemble CPU memory instead, e.g. C000-C0FF")
+                            .validator(check(parse_address_range))
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("frames")
+                            .long("frames")
+                            .value_name("FRAMES")
+                            .help("How many frames to run before disassembling the range")
+                            .requires("range")
+                            .validator(check(parse_number::<u32>))
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("no-labels")
+                            .long("no-labels")
+                            .help("Show addresses instead of register and interrupt handler names"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("asm")
+                    .about("Assemble a 6502 program into an NROM ROM, for test programs")
+                    .arg(
+                        Arg::with_name("source")
+                            .value_name("SOURCE")
+                            .help("The assembly source")
+                            .required(true)
+                            .index(1),
+                    )
+                    .arg(
+                        Arg::with_name("rom-file")
+                            .short("f")
+                            .long("rom")
+                            .value_name("FILE")
+                            .help("Where to write the ROM")
+                            .required(true)
+                            .takes_value(true),
+                    )
+                    .args(&logging_args()),
             )
             .get_matches();
-        let rom_file_arg = matches
-            .value_of("rom-file")
-            .expect("Must pass in ROM file to use");
-
+        let (matches, command) = match matches.subcommand() {
+            ("disasm", Some(disasm)) => (
+                disasm,
+                Command::Disasm(DisasmSettings::from_matches(disasm)),
+            ),
+            ("run", Some(run)) => (run, Command::Run(RunSettings::from_matches(run))),
+            ("asm", Some(asm)) => (
+                asm,
+                Command::Asm {
+                    source: PathBuf::from(asm.value_of("source").unwrap_or_default()),
+                },
+            ),
+            ("info", Some(info)) => (
+                info,
+                Command::Info {
+                    json: info.is_present("json"),
+                },
+            ),
+            _ => (&matches, Command::Play),
+        };
+        // tracing every instruction would be most of a headless run's time
+        let default_log_level = match command {
+            Command::Play => LevelFilter::Info,
+            _ => LevelFilter::Warn,
+        };
         Self {
-            rom_file: PathBuf::from(rom_file_arg),
+            command,
+            // every command requires it
+            rom_file: PathBuf::from(matches.value_of("rom-file").unwrap_or_default()),
+            palette_file: matches.value_of("palette").map(PathBuf::from),
+            region: parsed(matches, "region", parse_auto).flatten(),
+            input_devices: parsed(matches, "input-device", parse_auto).flatten(),
+            muted_channels: parsed_values(matches, "mute", str::parse),
+            solo_channel: parsed(matches, "solo", str::parse),
+            channel_volumes: parsed_values(matches, "volume", parse_channel_volume),
+            // subcommands like `info` don't take audio options
+            sample_rate: parsed(matches, "sample-rate", parse_positive)
+                .unwrap_or(DEFAULT_SAMPLE_RATE),
+            wav_file: matches.value_of("record-wav").map(PathBuf::from),
+            record_channels: matches.is_present("record-channels"),
+            track: parsed(matches, "track", parse_number),
+            track_length: parsed(matches, "duration", parse_milliseconds),
+            track_fade: parsed(matches, "fade", parse_milliseconds),
+            #[cfg(feature = "sdl")]
+            scale: parsed(matches, "scale", parse_positive).unwrap_or(DEFAULT_SCALE),
+            #[cfg(feature = "sdl")]
+            bindings: parsed(matches, "bindings", |path| Bindings::load_from_file(path))
+                .unwrap_or_default(),
+            log: log_config(matches, default_log_level).unwrap_or_else(|e| {
+                clap::Error::with_description(&e, ErrorKind::InvalidValue).exit()
+            }),
         }
     }
 }
+
+/// The arguments accepted both with and without a subcommand.
+fn shared_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        rom_arg(),
+        Arg::with_name("palette")
+            .long("palette")
+            .value_name("PAL_FILE")
+            .help("A 192 or 1536 byte .pal file to use instead of the built-in palette")
+            .takes_value(true),
+        Arg::with_name("region")
+            .long("region")
+            .value_name("REGION")
+            .help("The console timing to use, defaults to the one in the ROM header")
+            .possible_values(&["auto", "ntsc", "pal", "dendy"])
+            .default_value("auto")
+            .takes_value(true),
+        Arg::with_name("input-device")
+            .long("input-device")
+            .value_name("DEVICE")
+            .help("What's plugged into the controller and expansion ports, defaults to the NES 2.0 header's expansion device or two controllers")
+            .possible_values(&[
+                "auto",
+                "controllers",
+                "four-score",
+                "hori",
+                "zapper",
+                "arkanoid",
+                "arkanoid-famicom",
+                "power-pad",
+                "family-trainer",
+                "family-keyboard",
+            ])
+            .default_value("auto")
+            .takes_value(true),
+        Arg::with_name("mute")
+            .long("mute")
+            .value_name("CHANNELS")
+            .help("Comma separated APU channels to silence: pulse1, pulse2, triangle, noise, dmc, expansion")
+            .validator(check(str::parse::<Channel>))
+            .takes_value(true)
+            .multiple(true)
+            .use_delimiter(true),
+        Arg::with_name("solo")
+            .long("solo")
+            .value_name("CHANNEL")
+            .help("Only play this APU channel")
+            .validator(check(str::parse::<Channel>))
+            .takes_value(true),
+        Arg::with_name("volume")
+            .long("volume")
+            .value_name("CHANNEL=VOLUME")
+            .help("Scale an APU channel's volume, e.g. triangle=0.5")
+            .validator(check(parse_channel_volume))
+            .takes_value(true)
+            .multiple(true)
+            .use_delimiter(true),
+        Arg::with_name("sample-rate")
+            .long("sample-rate")
+            .value_name("HZ")
+            .help("The audio sample rate")
+            .default_value("44100")
+            .validator(check(parse_positive))
+            .takes_value(true),
+        Arg::with_name("record-wav")
+            .long("record-wav")
+            .value_name("WAV_FILE")
+            .help("Record the audio to a 16 bit WAV file")
+            .takes_value(true),
+        Arg::with_name("record-channels")
+            .long("record-channels")
+            .help("Also record each APU channel next to the WAV file, e.g. out-pulse1.wav")
+            .requires("record-wav"),
+        Arg::with_name("track")
+            .long("track")
+            .value_name("TRACK")
+            .help("The NSF track to render, numbered from 1")
+            .validator(check(parse_number::<u8>))
+            .takes_value(true),
+        Arg::with_name("duration")
+            .long("duration")
+            .value_name("SECONDS")
+            .help("How long to render an NSF track for, including the fade")
+            .validator(check(parse_milliseconds))
+            .takes_value(true),
+        Arg::with_name("fade")
+            .long("fade")
+            .value_name("SECONDS")
+            .help("How long to fade out the end of an NSF track for")
+            .validator(check(parse_milliseconds))
+            .takes_value(true),
+    ]
+}
+
+/// The arguments that only mean something with a window to play in.
+#[cfg(feature = "sdl")]
+fn window_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("scale")
+            .long("scale")
+            .value_name("N")
+            .help("Start the window at N times the screen size, it keeps to whole multiples when resized")
+            .default_value("3")
+            .validator(check(parse_positive))
+            .takes_value(true),
+        Arg::with_name("bindings")
+            .long("bindings")
+            .value_name("INI_FILE")
+            .help("Keyboard and gamepad bindings for both players and the hotkeys")
+            .takes_value(true),
+    ]
+}
+
+/// The arguments every command takes for configuring logging.
+fn logging_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("log-level")
+            .long("log-level")
+            .value_name("LEVEL")
+            .help("The most verbose messages to log, defaults to info when playing and warn otherwise")
+            .possible_values(&["off", "error", "warn", "info", "debug", "trace"])
+            .takes_value(true),
+        Arg::with_name("log")
+            .long("log")
+            .value_name("FILTERS")
+            .help("Comma separated levels for parts of the emulator, e.g. cpu=trace,apu=off")
+            .takes_value(true),
+        Arg::with_name("log-file")
+            .long("log-file")
+            .value_name("FILE")
+            .help("Write the log to a file instead of stdout")
+            .takes_value(true),
+        Arg::with_name("log-prefix")
+            .long("log-prefix")
+            .value_name("PREFIX")
+            .help("Start each log line with the seconds since starting or the frame number")
+            .possible_values(&["none", "time", "frame"])
+            .default_value("none")
+            .takes_value(true),
+        Arg::with_name("log-rate-limit")
+            .long("log-rate-limit")
+            .value_name("N")
+            .help("How many times a second each warning is logged before repeats are dropped, 0 for no limit")
+            .default_value("5")
+            .validator(check(parse_number::<u32>))
+            .takes_value(true),
+    ]
+}
+
+fn log_config(matches: &ArgMatches, default_level: LevelFilter) -> Result<LogConfig, String> {
+    let mut config = LogConfig::new(default_level);
+    if let Some(level) = matches.value_of("log-level") {
+        config.level = level
+            .parse()
+            .map_err(|_| format!("Unknown log level {:?}", level))?;
+    }
+    if let Some(filters) = matches.value_of("log") {
+        config.add_filters(filters)?;
+    }
+    config.file = matches.value_of("log-file").map(PathBuf::from);
+    config.prefix = matches.value_of("log-prefix").unwrap_or("none").parse()?;
+    config.rate_limit = match matches.value_of("log-rate-limit") {
+        Some(limit) => limit
+            .parse()
+            .map_err(|e| format!("Invalid rate limit {:?}: {}", limit, e))?,
+        None => 0,
+    };
+    Ok(config)
+}
+
+fn rom_arg() -> Arg<'static, 'static> {
+    Arg::with_name("rom-file")
+        .short("f")
+        .long("rom")
+        .value_name("FILE")
+        .help("The path to a ROM file, or an NSF or NSFe to render to WAV")
+        .required(true)
+        .takes_value(true)
+}
+
+/// A clap validator that checks a value with `parse`.
+fn check<T>(parse: fn(&str) -> Result<T, String>) -> impl Fn(String) -> Result<(), String> {
+    move |s| parse(&s).map(|_| ())
+}
+
+/// Parse an argument's value, which its validator has already checked.
+/// Anything invalid is reported like clap reports any other bad argument.
+fn parsed<T>(matches: &ArgMatches, name: &str, parse: fn(&str) -> Result<T, String>) -> Option<T> {
+    matches
+        .value_of(name)
+        .map(|value| parse_or_exit(name, value, parse))
+}
+
+/// `parsed` for arguments that take several values.
+fn parsed_values<T>(
+    matches: &ArgMatches,
+    name: &str,
+    parse: fn(&str) -> Result<T, String>,
+) -> Vec<T> {
+    matches
+        .values_of(name)
+        .into_iter()
+        .flatten()
+        .map(|value| parse_or_exit(name, value, parse))
+        .collect()
+}
+
+fn parse_or_exit<T>(name: &str, value: &str, parse: fn(&str) -> Result<T, String>) -> T {
+    parse(value).unwrap_or_else(|e| {
+        clap::Error::with_description(
+            &format!("Invalid value for '--{}': {}", name, e),
+            ErrorKind::InvalidValue,
+        )
+        .exit()
+    })
+}
+
+fn parse_number<T: FromStr>(s: &str) -> Result<T, String>
+where
+    T::Err: Display,
+{
+    s.parse()
+        .map_err(|e| format!("Invalid number {:?}: {}", s, e))
+}
+
+/// `auto` for `None`, or the value.
+fn parse_auto<T: FromStr<Err = String>>(s: &str) -> Result<Option<T>, String> {
+    match s {
+        "auto" => Ok(None),
+        _ => s.parse().map(Some),
+    }
+}
+
+/// Parse a number that's at least 1.
+fn parse_positive(s: &str) -> Result<u32, String> {
+    match parse_number(s)? {
+        0 => Err("It must be at least 1".to_string()),
+        n => Ok(n),
+    }
+}
+
+/// Parse a `CHANNEL=VOLUME` pair.
+fn parse_channel_volume(s: &str) -> Result<(Channel, f32), String> {
+    let mut parts = s.splitn(2, '=');
+    let channel = parts.next().unwrap_or_default().parse()?;
+    let volume = parts
+        .next()
+        .ok_or_else(|| format!("Expected CHANNEL=VOLUME, got {:?}", s))?;
+    let volume = volume
+        .parse()
+        .map_err(|e| format!("Invalid volume {:?}: {}", volume, e))?;
+    Ok((channel, volume))
+}
+
+/// Parse a hex address like `C000` or `$C000`.
+fn parse_address(s: &str) -> Result<u16, String> {
+    let hex = s.trim_start_matches('$').trim_start_matches("0x");
+    u16::from_str_radix(hex, 16).map_err(|e| format!("Invalid address {:?}: {}", s, e))
+}
+
+/// Parse an inclusive `START-END` range of hex addresses.
+fn parse_address_range(s: &str) -> Result<(u16, u16), String> {
+    let mut parts = s.splitn(2, '-');
+    let start = parse_address(parts.next().unwrap_or_default())?;
+    let end = parts
+        .next()
+        .ok_or_else(|| format!("Expected START-END, got {:?}", s))?;
+    let end = parse_address(end)?;
+    if end < start {
+        return Err(format!("The range {:?} ends before it starts", s));
+    }
+    Ok((start, end))
+}
+
+/// Parse a number of seconds, like `90` or `2.5`, into milliseconds.
+fn parse_milliseconds(s: &str) -> Result<u32, String> {
+    let seconds: f64 = s
+        .parse()
+        .map_err(|e| format!("Invalid number of seconds {:?}: {}", s, e))?;
+    if !seconds.is_finite() || seconds < 0.0 {
+        return Err(format!("Expected a positive number of seconds, got {}", s));
+    }
+    Ok((seconds * 1000.0).round() as u32)
+}
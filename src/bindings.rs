@@ -0,0 +1,514 @@
+//! Which keys and gamepad inputs press which buttons, for the desktop
+//! frontend.  Bindings are read from an ini style file like
+//!
+//! ```ini
+//! # the Nth gamepad plugged in is player N's
+//! [player1]
+//! a = key:X, button:b
+//! left = key:Left, button:dpleft, axis:leftx-
+//!
+//! turbo-b = key:A
+//! turbo-rate = 3
+//!
+//! [hotkeys]
+//! save-state = key:F5
+//!
+//! # FILE = PLAYER, INPUTS
+//! [macros]
+//! jump.txt = player1, key:Q
+//! ```
+//!
+//! Keys use SDL's key names and gamepad buttons and axes use SDL's game
+//! controller names.  Anything the file leaves out keeps its default.
+//! Turbo buttons press and release every `turbo-rate` frames, for all of a
+//! player's buttons or with e.g. `turbo-rate-b` for one.  Macro files are
+//! in the `input_macro` format, relative to the bindings file.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::input_macro::InputMacro;
+use crate::nes::{Button, Controller};
+
+/// Things the frontend does rather than the console.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hotkey {
+    Pause,
+    Reset,
+    SaveState,
+    LoadState,
+    /// Run as fast as possible while held
+    FastForward,
+    Screenshot,
+    /// Start or stop recording every controller
+    RecordMacro,
+    /// Play the last recording
+    PlayMacro,
+}
+
+impl Hotkey {
+    pub const ALL: [Hotkey; 8] = [
+        Hotkey::Pause,
+        Hotkey::Reset,
+        Hotkey::SaveState,
+        Hotkey::LoadState,
+        Hotkey::FastForward,
+        Hotkey::Screenshot,
+        Hotkey::RecordMacro,
+        Hotkey::PlayMacro,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Hotkey::Pause => "pause",
+            Hotkey::Reset => "reset",
+            Hotkey::SaveState => "save-state",
+            Hotkey::LoadState => "load-state",
+            Hotkey::FastForward => "fast-forward",
+            Hotkey::Screenshot => "screenshot",
+            Hotkey::RecordMacro => "record-macro",
+            Hotkey::PlayMacro => "play-macro",
+        }
+    }
+}
+
+impl FromStr for Hotkey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Hotkey::ALL
+            .iter()
+            .copied()
+            .find(|hotkey| hotkey.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("Unknown hotkey {:?}", s))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Button(Controller, Button),
+    /// Hold a button with turbo
+    Turbo(Controller, Button),
+    Hotkey(Hotkey),
+    /// Play one of `Bindings::macros`
+    Macro(usize),
+}
+
+/// Something on a keyboard or gamepad.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    /// A key name like `X`, `Return` or `Left Shift`
+    Key(String),
+    /// A gamepad button name like `a`, `start` or `dpup`
+    PadButton(String),
+    /// A gamepad axis name like `leftx` or `triggerleft`, pushed towards
+    /// the positive or negative end
+    PadAxis { axis: String, positive: bool },
+}
+
+impl FromStr for Input {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, name) = s
+            .split_once(':')
+            .ok_or_else(|| format!("Expected key:, button: or axis:, got {:?}", s))?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(format!("Missing the name in {:?}", s));
+        }
+        match kind.trim().to_ascii_lowercase().as_str() {
+            "key" => Ok(Input::Key(name.to_string())),
+            "button" => Ok(Input::PadButton(name.to_ascii_lowercase())),
+            "axis" => {
+                let (axis, positive) = match name.as_bytes()[name.len() - 1] {
+                    b'+' => (&name[..name.len() - 1], true),
+                    b'-' => (&name[..name.len() - 1], false),
+                    _ => (name, true),
+                };
+                Ok(Input::PadAxis {
+                    axis: axis.to_ascii_lowercase(),
+                    positive,
+                })
+            }
+            _ => Err(format!("Expected key:, button: or axis:, got {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Input::Key(name) => write!(f, "key:{}", name),
+            Input::PadButton(name) => write!(f, "button:{}", name),
+            Input::PadAxis { axis, positive } => {
+                write!(f, "axis:{}{}", axis, if *positive { '+' } else { '-' })
+            }
+        }
+    }
+}
+
+/// `player1` to `player4`.
+fn parse_player(name: &str) -> Option<Controller> {
+    let number = name.strip_prefix("player")?.parse().ok()?;
+    Controller::from_number(number)
+}
+
+/// Which part of a bindings file a line is in.
+#[derive(Debug, Clone, Copy)]
+enum Section {
+    Player(Controller),
+    Hotkeys,
+    Macros,
+}
+
+#[derive(Debug, Clone)]
+pub struct Bindings {
+    /// Each action and the inputs that trigger it.  Gamepad inputs for a
+    /// player's buttons only count on that player's gamepad, for everything
+    /// else they count on any.
+    bindings: Vec<(Action, Vec<Input>)>,
+    /// Frames per press and release for turbo buttons, applied in order
+    turbo_rates: Vec<(Controller, Button, u8)>,
+    /// The macros `Action::Macro` plays, and the controller to play them on
+    macros: Vec<(Controller, InputMacro)>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self::parse(DEFAULT_BINDINGS).expect("parse default bindings")
+    }
+}
+
+/// Keyboard for player 1, and gamepads with NES B and A on the bottom and
+/// right face buttons, where a Super Famicom pad has them.
+const DEFAULT_BINDINGS: &str = "
+[player1]
+a = key:X, button:b
+b = key:Z, button:a
+select = key:Right Shift, button:back
+start = key:Return, button:start
+up = key:Up, button:dpup, axis:lefty-
+down = key:Down, button:dpdown, axis:lefty+
+left = key:Left, button:dpleft, axis:leftx-
+right = key:Right, button:dpright, axis:leftx+
+
+[player2]
+a = button:b
+b = button:a
+select = button:back
+start = button:start
+up = button:dpup, axis:lefty-
+down = button:dpdown, axis:lefty+
+left = button:dpleft, axis:leftx-
+right = button:dpright, axis:leftx+
+
+[player3]
+a = button:b
+b = button:a
+select = button:back
+start = button:start
+up = button:dpup, axis:lefty-
+down = button:dpdown, axis:lefty+
+left = button:dpleft, axis:leftx-
+right = button:dpright, axis:leftx+
+
+[player4]
+a = button:b
+b = button:a
+select = button:back
+start = button:start
+up = button:dpup, axis:lefty-
+down = button:dpdown, axis:lefty+
+left = button:dpleft, axis:leftx-
+right = button:dpright, axis:leftx+
+
+[hotkeys]
+pause = key:P
+reset = key:F1
+save-state = key:F5
+load-state = key:F7
+fast-forward = key:Tab
+screenshot = key:F12
+record-macro = key:F8
+play-macro = key:F9
+";
+
+impl Bindings {
+    /// The defaults with the bindings in `path` on top.
+    pub fn load_from_file(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text =
+            fs::read_to_string(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+        let mut bindings = Self::default();
+        // macro files are relative to the bindings
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        bindings
+            .apply(&text, dir)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(bindings)
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut bindings = Self {
+            bindings: Vec::new(),
+            turbo_rates: Vec::new(),
+            macros: Vec::new(),
+        };
+        bindings.apply(text, Path::new(""))?;
+        Ok(bindings)
+    }
+
+    /// Replace the inputs for each action `text` lists.
+    fn apply(&mut self, text: &str, dir: &Path) -> Result<(), String> {
+        let mut section = None;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |e: String| format!("line {}: {}", number + 1, e);
+
+            if line.starts_with('[') && line.ends_with(']') {
+                let name = line[1..line.len() - 1].trim().to_ascii_lowercase();
+                section = Some(match name.as_str() {
+                    "hotkeys" => Section::Hotkeys,
+                    "macros" => Section::Macros,
+                    _ => match parse_player(&name) {
+                        Some(controller) => Section::Player(controller),
+                        None => return Err(error(format!("Unknown section [{}]", name))),
+                    },
+                });
+                continue;
+            }
+
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| error(format!("Expected NAME = INPUTS, got {:?}", line)))?;
+            let name = name.trim();
+            let mut values = value
+                .split(',')
+                .map(str::trim)
+                .filter(|value| !value.is_empty());
+            let action = match section {
+                Some(Section::Player(controller)) => {
+                    if let Some(button) = name.strip_prefix("turbo-rate") {
+                        self.add_turbo_rate(controller, button, value)
+                            .map_err(error)?;
+                        continue;
+                    }
+                    match name.strip_prefix("turbo-") {
+                        Some(button) => Action::Turbo(controller, button.parse().map_err(error)?),
+                        None => Action::Button(controller, name.parse().map_err(error)?),
+                    }
+                }
+                Some(Section::Hotkeys) => Action::Hotkey(name.parse().map_err(error)?),
+                Some(Section::Macros) => {
+                    let controller = match values.next().and_then(parse_player) {
+                        Some(controller) => controller,
+                        None => {
+                            return Err(error(format!(
+                                "Expected FILE = player1 to player4, INPUTS, got {:?}",
+                                line
+                            )))
+                        }
+                    };
+                    let input_macro = InputMacro::load_from_file(dir.join(name)).map_err(error)?;
+                    self.macros.push((controller, input_macro));
+                    Action::Macro(self.macros.len() - 1)
+                }
+                None => return Err(error(
+                    "Bindings must be in a [player1] to [player4], [hotkeys] or [macros] section"
+                        .to_string(),
+                )),
+            };
+            let inputs = values
+                .map(Input::from_str)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(error)?;
+            self.set(action, inputs);
+        }
+        Ok(())
+    }
+
+    /// `turbo-rate = N` for every button or `turbo-rate-a = N` for one.
+    fn add_turbo_rate(
+        &mut self,
+        controller: Controller,
+        button: &str,
+        rate: &str,
+    ) -> Result<(), String> {
+        let rate = rate.trim();
+        let rate = match rate.parse() {
+            Ok(rate) if rate > 0 => rate,
+            _ => {
+                return Err(format!(
+                    "Expected a turbo rate of 1 or more frames, got {:?}",
+                    rate
+                ))
+            }
+        };
+        let buttons = match button.strip_prefix('-') {
+            Some(button) => vec![button.parse()?],
+            None if button.is_empty() => Button::ALL.to_vec(),
+            None => return Err(format!("Unknown setting \"turbo-rate{}\"", button)),
+        };
+        for button in buttons {
+            self.turbo_rates.push((controller, button, rate));
+        }
+        Ok(())
+    }
+
+    /// Replace the inputs for `action`, none unbinds it.
+    pub fn set(&mut self, action: Action, inputs: Vec<Input>) {
+        match self.bindings.iter_mut().find(|(bound, _)| *bound == action) {
+            Some((_, bound)) => *bound = inputs,
+            None => self.bindings.push((action, inputs)),
+        }
+    }
+
+    /// Every action and an input that triggers it.
+    pub fn iter(&self) -> impl Iterator<Item = (Action, &Input)> {
+        self.bindings
+            .iter()
+            .flat_map(|(action, inputs)| inputs.iter().map(move |input| (*action, input)))
+    }
+
+    /// Frames per press and release for turbo buttons, later ones replace
+    /// earlier ones.
+    pub fn turbo_rates(&self) -> &[(Controller, Button, u8)] {
+        &self.turbo_rates
+    }
+
+    /// The controller and macro for each `Action::Macro`.
+    pub fn macros(&self) -> &[(Controller, InputMacro)] {
+        &self.macros
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs(bindings: &Bindings, action: Action) -> Vec<String> {
+        bindings
+            .iter()
+            .filter(|&(bound, _)| bound == action)
+            .map(|(_, input)| input.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn parse_inputs() {
+        assert_eq!(
+            "key:Left Shift".parse(),
+            Ok(Input::Key("Left Shift".to_string()))
+        );
+        assert_eq!(
+            "Button:DPUp".parse(),
+            Ok(Input::PadButton("dpup".to_string()))
+        );
+        for &(text, axis, positive) in &[
+            ("axis:leftx-", "leftx", false),
+            ("axis:LeftY+", "lefty", true),
+            ("axis:triggerleft", "triggerleft", true),
+        ] {
+            let axis = axis.to_string();
+            assert_eq!(text.parse(), Ok(Input::PadAxis { axis, positive }));
+        }
+        assert!("X".parse::<Input>().is_err());
+        assert!("key:".parse::<Input>().is_err());
+        assert!("mouse:left".parse::<Input>().is_err());
+    }
+
+    #[test]
+    fn defaults() {
+        let bindings = Bindings::default();
+        let a = Action::Button(Controller::One, Button::A);
+        assert_eq!(inputs(&bindings, a), ["key:X", "button:b"]);
+        let up = Action::Button(Controller::Four, Button::Up);
+        assert_eq!(inputs(&bindings, up), ["button:dpup", "axis:lefty-"]);
+        for &hotkey in Hotkey::ALL.iter() {
+            assert_eq!(inputs(&bindings, Action::Hotkey(hotkey)).len(), 1);
+        }
+    }
+
+    #[test]
+    fn overrides() {
+        let mut bindings = Bindings::default();
+        bindings
+            .apply(
+                "
+                # comments and blank lines are ignored
+                [Player2]
+                a = key:K  # so are trailing comments
+                turbo-b = key:J, button:x
+                turbo-rate = 3
+                turbo-rate-b = 1
+
+                [hotkeys]
+                Fast-Forward =
+                ",
+                Path::new(""),
+            )
+            .unwrap();
+        let a = Action::Button(Controller::Two, Button::A);
+        assert_eq!(inputs(&bindings, a), ["key:K"]);
+        let turbo = Action::Turbo(Controller::Two, Button::B);
+        assert_eq!(inputs(&bindings, turbo), ["key:J", "button:x"]);
+        let fast_forward = Action::Hotkey(Hotkey::FastForward);
+        assert!(inputs(&bindings, fast_forward).is_empty());
+        // left alone
+        let b = Action::Button(Controller::Two, Button::B);
+        assert_eq!(inputs(&bindings, b), ["button:a"]);
+
+        let rates = bindings.turbo_rates();
+        assert_eq!(rates.len(), Button::ALL.len() + 1);
+        assert!(rates[..Button::ALL.len()]
+            .iter()
+            .all(|&(_, _, rate)| rate == 3));
+        assert_eq!(rates.last(), Some(&(Controller::Two, Button::B, 1)));
+    }
+
+    #[test]
+    fn errors() {
+        for &text in &[
+            "a = key:X",
+            "[player5]",
+            "[player1]\njump = key:X",
+            "[player1]\na key:X",
+            "[player1]\na = X",
+            "[player1]\nturbo-rate = 0",
+            "[player1]\nturbo-rate-jump = 2",
+            "[player1]\nturbo-rated = 2",
+            "[hotkeys]\nquit = key:Q",
+            "[macros]\njump.txt = key:Q",
+        ] {
+            assert!(Bindings::parse(text).is_err(), "{:?}", text);
+        }
+        let error = Bindings::parse("[player1]\n\njump = key:X").unwrap_err();
+        assert!(error.starts_with("line 3: "), "{}", error);
+    }
+
+    #[test]
+    fn macros() {
+        let dir = std::env::temp_dir().join(format!("nerust-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("jump.txt"), "a *2\n-\n").unwrap();
+        let path = dir.join("bindings.ini");
+        fs::write(&path, "[macros]\njump.txt = player2, key:Q\n").unwrap();
+        let bindings = Bindings::load_from_file(&path);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let bindings = bindings.unwrap();
+        let a = Button::A.bit();
+        assert_eq!(
+            bindings.macros(),
+            [(Controller::Two, InputMacro::new(vec![a, a, 0]))]
+        );
+        assert_eq!(inputs(&bindings, Action::Macro(0)), ["key:Q"]);
+    }
+}
@@ -1,36 +1,53 @@
-/*
-    THIS CARTRIDGE ONLY WORK FOR SMB
-*/
-
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
 use std::path::Path;
 
 use crate::header::*;
+use crate::mapper::fme7::Fme7;
+use crate::mapper::namco163::Namco163;
+use crate::mapper::nrom::Nrom;
+use crate::mapper::nsf::NsfBoard;
+use crate::mapper::vrc6::Vrc6;
+use crate::mapper::Mapper;
+use crate::nsf::Nsf;
+use crate::region::Region;
 
 pub struct Cartridge {
     pub header: INESHeader,
-    pub prg_rom: Box<[u8]>,
-    /// Battery-backed save/work RAM
-    pub prg_ram: Box<[u8]>,
-    pub chr_rom: Box<[u8]>,
+    pub mapper: Box<dyn Mapper>,
+}
+
+impl Clone for Cartridge {
+    fn clone(&self) -> Self {
+        Self {
+            header: self.header.clone(),
+            mapper: self.mapper.box_clone(),
+        }
+    }
 }
 
 impl Cartridge {
-    pub fn load_from_file(rom_name: &Path) -> Self {
-        let file = File::open(rom_name).unwrap();
-        let mut buf_reader = BufReader::new(file);
+    /// Whether `load_from_bytes` has a mapper for an iNES mapper number.
+    pub fn supports_mapper(id: u16) -> bool {
+        matches!(id, 0 | 19 | 24 | 26 | 69)
+    }
+
+    pub fn load_from_file(rom_name: &Path) -> Result<Self, String> {
+        let file =
+            File::open(rom_name).map_err(|e| format!("Failed to open {:?}: {}", rom_name, e))?;
+        let buf_reader = BufReader::new(file);
 
         Self::load_from_bytes(buf_reader)
+            .map_err(|e| format!("Failed to load {:?}: {}", rom_name, e))
     }
 
-    pub fn load_from_bytes<R: Read>(mut buf_reader: BufReader<R>) -> Self {
+    pub fn load_from_bytes<R: Read>(mut buf_reader: BufReader<R>) -> Result<Self, String> {
         // read the first 16 bytes
         let mut header_bytes = [0u8; 16];
         buf_reader
             .read_exact(&mut header_bytes)
-            .expect("read header");
+            .map_err(|e| format!("Failed to read the header: {}", e))?;
 
         // create header
         let header = INESHeader::from(header_bytes);
@@ -42,53 +59,90 @@ impl Cartridge {
         );
         let prg_ram = vec![0; header.get_prg_ram_size()].into_boxed_slice();
 
-        // load the mapper
-        let _mapper = match header.get_mapper_id() {
-            0x00 => {}
-            _ => panic!("Mapper for this cart has not been implemented!"),
-        };
-
         // extract trainer if it exists (Do nothing with it for now)
         if header.contains_trainer() {
             // what even is a trainer??
             let mut trainer_bytes = [0u8; 512];
             buf_reader
                 .read_exact(&mut trainer_bytes)
-                .expect("read trainer");
+                .map_err(|e| format!("Failed to read the trainer: {}", e))?;
         }
 
         // extract PRG rom
         let mut prg_rom_bytes = vec![0u8; header.get_prg_rom_size()].into_boxed_slice();
         buf_reader
             .read_exact(&mut prg_rom_bytes)
-            .expect("read prg rom");
+            .map_err(|e| format!("Failed to read the PRG ROM: {}", e))?;
         let prg_rom = prg_rom_bytes;
+        // every mapper has at least one 8 KiB bank, fixed at the end
+        if prg_rom.len() < 0x2000 {
+            return Err(format!(
+                "The PRG ROM is {} bytes, it must be at least 8 KiB",
+                prg_rom.len()
+            ));
+        }
 
-        // extract chr rom
-        let mut chr_rom_bytes = vec![0u8; header.get_chr_rom_size()].into_boxed_slice();
+        // extract chr rom, the mapper uses CHR RAM instead if there isn't any
+        let mut chr_rom = vec![0u8; header.get_chr_rom_size()].into_boxed_slice();
         buf_reader
-            .read_exact(&mut chr_rom_bytes)
-            .expect("chr rom bytes");
-        let chr_rom = chr_rom_bytes;
+            .read_exact(&mut chr_rom)
+            .map_err(|e| format!("Failed to read the CHR ROM: {}", e))?;
 
-        // more later
+        // load the mapper
+        let mapper: Box<dyn Mapper> = match header.get_mapper_id() {
+            0 => Box::new(Nrom::new(prg_rom, prg_ram, chr_rom, header.get_mirroring())),
+            19 => Box::new(Namco163::new(prg_rom, prg_ram, chr_rom)),
+            24 => Box::new(Vrc6::new(prg_rom, prg_ram, chr_rom, false)),
+            26 => Box::new(Vrc6::new(prg_rom, prg_ram, chr_rom, true)),
+            69 => Box::new(Fme7::new(prg_rom, prg_ram, chr_rom)),
+            id => return Err(format!("Mapper {} isn't supported", id)),
+        };
 
         // create the cart
-        Self {
-            header,
-            prg_rom,
-            prg_ram,
-            chr_rom,
-        }
+        Ok(Self { header, mapper })
+    }
+
+    /// A cartridge holding an NSF's music code and data.
+    pub fn from_nsf(nsf: &Nsf) -> Result<Self, String> {
+        let mut header_bytes = [0u8; 16];
+        header_bytes[..4].copy_from_slice(b"NES\x1A");
+        header_bytes[9] = (nsf.region() != Region::Ntsc) as u8;
+
+        Ok(Self {
+            header: INESHeader::from(header_bytes),
+            mapper: Box::new(NsfBoard::new(nsf)?),
+        })
+    }
+
+    pub fn mirroring(&self) -> Mirroring {
+        self.mapper.mirroring()
+    }
+
+    /// Run the cartridge's hardware for one CPU cycle.
+    pub fn clock(&mut self) {
+        self.mapper.clock();
+    }
+
+    pub fn irq(&self) -> bool {
+        self.mapper.irq()
+    }
+
+    pub fn audio_output(&self) -> f32 {
+        self.mapper.audio_output()
+    }
+
+    /// Read $4020-$FFFF without side effects, see `Mapper::cpu_peek`.
+    pub fn cpu_peek(&self, addr: u16) -> u8 {
+        self.mapper.cpu_peek(addr)
     }
 
     /// Get the CPU's view of the cartridge.
-    pub fn cpu_view(&mut self) -> CartridgeCpuView {
+    pub fn cpu_view(&mut self) -> CartridgeCpuView<'_> {
         CartridgeCpuView { cart: self }
     }
 
     /// Get the PPU's view of the cartridge.
-    pub fn ppu_view(&mut self) -> CartridgePpuView {
+    pub fn ppu_view(&mut self) -> CartridgePpuView<'_> {
         CartridgePpuView { cart: self }
     }
 }
@@ -100,33 +154,11 @@ pub struct CartridgeCpuView<'a> {
 
 impl<'a> CartridgeCpuView<'a> {
     pub fn get(&mut self, addr: u16) -> u8 {
-        let rom_wrap = (self.cart.header.get_prg_rom_size() as usize)
-            .checked_sub(1)
-            .unwrap_or(0);
-        let ram_wrap = (self.cart.header.get_prg_ram_size() as usize);
-        match addr {
-            0x4020..=0x5FFF => unreachable!("cart CPU view 0x4020..=0x5FFF"),
-            0x6000..=0x7FFF => self.cart.prg_ram[(addr - 0x6000) as usize & ram_wrap],
-            0x8000..=0xFFFF => self.cart.prg_rom[(addr - 0x8000) as usize & rom_wrap],
-            e => panic!("Invalid address lookup in Cartridge for CPU: {:x}", e),
-        }
+        self.cart.mapper.cpu_read(addr)
     }
 
     pub fn set(&mut self, addr: u16, val: u8) {
-        match addr {
-            0x6000..=0x7FFF => {
-                let ram_wrap = (self.cart.header.get_prg_ram_size() as usize);
-                self.cart.prg_ram[(addr - 0x6000) as usize & ram_wrap] = val;
-            }
-            _ => {
-                error!(
-                    "Attempting to write to cartridge 0x{:X} with value {}",
-                    addr, val
-                );
-
-                unreachable!()
-            }
-        }
+        self.cart.mapper.cpu_write(addr, val);
     }
 }
 
@@ -138,16 +170,41 @@ pub struct CartridgePpuView<'a> {
 impl<'a> CartridgePpuView<'a> {
     pub fn get(&mut self, addr: u16) -> u8 {
         match addr {
-            0x0000..=0x1FFF => self.cart.chr_rom[addr as usize],
+            0x0000..=0x1FFF => self.cart.mapper.ppu_read(addr),
             e => unreachable!("Invalid address lookup in Cartridge for PPU: {:x}", e),
         }
     }
 
-    pub fn set(&mut self, _addr: u16, _val: u8) {
-        error!(
-            "Attempting to write to cartridge 0x{:X} with value {}",
-            _addr, _val
-        );
-        unreachable!()
+    pub fn set(&mut self, addr: u16, val: u8) {
+        self.cart.mapper.ppu_write(addr & 0x1FFF, val);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An iNES image with `prg_banks` 16 KiB PRG banks, no CHR ROM and the
+    /// low nibble of the mapper number.
+    fn image(prg_banks: u8, mapper: u8) -> Vec<u8> {
+        let mut image = b"NES\x1A".to_vec();
+        image.extend_from_slice(&[prg_banks, 0, mapper << 4]);
+        image.resize(16 + prg_banks as usize * 0x4000, 0);
+        image
+    }
+
+    #[test]
+    fn unsupported_mapper() {
+        let result = Cartridge::load_from_bytes(BufReader::new(&image(1, 4)[..]));
+        assert_eq!(result.err().unwrap(), "Mapper 4 isn't supported");
+    }
+
+    #[test]
+    fn too_little_prg_rom() {
+        assert!(Cartridge::load_from_bytes(BufReader::new(&image(0, 0)[..])).is_err());
+        // a truncated file
+        let image = image(1, 0);
+        assert!(Cartridge::load_from_bytes(BufReader::new(&image[..0x1000])).is_err());
+        assert!(Cartridge::load_from_bytes(BufReader::new(&image[..])).is_ok());
     }
 }
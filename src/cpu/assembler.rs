@@ -0,0 +1,771 @@
+//! A small 6502 assembler, so test programs can be written as source
+//! instead of hand assembled hex.  The `asm` command builds them into ROMs.
+//!
+//! ```text
+//! PPUCTRL = $2000
+//!         .org $8000
+//! reset:  ldx #0
+//! loop:   lda message,x       ; labels can be used before they're defined
+//!         beq done
+//!         sta $0200,x
+//!         inx
+//!         bne loop
+//! done:   jmp done
+//! message:
+//!         .byte "HI", 0
+//!         .word reset, >PPUCTRL + 1
+//! ```
+//!
+//! Mnemonics and registers are case insensitive, labels aren't.  Numbers
+//! can be decimal, `$hex`, `%binary` or `'c'` characters, and expressions
+//! support `+ - * / % & | ^ << >>`, parentheses, unary `-` and `~`, `<` and
+//! `>` for the low and high bytes, and `*` for the current address.
+//! Operands that fit in a byte use zero page addressing unless they refer
+//! to a label that isn't defined yet.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::cpu::opcodes::{AddressingMode, Opcode, OPCODES};
+
+/// Where code goes if there's no `.org`.
+const DEFAULT_ORIGIN: u16 = 0x8000;
+
+/// Interrupt vectors, with the labels used for them when the program
+/// doesn't write them itself.
+const VECTORS: [(u16, &str); 3] = [(0xFFFA, "nmi"), (0xFFFC, "reset"), (0xFFFE, "irq")];
+
+/// The output of `assemble`.
+#[derive(Debug, Clone, Default)]
+pub struct Assembly {
+    /// Every byte written, by address
+    pub bytes: BTreeMap<u16, u8>,
+    pub labels: HashMap<String, u16>,
+}
+
+impl Assembly {
+    pub fn label(&self, name: &str) -> Option<u16> {
+        self.labels.get(name).copied()
+    }
+
+    /// The lowest address written.
+    pub fn start(&self) -> Option<u16> {
+        self.bytes.keys().next().copied()
+    }
+
+    /// Build an NROM iNES image for `Cartridge::load_from_bytes`, with 8 KiB
+    /// of CHR RAM.  The program must fit in $8000-$FFFF, and is 16 KiB if it
+    /// fits in $C000-$FFFF.  Vectors the program doesn't set point to the
+    /// `nmi`, `reset` and `irq` labels, or the start of the program.
+    pub fn to_nrom(&self) -> Result<Vec<u8>, String> {
+        let start = self.start().ok_or("The program is empty")?;
+        if start < 0x8000 {
+            return Err(format!(
+                "NROM PRG ROM is at $8000-$FFFF, but the program starts at ${:04X}",
+                start
+            ));
+        }
+        let base: u16 = if start >= 0xC000 { 0xC000 } else { 0x8000 };
+        let mut prg = vec![0; 0x10000 - base as usize];
+        for (&addr, &value) in &self.bytes {
+            prg[(addr - base) as usize] = value;
+        }
+        for &(addr, name) in VECTORS.iter() {
+            if self.bytes.contains_key(&addr) || self.bytes.contains_key(&(addr + 1)) {
+                continue;
+            }
+            let target = self.label(name).unwrap_or(start);
+            let offset = (addr - base) as usize;
+            prg[offset..offset + 2].copy_from_slice(&target.to_le_bytes());
+        }
+
+        let mut image = b"NES\x1A".to_vec();
+        // PRG banks, no CHR ROM, then flags that are all 0 for NROM
+        image.push((prg.len() / 0x4000) as u8);
+        image.extend_from_slice(&[0; 11]);
+        image.extend_from_slice(&prg);
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+impl Assembly {
+    /// Load the program into a console as an NROM cartridge, powered on but
+    /// with nothing run yet.  For tests of the program's behaviour.
+    pub fn boot(&self) -> crate::nes::Nes {
+        let rom = self.to_nrom().unwrap();
+        let cart = crate::cartridge::Cartridge::load_from_bytes(std::io::BufReader::new(&rom[..]));
+        crate::nes::Nes::new(cart.unwrap())
+    }
+}
+
+/// Assemble a program, see the module docs for the syntax.  Errors start
+/// with the line number.
+pub fn assemble(source: &str) -> Result<Assembly, String> {
+    let mut statements = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        let parsed = parse_line(line).map_err(|e| format!("Line {}: {}", number + 1, e))?;
+        statements.extend(parsed.into_iter().map(|statement| (number + 1, statement)));
+    }
+
+    // the first pass finds the labels, and decides how big each instruction
+    // is so they don't move in the second pass
+    let mut assembler = Assembler::default();
+    let mut modes = Vec::with_capacity(statements.len());
+    for (number, statement) in &statements {
+        let mode = assembler
+            .run(statement, None, false)
+            .map_err(|e| format!("Line {}: {}", number, e))?;
+        modes.push(mode);
+    }
+
+    let labels = assembler.labels;
+    let mut assembler = Assembler {
+        labels,
+        ..Assembler::default()
+    };
+    for ((number, statement), mode) in statements.iter().zip(modes) {
+        assembler
+            .run(statement, mode, true)
+            .map_err(|e| format!("Line {}: {}", number, e))?;
+    }
+
+    Ok(Assembly {
+        bytes: assembler.bytes,
+        labels: assembler.labels,
+    })
+}
+
+#[derive(Debug, Clone)]
+enum Statement {
+    Label(String),
+    Constant(String, String),
+    Org(String),
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+    Instruction(String, Operand),
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    None,
+    Accumulator,
+    Immediate(String),
+    /// `(expr)`, which is also just an expression for anything but `JMP`
+    Indirect(String),
+    IndirectX(String),
+    IndirectY(String),
+    Direct(String),
+    DirectX(String),
+    DirectY(String),
+}
+
+struct Assembler {
+    pc: u32,
+    labels: HashMap<String, u16>,
+    bytes: BTreeMap<u16, u8>,
+}
+
+impl Default for Assembler {
+    fn default() -> Self {
+        Self {
+            pc: DEFAULT_ORIGIN as u32,
+            labels: HashMap::new(),
+            bytes: BTreeMap::new(),
+        }
+    }
+}
+
+impl Assembler {
+    /// Assemble one statement.  In the first pass expressions can refer to
+    /// labels that aren't defined yet, and the addressing mode chosen for
+    /// instructions is returned so the second pass can use it.
+    fn run(
+        &mut self,
+        statement: &Statement,
+        mode: Option<AddressingMode>,
+        last_pass: bool,
+    ) -> Result<Option<AddressingMode>, String> {
+        match statement {
+            Statement::Label(name) => {
+                if !last_pass && self.labels.contains_key(name) {
+                    return Err(format!("{} is already defined", name));
+                }
+                self.labels.insert(name.clone(), self.pc as u16);
+            }
+            Statement::Constant(name, expr) => {
+                if !last_pass && self.labels.contains_key(name) {
+                    return Err(format!("{} is already defined", name));
+                }
+                if let Some(value) = self.eval(expr, last_pass)? {
+                    self.labels.insert(name.clone(), value as u16);
+                }
+            }
+            Statement::Org(expr) => {
+                // the origin can't depend on labels that come later
+                let origin = self.eval(expr, true)?.unwrap_or_default();
+                self.pc = check_range(origin, 0, 0xFFFF)? as u32;
+            }
+            Statement::Bytes(items) => {
+                for item in items {
+                    if let Some(text) = string_literal(item) {
+                        for byte in text.bytes() {
+                            self.emit(byte)?;
+                        }
+                    } else {
+                        let value = self.eval(item, last_pass)?.unwrap_or_default();
+                        self.emit(check_range(value, -128, 0xFF)? as u8)?;
+                    }
+                }
+            }
+            Statement::Words(items) => {
+                for item in items {
+                    let value = self.eval(item, last_pass)?.unwrap_or_default();
+                    let value = check_range(value, -0x8000, 0xFFFF)? as u16;
+                    for byte in value.to_le_bytes().iter() {
+                        self.emit(*byte)?;
+                    }
+                }
+            }
+            Statement::Instruction(mnemonic, operand) => {
+                let mode = match mode {
+                    Some(mode) => mode,
+                    None => self.choose_mode(mnemonic, operand)?,
+                };
+                self.instruction(mnemonic, operand, mode, last_pass)?;
+                return Ok(Some(mode));
+            }
+        }
+        Ok(None)
+    }
+
+    fn emit(&mut self, value: u8) -> Result<(), String> {
+        if self.pc > 0xFFFF {
+            return Err("The program runs past $FFFF".to_string());
+        }
+        self.bytes.insert(self.pc as u16, value);
+        self.pc += 1;
+        Ok(())
+    }
+
+    /// Evaluate an expression, `None` if it uses a label that isn't defined
+    /// yet and this isn't the last pass.
+    fn eval(&self, expr: &str, last_pass: bool) -> Result<Option<i64>, String> {
+        let tokens = tokenize(expr)?;
+        let mut parser = ExprParser {
+            tokens: &tokens,
+            position: 0,
+            labels: &self.labels,
+            pc: self.pc as i64,
+            last_pass,
+        };
+        let value = parser.expression(0)?;
+        if parser.position < tokens.len() {
+            return Err(format!(
+                "Unexpected {:?} in {:?}",
+                tokens[parser.position], expr
+            ));
+        }
+        Ok(value)
+    }
+
+    fn choose_mode(&self, mnemonic: &str, operand: &Operand) -> Result<AddressingMode, String> {
+        use self::AddressingMode::*;
+        let has = |mode| Opcode::exists(mnemonic, mode);
+        // zero page if the value is known now and fits
+        let zero_page = |expr: &str| -> Result<bool, String> {
+            Ok(matches!(self.eval(expr, false)?, Some(value) if (0..=0xFF).contains(&value)))
+        };
+        let mode = match operand {
+            Operand::None if has(Implied) => Implied,
+            Operand::None => Accumulator,
+            Operand::Accumulator => Accumulator,
+            Operand::Immediate(_) => Immediate,
+            Operand::Indirect(_) if has(Indirect) => Indirect,
+            Operand::IndirectX(_) => IndirectX,
+            Operand::IndirectY(_) => IndirectY,
+            Operand::Indirect(expr) | Operand::Direct(expr) => {
+                if has(Relative) {
+                    Relative
+                } else if has(ZeroPage) && (!has(Absolute) || zero_page(expr)?) {
+                    ZeroPage
+                } else {
+                    Absolute
+                }
+            }
+            Operand::DirectX(expr) => {
+                if has(ZeroPageX) && (!has(AbsoluteX) || zero_page(expr)?) {
+                    ZeroPageX
+                } else {
+                    AbsoluteX
+                }
+            }
+            Operand::DirectY(expr) => {
+                if has(ZeroPageY) && (!has(AbsoluteY) || zero_page(expr)?) {
+                    ZeroPageY
+                } else {
+                    AbsoluteY
+                }
+            }
+        };
+        if has(mode) {
+            Ok(mode)
+        } else {
+            Err(format!("{} can't use {:?} addressing", mnemonic, mode))
+        }
+    }
+
+    fn instruction(
+        &mut self,
+        mnemonic: &str,
+        operand: &Operand,
+        mode: AddressingMode,
+        last_pass: bool,
+    ) -> Result<(), String> {
+        let opcode = Opcode::find(mnemonic, mode)
+            .ok_or_else(|| format!("{} can't use {:?} addressing", mnemonic, mode))?;
+        let value = match operand {
+            Operand::None | Operand::Accumulator => 0,
+            Operand::Immediate(expr)
+            | Operand::Indirect(expr)
+            | Operand::IndirectX(expr)
+            | Operand::IndirectY(expr)
+            | Operand::Direct(expr)
+            | Operand::DirectX(expr)
+            | Operand::DirectY(expr) => self.eval(expr, last_pass)?.unwrap_or_default(),
+        };
+
+        let pc = self.pc as i64;
+        self.emit(opcode)?;
+        match mode.operand_len() {
+            0 => {}
+            1 if mode == AddressingMode::Relative => {
+                let offset = if last_pass { value - (pc + 2) } else { 0 };
+                if !(-128..=127).contains(&offset) {
+                    return Err(format!("The branch is {} bytes, too far", offset));
+                }
+                self.emit(offset as u8)?;
+            }
+            1 => {
+                let min = if mode == AddressingMode::Immediate {
+                    -128
+                } else {
+                    0
+                };
+                self.emit(check_range(value, min, 0xFF)? as u8)?;
+            }
+            _ => {
+                let value = check_range(value, 0, 0xFFFF)? as u16;
+                for byte in value.to_le_bytes().iter() {
+                    self.emit(*byte)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_range(value: i64, min: i64, max: i64) -> Result<i64, String> {
+    if (min..=max).contains(&value) {
+        Ok(value)
+    } else {
+        Err(format!("{} doesn't fit in {}..={}", value, min, max))
+    }
+}
+
+/// A line is any of `label:`, then an instruction, directive or
+/// `NAME = expr`, then a `;` comment.
+fn parse_line(line: &str) -> Result<Vec<Statement>, String> {
+    let mut line = strip_comment(line).trim();
+    let mut statements = Vec::new();
+
+    if let Some(colon) = line.find(':') {
+        let name = line[..colon].trim();
+        if is_identifier(name) {
+            statements.push(Statement::Label(name.to_string()));
+            line = line[colon + 1..].trim();
+        }
+    }
+    if line.is_empty() {
+        return Ok(statements);
+    }
+
+    if let Some(equals) = line.find('=') {
+        let name = line[..equals].trim();
+        if is_identifier(name) {
+            let expr = line[equals + 1..].trim();
+            statements.push(Statement::Constant(name.to_string(), expr.to_string()));
+            return Ok(statements);
+        }
+    }
+
+    let (word, rest) = match line.find(char::is_whitespace) {
+        Some(space) => (&line[..space], line[space..].trim()),
+        None => (line, ""),
+    };
+    let statement = match word.to_ascii_lowercase().as_str() {
+        ".org" => Statement::Org(rest.to_string()),
+        ".byte" => Statement::Bytes(split_list(rest)?),
+        ".word" => Statement::Words(split_list(rest)?),
+        directive if directive.starts_with('.') => {
+            return Err(format!("Unknown directive {}", word));
+        }
+        _ => {
+            let known = OPCODES
+                .iter()
+                .any(|opcode| opcode.mnemonic.eq_ignore_ascii_case(word));
+            if !known {
+                return Err(format!("Unknown instruction {}", word));
+            }
+            Statement::Instruction(word.to_ascii_uppercase(), parse_operand(rest)?)
+        }
+    };
+    statements.push(statement);
+    Ok(statements)
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, ';') => return &line[..i],
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            _ => {}
+        }
+    }
+    line
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Split comma separated items, keeping commas in strings.
+fn split_list(s: &str) -> Result<Vec<String>, String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+    for c in s.chars() {
+        match (quote, c) {
+            (None, ',') => {
+                items.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            _ => {}
+        }
+        current.push(c);
+    }
+    if quote.is_some() {
+        return Err(format!("Unterminated string in {:?}", s));
+    }
+    items.push(current.trim().to_string());
+    if items.iter().any(|item| item.is_empty()) {
+        return Err(format!("Empty item in {:?}", s));
+    }
+    Ok(items)
+}
+
+fn string_literal(s: &str) -> Option<&str> {
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        Some(&s[1..s.len() - 1])
+    } else {
+        None
+    }
+}
+
+fn parse_operand(s: &str) -> Result<Operand, String> {
+    let s = s.trim();
+    let operand = if s.is_empty() {
+        Operand::None
+    } else if s.eq_ignore_ascii_case("A") {
+        Operand::Accumulator
+    } else if let Some(expr) = s.strip_prefix('#') {
+        Operand::Immediate(expr.trim().to_string())
+    } else if let Some((base, index)) = split_index(s) {
+        let indirect = base.starts_with('(') && matching_paren(base) == Some(base.len() - 1);
+        match index {
+            'Y' if indirect => Operand::IndirectY(base[1..base.len() - 1].trim().to_string()),
+            'X' => Operand::DirectX(base.to_string()),
+            _ => Operand::DirectY(base.to_string()),
+        }
+    } else if s.starts_with('(') && matching_paren(s) == Some(s.len() - 1) {
+        let inner = s[1..s.len() - 1].trim();
+        match split_index(inner) {
+            Some((base, 'X')) => Operand::IndirectX(base.to_string()),
+            Some(_) => return Err(format!("Expected (expr,X) or (expr),Y, got {:?}", s)),
+            None => Operand::Indirect(inner.to_string()),
+        }
+    } else {
+        Operand::Direct(s.to_string())
+    };
+    Ok(operand)
+}
+
+/// Split `expr,X` or `expr,Y` into the expression and the index register.
+fn split_index(s: &str) -> Option<(&str, char)> {
+    let comma = s.rfind(',')?;
+    let index = match s[comma + 1..].trim().to_ascii_uppercase().as_str() {
+        "X" => 'X',
+        "Y" => 'Y',
+        _ => return None,
+    };
+    Some((s[..comma].trim(), index))
+}
+
+/// The index of the `)` matching a leading `(`.
+fn matching_paren(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    /// Operators and parentheses
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 15] = [
+    "<<", ">>", "+", "-", "*", "/", "%", "&", "|", "^", "~", "<", ">", "(", ")",
+];
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = expr.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap_or_default();
+        // `%` after a value is the remainder operator
+        let after_value = matches!(
+            tokens.last(),
+            Some(Token::Number(_)) | Some(Token::Name(_)) | Some(Token::Symbol(")"))
+        );
+        let (token, len) = if let Some(hex) = rest.strip_prefix('$') {
+            number(hex, 16, 1)?
+        } else if let (Some(binary), false) = (rest.strip_prefix('%'), after_value) {
+            number(binary, 2, 1)?
+        } else if c.is_ascii_digit() {
+            number(rest, 10, 0)?
+        } else if c == '\'' {
+            let mut chars = rest[1..].chars();
+            match (chars.next(), chars.next()) {
+                (Some(value), Some('\'')) => (Token::Number(value as i64), 2 + value.len_utf8()),
+                _ => return Err(format!("Bad character literal in {:?}", expr)),
+            }
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (Token::Name(rest[..len].to_string()), len)
+        } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            (Token::Symbol(symbol), symbol.len())
+        } else {
+            return Err(format!("Unexpected {:?} in {:?}", c, expr));
+        };
+        tokens.push(token);
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Parse the digits at the start of `s`, `prefix` is the length of the `$`
+/// or `%` before them.
+fn number(s: &str, radix: u32, prefix: usize) -> Result<(Token, usize), String> {
+    let len = s.find(|c: char| !c.is_digit(radix)).unwrap_or(s.len());
+    let value = i64::from_str_radix(&s[..len], radix)
+        .map_err(|e| format!("Invalid number {:?}: {}", &s[..len], e))?;
+    Ok((Token::Number(value), prefix + len))
+}
+
+/// Precedence climbing over the tokens of one expression.
+struct ExprParser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    labels: &'a HashMap<String, u16>,
+    pc: i64,
+    last_pass: bool,
+}
+
+impl<'a> ExprParser<'a> {
+    fn binary_precedence(symbol: &str) -> Option<u8> {
+        Some(match symbol {
+            "|" => 1,
+            "^" => 2,
+            "&" => 3,
+            "<<" | ">>" => 4,
+            "+" | "-" => 5,
+            "*" | "/" | "%" => 6,
+            _ => return None,
+        })
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    /// Values are `None` while they depend on undefined labels.
+    fn expression(&mut self, min_precedence: u8) -> Result<Option<i64>, String> {
+        let mut left = self.unary()?;
+        while let Some(Token::Symbol(symbol)) = self.tokens.get(self.position) {
+            let precedence = match Self::binary_precedence(symbol) {
+                Some(precedence) if precedence > min_precedence => precedence,
+                _ => break,
+            };
+            self.position += 1;
+            let right = self.expression(precedence)?;
+            left = match (left, right) {
+                (Some(left), Some(right)) => Some(apply(symbol, left, right)?),
+                _ => None,
+            };
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Option<i64>, String> {
+        let value = match self.next() {
+            Some(Token::Number(value)) => Some(*value),
+            Some(Token::Name(name)) => match self.labels.get(name) {
+                Some(value) => Some(*value as i64),
+                None if self.last_pass => return Err(format!("{} isn't defined", name)),
+                None => None,
+            },
+            // `*` where a value is expected is the current address
+            Some(Token::Symbol("*")) => Some(self.pc),
+            Some(Token::Symbol("(")) => {
+                let value = self.expression(0)?;
+                if self.next() != Some(&Token::Symbol(")")) {
+                    return Err("Missing )".to_string());
+                }
+                value
+            }
+            Some(Token::Symbol(symbol)) => {
+                let symbol = *symbol;
+                let value = self.unary()?;
+                return Ok(match symbol {
+                    "-" => value.map(|value| -value),
+                    "~" => value.map(|value| !value & 0xFFFF),
+                    "<" => value.map(|value| value & 0xFF),
+                    ">" => value.map(|value| (value >> 8) & 0xFF),
+                    _ => return Err(format!("Unexpected {}", symbol)),
+                });
+            }
+            None => return Err("Expected a value".to_string()),
+        };
+        Ok(value)
+    }
+}
+
+fn apply(symbol: &str, left: i64, right: i64) -> Result<i64, String> {
+    Ok(match symbol {
+        "|" => left | right,
+        "^" => left ^ right,
+        "&" => left & right,
+        "<<" => left << (right & 63),
+        ">>" => left >> (right & 63),
+        "+" => left + right,
+        "-" => left - right,
+        "*" => left * right,
+        "/" | "%" if right == 0 => return Err("Division by zero".to_string()),
+        "/" => left / right,
+        "%" => left % right,
+        _ => unreachable!("not a binary operator: {}", symbol),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::cpu::ProcessorStatusFlag;
+    use crate::nes::Nes;
+
+    /// Assemble `source` and run it for a frame.
+    fn run(source: &str) -> Nes {
+        let mut nes = assemble(source).unwrap().boot();
+        nes.run_frame();
+        nes
+    }
+
+    #[test]
+    fn copy_loop() {
+        let nes = run("
+            reset:  ldx #0
+            loop:   lda message,x
+                    beq done
+                    sta $0200,x
+                    inx
+                    bne loop
+            done:   jmp done
+            message:
+                    .byte \"HI!\", 0
+        ");
+        assert_eq!(&nes.cpu_ram[0x200..0x204], b"HI!\0");
+        assert_eq!(nes.cpu.x, 3);
+        assert_eq!(nes.cpu.acc, 0);
+        assert!(nes.cpu.get_processor_status_flag(ProcessorStatusFlag::Zero));
+    }
+
+    #[test]
+    fn arithmetic_and_flags() {
+        let nes = run("
+            reset:  clc
+                    lda #$40
+                    adc #$50
+                    sta $10
+                    sec
+                    sbc #1
+                    sta $0811       ; RAM is mirrored every 2 KiB
+            done:   jmp done
+        ");
+        assert_eq!(nes.cpu_ram[0x10], 0x90);
+        assert_eq!(nes.cpu_ram[0x11], 0x8F);
+        assert_eq!(nes.cpu.acc, 0x8F);
+        assert!(nes
+            .cpu
+            .get_processor_status_flag(ProcessorStatusFlag::Negative));
+        assert!(nes
+            .cpu
+            .get_processor_status_flag(ProcessorStatusFlag::Carry));
+    }
+
+    #[test]
+    fn subroutines_and_indirect_addressing() {
+        let nes = run("
+            POINTER = $20
+            reset:  ldx #$FF
+                    txs
+                    lda #<table
+                    sta POINTER
+                    lda #>table
+                    sta POINTER + 1
+                    ldy #2
+                    jsr fetch
+                    sta $30
+            done:   jmp done
+            fetch:  lda (POINTER),y
+                    rts
+            table:  .byte 7, 8, 9
+        ");
+        assert_eq!(nes.cpu_ram[0x30], 9);
+        assert_eq!(nes.cpu.y, 2);
+        assert_eq!(nes.cpu.s, 0xFF);
+    }
+}
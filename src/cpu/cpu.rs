@@ -1,7 +1,6 @@
-use crate::cpu::addressing_modes::*;
-use crate::cpu::opcode_logic::*;
 use crate::nes::Nes;
 
+#[derive(Clone)]
 pub struct Cpu {
     pub acc: u8,
     pub x: u8,
@@ -17,6 +16,10 @@ pub struct Cpu {
     // interrupt
     pub reset: bool,
     pub nmi: bool,
+    /// The IRQ line, held high by the APU and cartridge while they want an interrupt
+    pub irq: bool,
+    /// Total number of cycles run since power on
+    pub cycles: u64,
 }
 
 #[repr(u8)]
@@ -45,6 +48,8 @@ impl Cpu {
             halt: false,
             reset: true,
             nmi: false,
+            irq: false,
+            cycles: 0,
         }
     }
 
@@ -81,6 +86,24 @@ impl Cpu {
 }
 
 impl Nes {
+    /// Push the PC and the status and jump to the address at `vector`, for
+    /// NMIs and IRQs.  The status is pushed with the break flag clear and
+    /// bit 5 set.  Interrupts take 7 cycles.
+    fn interrupt(&mut self, vector: u16) {
+        let pc = self.cpu.pc;
+        let status = (self.cpu.p & !(1 << ProcessorStatusFlag::Break as u8))
+            | 1 << ProcessorStatusFlag::Always as u8;
+        self.cpu_write(self.cpu.s as u16 | 0x100, (pc >> 8) as u8);
+        self.cpu_write(self.cpu.s.wrapping_sub(1) as u16 | 0x100, pc as u8);
+        self.cpu_write(self.cpu.s.wrapping_sub(2) as u16 | 0x100, status);
+        self.cpu.s = self.cpu.s.wrapping_sub(3);
+
+        self.cpu.set_flag(ProcessorStatusFlag::Interrupt);
+        let lo = self.cpu_read(vector);
+        let hi = self.cpu_read(vector + 1);
+        self.cpu.pc = lo as u16 | ((hi as u16) << 8);
+    }
+
     pub fn step_cpu(&mut self) -> u8 {
         let op = self.cpu_read(self.cpu.pc);
 
@@ -119,25 +142,18 @@ impl Nes {
         if self.cpu.nmi {
             trace!("Interrupt: NMI");
             self.cpu.nmi = false;
+            self.interrupt(0xFFFA);
+            return 7;
+        }
 
-            // push flags and PC to stack
-            self.cpu.s = self.cpu.s.wrapping_sub(3);
-            self.cpu_write(self.cpu.s.wrapping_add(1) as u16 | 0x100, self.cpu.p); // this is wrong
-            self.cpu_write(self.cpu.s.wrapping_add(2) as u16 | 0x100, self.cpu.pc as u8);
-            self.cpu_write(
-                self.cpu.s.wrapping_add(3) as u16 | 0x100,
-                (self.cpu.pc >> 8) as u8,
-            );
-
-            // set PC to address at $FFFA (NMI vector)
-            let lo = self.cpu_read(0xFFFA);
-            let hi = self.cpu_read(0xFFFB);
-            self.cpu.pc = lo as u16 | ((hi as u16) << 8);
-
-            // set interrupt flag
-            self.cpu.set_flag(ProcessorStatusFlag::Interrupt);
-
-            // interrupts take 7 cycles
+        // IRQ interrupt, ignored while the interrupt flag is set
+        if self.cpu.irq
+            && !self
+                .cpu
+                .get_processor_status_flag(ProcessorStatusFlag::Interrupt)
+        {
+            trace!("Interrupt: IRQ");
+            self.interrupt(0xFFFE);
             return 7;
         }
 
@@ -1388,3 +1404,84 @@ impl Nes {
         cyc_inc_by
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::assembler::assemble;
+    use crate::nes::Nes;
+
+    fn run(source: &str) -> Nes {
+        let mut nes = assemble(source).unwrap().boot();
+        for _ in 0..3 {
+            nes.run_frame();
+        }
+        nes
+    }
+
+    #[test]
+    fn irq_pushes_status_without_break() {
+        let nes = run("
+            reset:  ldx #$FF
+                    txs
+                    lda #0
+                    sta $4017       ; enable the frame counter IRQ
+                    sec
+                    cli
+            done:   jmp done
+            irq:    tsx
+                    lda $0101,x
+                    sta $00
+                    lda #$40
+                    sta $4017       ; and acknowledge it
+                    rti
+        ");
+        // carry, zero and bit 5 set, break and interrupt disable clear
+        assert_eq!(nes.cpu_ram[0x00], 0x23);
+    }
+
+    #[test]
+    fn nmi_pushes_status_without_break() {
+        let nes = run("
+            reset:  ldx #$FF
+                    txs
+                    sed
+                    lda #$80
+                    sta $2000
+            done:   jmp done
+            nmi:    tsx
+                    lda $0101,x
+                    sta $00
+                    rti
+        ");
+        // decimal, interrupt disable and bit 5 set, break clear
+        assert_eq!(nes.cpu_ram[0x00] & 0b0011_1100, 0b0010_1100);
+    }
+
+    #[test]
+    fn branch_cycles() {
+        let mut nes = assemble(
+            "
+                    .org $8000
+            reset:  inx
+                    cpx #2
+                    bne reset       ; back to the start of ROM once
+                    lda #0
+                    beq same        ; taken on the same page
+                    nop
+            same:   jmp cross
+                    .org $80FB
+            cross:  bne cross       ; not taken
+                    beq far         ; taken onto the next page
+                    nop
+                    nop
+            far:    jmp far
+        ",
+        )
+        .unwrap()
+        .boot();
+        let cycles: Vec<u8> = (0..13).map(|_| nes.step_cpu()).collect();
+        // reset, INX, CPX, BNE, INX, CPX, BNE, LDA, BEQ, JMP, BNE, BEQ, JMP
+        assert_eq!(cycles, [7, 2, 2, 3, 2, 2, 2, 2, 3, 3, 2, 4, 3]);
+        assert_eq!(nes.cpu.pc, 0x8101);
+    }
+}
@@ -0,0 +1,260 @@
+//! Turning machine code back into assembly, for reading game code.
+
+use std::collections::HashMap;
+
+use crate::cpu::opcodes::{AddressingMode, Opcode, OPCODES};
+
+/// Where the CPU finds the addresses of its interrupt handlers.
+const VECTORS: [(u16, &str); 3] = [(0xFFFA, "nmi"), (0xFFFC, "reset"), (0xFFFE, "irq")];
+
+/// The names the NESdev wiki uses for the memory mapped registers.
+const REGISTERS: [(u16, &str); 29] = [
+    (0x2000, "PPUCTRL"),
+    (0x2001, "PPUMASK"),
+    (0x2002, "PPUSTATUS"),
+    (0x2003, "OAMADDR"),
+    (0x2004, "OAMDATA"),
+    (0x2005, "PPUSCROLL"),
+    (0x2006, "PPUADDR"),
+    (0x2007, "PPUDATA"),
+    (0x4000, "SQ1_VOL"),
+    (0x4001, "SQ1_SWEEP"),
+    (0x4002, "SQ1_LO"),
+    (0x4003, "SQ1_HI"),
+    (0x4004, "SQ2_VOL"),
+    (0x4005, "SQ2_SWEEP"),
+    (0x4006, "SQ2_LO"),
+    (0x4007, "SQ2_HI"),
+    (0x4008, "TRI_LINEAR"),
+    (0x400A, "TRI_LO"),
+    (0x400B, "TRI_HI"),
+    (0x400C, "NOISE_VOL"),
+    (0x400E, "NOISE_LO"),
+    (0x400F, "NOISE_HI"),
+    (0x4010, "DMC_FREQ"),
+    (0x4011, "DMC_RAW"),
+    (0x4012, "DMC_START"),
+    (0x4013, "DMC_LEN"),
+    (0x4014, "OAMDMA"),
+    (0x4015, "SND_CHN"),
+    (0x4016, "JOY1"),
+];
+
+/// Names to show instead of addresses.
+#[derive(Debug, Clone, Default)]
+pub struct Labels {
+    names: HashMap<u16, String>,
+}
+
+impl Labels {
+    /// The PPU, APU and controller registers, and the interrupt vectors.
+    pub fn hardware() -> Self {
+        let mut labels = Self::default();
+        for &(addr, name) in REGISTERS.iter() {
+            labels.insert(addr, name);
+        }
+        // $4017 is JOY2 when read and the frame counter when written
+        labels.insert(0x4017, "JOY2");
+        for &(addr, name) in VECTORS.iter() {
+            labels.insert(addr, &format!("{}_vector", name));
+        }
+        labels
+    }
+
+    /// Name the interrupt handlers that the vectors point to.
+    pub fn add_vector_targets(&mut self, mut peek: impl FnMut(u16) -> u8) {
+        for &(addr, name) in VECTORS.iter() {
+            let target = u16::from_le_bytes([peek(addr), peek(addr + 1)]);
+            // several vectors often share a handler
+            if self.get(target).is_none() {
+                self.insert(target, name);
+            }
+        }
+    }
+
+    pub fn insert(&mut self, addr: u16, name: &str) {
+        self.names.insert(addr, name.to_string());
+    }
+
+    pub fn get(&self, addr: u16) -> Option<&str> {
+        self.names.get(&addr).map(String::as_str)
+    }
+
+    /// The label for `addr`, or `addr` in hex.
+    fn name_or_hex(&self, addr: u16, zero_page: bool) -> String {
+        match self.get(addr) {
+            Some(name) => name.to_string(),
+            None if zero_page => format!("${:02X}", addr),
+            None => format!("${:04X}", addr),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Instruction {
+    pub addr: u16,
+    pub opcode: u8,
+    /// The bytes after the opcode, little endian, 0 if there are none
+    pub operand: u16,
+}
+
+impl Instruction {
+    /// Decode the instruction at `addr`, reading memory through `peek`.
+    pub fn decode(addr: u16, mut peek: impl FnMut(u16) -> u8) -> Self {
+        let opcode = peek(addr);
+        let operand = match OPCODES[opcode as usize].mode.operand_len() {
+            0 => 0,
+            1 => peek(addr.wrapping_add(1)) as u16,
+            _ => u16::from_le_bytes([peek(addr.wrapping_add(1)), peek(addr.wrapping_add(2))]),
+        };
+        Self {
+            addr,
+            opcode,
+            operand,
+        }
+    }
+
+    pub fn info(&self) -> &'static Opcode {
+        &OPCODES[self.opcode as usize]
+    }
+
+    /// Bytes including the opcode.
+    pub fn len(&self) -> u16 {
+        1 + self.info().mode.operand_len()
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        let operand = self.operand.to_le_bytes();
+        let mut bytes = vec![self.opcode];
+        bytes.extend_from_slice(&operand[..self.info().mode.operand_len() as usize]);
+        bytes
+    }
+
+    /// Where a branch goes if it's taken.
+    pub fn branch_target(&self) -> Option<u16> {
+        if self.info().mode == AddressingMode::Relative {
+            let offset = self.operand as u8 as i8 as u16;
+            Some(self.addr.wrapping_add(2).wrapping_add(offset))
+        } else {
+            None
+        }
+    }
+
+    /// The instruction in assembly syntax, like `LDA ($10),Y`.
+    pub fn text(&self, labels: &Labels) -> String {
+        let info = self.info();
+        let operand = self.operand;
+        let operand = match info.mode {
+            AddressingMode::Implied => String::new(),
+            AddressingMode::Accumulator => "A".to_string(),
+            AddressingMode::Immediate => format!("#${:02X}", operand),
+            AddressingMode::ZeroPage => labels.name_or_hex(operand, true),
+            AddressingMode::ZeroPageX => format!("{},X", labels.name_or_hex(operand, true)),
+            AddressingMode::ZeroPageY => format!("{},Y", labels.name_or_hex(operand, true)),
+            AddressingMode::Absolute => labels.name_or_hex(operand, false),
+            AddressingMode::AbsoluteX => format!("{},X", labels.name_or_hex(operand, false)),
+            AddressingMode::AbsoluteY => format!("{},Y", labels.name_or_hex(operand, false)),
+            AddressingMode::Indirect => format!("({})", labels.name_or_hex(operand, false)),
+            AddressingMode::IndirectX => format!("(${:02X},X)", operand),
+            AddressingMode::IndirectY => format!("(${:02X}),Y", operand),
+            AddressingMode::Relative => {
+                let target = self.branch_target().unwrap_or_default();
+                labels.name_or_hex(target, false)
+            }
+        };
+        if operand.is_empty() {
+            info.mnemonic.to_string()
+        } else {
+            format!("{} {}", info.mnemonic, operand)
+        }
+    }
+}
+
+/// Disassemble `start` to `end` inclusive, one line per instruction like
+/// `C000  A9 10     LDA #$10`.  Unofficial opcodes are marked with a `*`,
+/// the interrupt vectors are shown as `.word`, and labelled addresses get a
+/// line of their own.
+pub fn disassemble(
+    start: u16,
+    end: u16,
+    labels: &Labels,
+    mut peek: impl FnMut(u16) -> u8,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut addr = start as u32;
+    while addr <= end as u32 {
+        let pc = addr as u16;
+        if let Some(label) = labels.get(pc) {
+            lines.push(format!("{}:", label));
+        }
+
+        if pc >= 0xFFFA && pc & 1 == 0 && end > pc {
+            let target = u16::from_le_bytes([peek(pc), peek(pc + 1)]);
+            lines.push(format!(
+                "{:04X}  {:02X} {:02X}      .word {}",
+                pc,
+                target as u8,
+                target >> 8,
+                labels.name_or_hex(target, false)
+            ));
+            addr += 2;
+            continue;
+        }
+
+        let opcode = peek(pc);
+        let len = 1 + OPCODES[opcode as usize].mode.operand_len() as u32;
+        if addr + len - 1 > end as u32 {
+            // the operand would be past the end, so it's probably data
+            lines.push(format!(
+                "{:04X}  {:02X}         .byte ${:02X}",
+                pc, opcode, opcode
+            ));
+            addr += 1;
+            continue;
+        }
+
+        let instruction = Instruction::decode(pc, &mut peek);
+
+        let bytes: Vec<String> = instruction
+            .bytes()
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        let marker = if instruction.info().official {
+            ' '
+        } else {
+            '*'
+        };
+        lines.push(format!(
+            "{:04X}  {:<8}  {}{}",
+            pc,
+            bytes.join(" "),
+            marker,
+            instruction.text(labels)
+        ));
+        addr += instruction.len() as u32;
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operand_past_the_end() {
+        // a JSR whose operand would be outside the bank
+        let data = [0xEA, 0xEA, 0x20];
+        let lines = disassemble(0x8000, 0x8002, &Labels::default(), |addr| {
+            data[(addr - 0x8000) as usize]
+        });
+        assert_eq!(
+            lines,
+            [
+                "8000  EA         NOP",
+                "8001  EA         NOP",
+                "8002  20         .byte $20",
+            ]
+        );
+    }
+}
@@ -1,3 +1,6 @@
 pub mod addressing_modes;
+pub mod assembler;
 pub mod cpu;
+pub mod disassembler;
 pub mod opcode_logic;
+pub mod opcodes;
@@ -187,7 +187,7 @@ impl Nes {
     // TODO: possibly wrong
     pub fn php(&mut self) {
         let idx = self.cpu.s as u16 + 0x100;
-        self.cpu_write(idx as u16, self.cpu.p | 0b00110000);
+        self.cpu_write(idx, self.cpu.p | 0b00110000);
         self.cpu.s = self.cpu.s.wrapping_sub(1);
     }
 
@@ -437,7 +437,7 @@ impl Nes {
             .set_flag_value(ProcessorStatusFlag::Carry, carry_flag);
     }
 
-    /// logic
+    // logic
 
     /// Function that implements the flag setting logic of `cmp`, `cpx`, and `cpy`
     fn common_cmp(&mut self, first: u8, second: u8) {
@@ -489,108 +489,75 @@ impl Nes {
     }
 
     // Branching
-    pub fn bpl(&mut self, val: u8) -> u8 {
-        if !self
-            .cpu
-            .get_processor_status_flag(ProcessorStatusFlag::Negative)
-        {
-            let old_pc = self.cpu.pc;
-            self.cpu.pc = (self.cpu.pc as i16 + val as i8 as i16) as u16;
-            return 1 + (0/*self.cpu.pc & 0xFF00 != old_pc & 0xFF00*/) as u8;
+
+    /// Jump `offset` bytes from the instruction after the branch if it's
+    /// taken.  Returns the extra cycles: 1 if it's taken, and another if
+    /// that lands on a different page.
+    fn branch(&mut self, taken: bool, offset: u8) -> u8 {
+        if !taken {
+            return 0;
         }
+        // the PC still points at the branch, it's moved past it afterwards
+        let next = self.cpu.pc.wrapping_add(2);
+        let target = next.wrapping_add(offset as i8 as u16);
+        self.cpu.pc = target.wrapping_sub(2);
+        1 + (target & 0xFF00 != next & 0xFF00) as u8
+    }
 
-        0
+    pub fn bpl(&mut self, val: u8) -> u8 {
+        let taken = !self
+            .cpu
+            .get_processor_status_flag(ProcessorStatusFlag::Negative);
+        self.branch(taken, val)
     }
 
     pub fn bmi(&mut self, val: u8) -> u8 {
-        if self
+        let taken = self
             .cpu
-            .get_processor_status_flag(ProcessorStatusFlag::Negative)
-        {
-            let old_pc = self.cpu.pc;
-            self.cpu.pc = (self.cpu.pc as i16 + val as i8 as i16) as u16;
-            return 1 + (0/*self.cpu.pc & 0xFF00 != old_pc & 0xFF00*/) as u8;
-        }
-
-        0
+            .get_processor_status_flag(ProcessorStatusFlag::Negative);
+        self.branch(taken, val)
     }
 
     pub fn bvc(&mut self, val: u8) -> u8 {
-        if !self
+        let taken = !self
             .cpu
-            .get_processor_status_flag(ProcessorStatusFlag::Overflow)
-        {
-            let old_pc = self.cpu.pc;
-            self.cpu.pc = (self.cpu.pc as i16 + val as i8 as i16) as u16;
-            return 1 + (0/*self.cpu.pc & 0xFF00 != old_pc & 0xFF00*/) as u8;
-        }
-
-        0
+            .get_processor_status_flag(ProcessorStatusFlag::Overflow);
+        self.branch(taken, val)
     }
 
     pub fn bvs(&mut self, val: u8) -> u8 {
-        if self
+        let taken = self
             .cpu
-            .get_processor_status_flag(ProcessorStatusFlag::Overflow)
-        {
-            let old_pc = self.cpu.pc;
-            self.cpu.pc = (self.cpu.pc as i16 + val as i8 as i16) as u16;
-            return 1 + (0/*self.cpu.pc & 0xFF00 != old_pc & 0xFF00*/) as u8;
-        }
-
-        0
+            .get_processor_status_flag(ProcessorStatusFlag::Overflow);
+        self.branch(taken, val)
     }
 
     pub fn bcc(&mut self, val: u8) -> u8 {
-        if !self
+        let taken = !self
             .cpu
-            .get_processor_status_flag(ProcessorStatusFlag::Carry)
-        {
-            let old_pc = self.cpu.pc;
-            self.cpu.pc = (self.cpu.pc as i16 + val as i8 as i16) as u16;
-            return 1 + (0/*self.cpu.pc & 0xFF00 != old_pc & 0xFF00*/) as u8;
-        }
-
-        0
+            .get_processor_status_flag(ProcessorStatusFlag::Carry);
+        self.branch(taken, val)
     }
 
     pub fn bcs(&mut self, val: u8) -> u8 {
-        if self
+        let taken = self
             .cpu
-            .get_processor_status_flag(ProcessorStatusFlag::Carry)
-        {
-            let old_pc = self.cpu.pc;
-            self.cpu.pc = (self.cpu.pc as i16 + val as i8 as i16) as u16;
-            return 1 + (0/*self.cpu.pc & 0xFF00 != old_pc & 0xFF00*/) as u8;
-        }
-
-        0
+            .get_processor_status_flag(ProcessorStatusFlag::Carry);
+        self.branch(taken, val)
     }
 
     pub fn bne(&mut self, val: u8) -> u8 {
-        if !self
+        let taken = !self
             .cpu
-            .get_processor_status_flag(ProcessorStatusFlag::Zero)
-        {
-            let old_pc = self.cpu.pc;
-            self.cpu.pc = (self.cpu.pc as i16 + val as i8 as i16) as u16;
-            return 1 + (0/*self.cpu.pc & 0xFF00 != old_pc & 0xFF00*/) as u8;
-        }
-
-        0
+            .get_processor_status_flag(ProcessorStatusFlag::Zero);
+        self.branch(taken, val)
     }
 
     pub fn beq(&mut self, val: u8) -> u8 {
-        if self
+        let taken = self
             .cpu
-            .get_processor_status_flag(ProcessorStatusFlag::Zero)
-        {
-            let old_pc = self.cpu.pc;
-            self.cpu.pc = (self.cpu.pc as i16 + val as i16) as u16;
-            return 1 + (0/*self.cpu.pc & 0xFF00 != old_pc & 0xFF00*/) as u8;
-        }
-
-        0
+            .get_processor_status_flag(ProcessorStatusFlag::Zero);
+        self.branch(taken, val)
     }
 
     // TODO: this function is probably wrong
@@ -0,0 +1,369 @@
+//! The 6502's instruction set, shared by the disassembler and assembler.
+//!
+//! Unofficial opcodes use the names from the NESdev wiki, and every one of
+//! them is here, including the ones that lock up the CPU (`KIL`).
+
+use self::AddressingMode::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressingMode {
+    Implied,
+    /// `ASL A`
+    Accumulator,
+    /// `LDA #$10`
+    Immediate,
+    /// `LDA $10`
+    ZeroPage,
+    /// `LDA $10,X`
+    ZeroPageX,
+    /// `LDX $10,Y`
+    ZeroPageY,
+    /// `LDA $1234`
+    Absolute,
+    /// `LDA $1234,X`
+    AbsoluteX,
+    /// `LDA $1234,Y`
+    AbsoluteY,
+    /// `JMP ($1234)`
+    Indirect,
+    /// `LDA ($10,X)`
+    IndirectX,
+    /// `LDA ($10),Y`
+    IndirectY,
+    /// Branches, a signed offset from the next instruction
+    Relative,
+}
+
+impl AddressingMode {
+    /// How many bytes follow the opcode.
+    pub fn operand_len(self) -> u16 {
+        match self {
+            Implied | Accumulator => 0,
+            Immediate | ZeroPage | ZeroPageX | ZeroPageY | IndirectX | IndirectY | Relative => 1,
+            Absolute | AbsoluteX | AbsoluteY | Indirect => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Opcode {
+    pub mnemonic: &'static str,
+    pub mode: AddressingMode,
+    /// Whether it's one of the 151 documented opcodes
+    pub official: bool,
+}
+
+const fn official(mnemonic: &'static str, mode: AddressingMode) -> Opcode {
+    Opcode {
+        mnemonic,
+        mode,
+        official: true,
+    }
+}
+
+const fn unofficial(mnemonic: &'static str, mode: AddressingMode) -> Opcode {
+    Opcode {
+        mnemonic,
+        mode,
+        official: false,
+    }
+}
+
+/// Indexed by opcode byte.
+pub const OPCODES: [Opcode; 256] = [
+    // $00
+    official("BRK", Implied),
+    official("ORA", IndirectX),
+    unofficial("KIL", Implied),
+    unofficial("SLO", IndirectX),
+    unofficial("NOP", ZeroPage),
+    official("ORA", ZeroPage),
+    official("ASL", ZeroPage),
+    unofficial("SLO", ZeroPage),
+    official("PHP", Implied),
+    official("ORA", Immediate),
+    official("ASL", Accumulator),
+    unofficial("ANC", Immediate),
+    unofficial("NOP", Absolute),
+    official("ORA", Absolute),
+    official("ASL", Absolute),
+    unofficial("SLO", Absolute),
+    // $10
+    official("BPL", Relative),
+    official("ORA", IndirectY),
+    unofficial("KIL", Implied),
+    unofficial("SLO", IndirectY),
+    unofficial("NOP", ZeroPageX),
+    official("ORA", ZeroPageX),
+    official("ASL", ZeroPageX),
+    unofficial("SLO", ZeroPageX),
+    official("CLC", Implied),
+    official("ORA", AbsoluteY),
+    unofficial("NOP", Implied),
+    unofficial("SLO", AbsoluteY),
+    unofficial("NOP", AbsoluteX),
+    official("ORA", AbsoluteX),
+    official("ASL", AbsoluteX),
+    unofficial("SLO", AbsoluteX),
+    // $20
+    official("JSR", Absolute),
+    official("AND", IndirectX),
+    unofficial("KIL", Implied),
+    unofficial("RLA", IndirectX),
+    official("BIT", ZeroPage),
+    official("AND", ZeroPage),
+    official("ROL", ZeroPage),
+    unofficial("RLA", ZeroPage),
+    official("PLP", Implied),
+    official("AND", Immediate),
+    official("ROL", Accumulator),
+    unofficial("ANC", Immediate),
+    official("BIT", Absolute),
+    official("AND", Absolute),
+    official("ROL", Absolute),
+    unofficial("RLA", Absolute),
+    // $30
+    official("BMI", Relative),
+    official("AND", IndirectY),
+    unofficial("KIL", Implied),
+    unofficial("RLA", IndirectY),
+    unofficial("NOP", ZeroPageX),
+    official("AND", ZeroPageX),
+    official("ROL", ZeroPageX),
+    unofficial("RLA", ZeroPageX),
+    official("SEC", Implied),
+    official("AND", AbsoluteY),
+    unofficial("NOP", Implied),
+    unofficial("RLA", AbsoluteY),
+    unofficial("NOP", AbsoluteX),
+    official("AND", AbsoluteX),
+    official("ROL", AbsoluteX),
+    unofficial("RLA", AbsoluteX),
+    // $40
+    official("RTI", Implied),
+    official("EOR", IndirectX),
+    unofficial("KIL", Implied),
+    unofficial("SRE", IndirectX),
+    unofficial("NOP", ZeroPage),
+    official("EOR", ZeroPage),
+    official("LSR", ZeroPage),
+    unofficial("SRE", ZeroPage),
+    official("PHA", Implied),
+    official("EOR", Immediate),
+    official("LSR", Accumulator),
+    unofficial("ALR", Immediate),
+    official("JMP", Absolute),
+    official("EOR", Absolute),
+    official("LSR", Absolute),
+    unofficial("SRE", Absolute),
+    // $50
+    official("BVC", Relative),
+    official("EOR", IndirectY),
+    unofficial("KIL", Implied),
+    unofficial("SRE", IndirectY),
+    unofficial("NOP", ZeroPageX),
+    official("EOR", ZeroPageX),
+    official("LSR", ZeroPageX),
+    unofficial("SRE", ZeroPageX),
+    official("CLI", Implied),
+    official("EOR", AbsoluteY),
+    unofficial("NOP", Implied),
+    unofficial("SRE", AbsoluteY),
+    unofficial("NOP", AbsoluteX),
+    official("EOR", AbsoluteX),
+    official("LSR", AbsoluteX),
+    unofficial("SRE", AbsoluteX),
+    // $60
+    official("RTS", Implied),
+    official("ADC", IndirectX),
+    unofficial("KIL", Implied),
+    unofficial("RRA", IndirectX),
+    unofficial("NOP", ZeroPage),
+    official("ADC", ZeroPage),
+    official("ROR", ZeroPage),
+    unofficial("RRA", ZeroPage),
+    official("PLA", Implied),
+    official("ADC", Immediate),
+    official("ROR", Accumulator),
+    unofficial("ARR", Immediate),
+    official("JMP", Indirect),
+    official("ADC", Absolute),
+    official("ROR", Absolute),
+    unofficial("RRA", Absolute),
+    // $70
+    official("BVS", Relative),
+    official("ADC", IndirectY),
+    unofficial("KIL", Implied),
+    unofficial("RRA", IndirectY),
+    unofficial("NOP", ZeroPageX),
+    official("ADC", ZeroPageX),
+    official("ROR", ZeroPageX),
+    unofficial("RRA", ZeroPageX),
+    official("SEI", Implied),
+    official("ADC", AbsoluteY),
+    unofficial("NOP", Implied),
+    unofficial("RRA", AbsoluteY),
+    unofficial("NOP", AbsoluteX),
+    official("ADC", AbsoluteX),
+    official("ROR", AbsoluteX),
+    unofficial("RRA", AbsoluteX),
+    // $80
+    unofficial("NOP", Immediate),
+    official("STA", IndirectX),
+    unofficial("NOP", Immediate),
+    unofficial("SAX", IndirectX),
+    official("STY", ZeroPage),
+    official("STA", ZeroPage),
+    official("STX", ZeroPage),
+    unofficial("SAX", ZeroPage),
+    official("DEY", Implied),
+    unofficial("NOP", Immediate),
+    official("TXA", Implied),
+    unofficial("XAA", Immediate),
+    official("STY", Absolute),
+    official("STA", Absolute),
+    official("STX", Absolute),
+    unofficial("SAX", Absolute),
+    // $90
+    official("BCC", Relative),
+    official("STA", IndirectY),
+    unofficial("KIL", Implied),
+    unofficial("AHX", IndirectY),
+    official("STY", ZeroPageX),
+    official("STA", ZeroPageX),
+    official("STX", ZeroPageY),
+    unofficial("SAX", ZeroPageY),
+    official("TYA", Implied),
+    official("STA", AbsoluteY),
+    official("TXS", Implied),
+    unofficial("TAS", AbsoluteY),
+    unofficial("SHY", AbsoluteX),
+    official("STA", AbsoluteX),
+    unofficial("SHX", AbsoluteY),
+    unofficial("AHX", AbsoluteY),
+    // $A0
+    official("LDY", Immediate),
+    official("LDA", IndirectX),
+    official("LDX", Immediate),
+    unofficial("LAX", IndirectX),
+    official("LDY", ZeroPage),
+    official("LDA", ZeroPage),
+    official("LDX", ZeroPage),
+    unofficial("LAX", ZeroPage),
+    official("TAY", Implied),
+    official("LDA", Immediate),
+    official("TAX", Implied),
+    unofficial("LAX", Immediate),
+    official("LDY", Absolute),
+    official("LDA", Absolute),
+    official("LDX", Absolute),
+    unofficial("LAX", Absolute),
+    // $B0
+    official("BCS", Relative),
+    official("LDA", IndirectY),
+    unofficial("KIL", Implied),
+    unofficial("LAX", IndirectY),
+    official("LDY", ZeroPageX),
+    official("LDA", ZeroPageX),
+    official("LDX", ZeroPageY),
+    unofficial("LAX", ZeroPageY),
+    official("CLV", Implied),
+    official("LDA", AbsoluteY),
+    official("TSX", Implied),
+    unofficial("LAS", AbsoluteY),
+    official("LDY", AbsoluteX),
+    official("LDA", AbsoluteX),
+    official("LDX", AbsoluteY),
+    unofficial("LAX", AbsoluteY),
+    // $C0
+    official("CPY", Immediate),
+    official("CMP", IndirectX),
+    unofficial("NOP", Immediate),
+    unofficial("DCP", IndirectX),
+    official("CPY", ZeroPage),
+    official("CMP", ZeroPage),
+    official("DEC", ZeroPage),
+    unofficial("DCP", ZeroPage),
+    official("INY", Implied),
+    official("CMP", Immediate),
+    official("DEX", Implied),
+    unofficial("AXS", Immediate),
+    official("CPY", Absolute),
+    official("CMP", Absolute),
+    official("DEC", Absolute),
+    unofficial("DCP", Absolute),
+    // $D0
+    official("BNE", Relative),
+    official("CMP", IndirectY),
+    unofficial("KIL", Implied),
+    unofficial("DCP", IndirectY),
+    unofficial("NOP", ZeroPageX),
+    official("CMP", ZeroPageX),
+    official("DEC", ZeroPageX),
+    unofficial("DCP", ZeroPageX),
+    official("CLD", Implied),
+    official("CMP", AbsoluteY),
+    unofficial("NOP", Implied),
+    unofficial("DCP", AbsoluteY),
+    unofficial("NOP", AbsoluteX),
+    official("CMP", AbsoluteX),
+    official("DEC", AbsoluteX),
+    unofficial("DCP", AbsoluteX),
+    // $E0
+    official("CPX", Immediate),
+    official("SBC", IndirectX),
+    unofficial("NOP", Immediate),
+    unofficial("ISC", IndirectX),
+    official("CPX", ZeroPage),
+    official("SBC", ZeroPage),
+    official("INC", ZeroPage),
+    unofficial("ISC", ZeroPage),
+    official("INX", Implied),
+    official("SBC", Immediate),
+    official("NOP", Implied),
+    unofficial("SBC", Immediate),
+    official("CPX", Absolute),
+    official("SBC", Absolute),
+    official("INC", Absolute),
+    unofficial("ISC", Absolute),
+    // $F0
+    official("BEQ", Relative),
+    official("SBC", IndirectY),
+    unofficial("KIL", Implied),
+    unofficial("ISC", IndirectY),
+    unofficial("NOP", ZeroPageX),
+    official("SBC", ZeroPageX),
+    official("INC", ZeroPageX),
+    unofficial("ISC", ZeroPageX),
+    official("SED", Implied),
+    official("SBC", AbsoluteY),
+    unofficial("NOP", Implied),
+    unofficial("ISC", AbsoluteY),
+    unofficial("NOP", AbsoluteX),
+    official("SBC", AbsoluteX),
+    official("INC", AbsoluteX),
+    unofficial("ISC", AbsoluteX),
+];
+
+impl Opcode {
+    /// The opcode byte for an instruction, preferring the official one when
+    /// there are several, like for `NOP` and `SBC #`.
+    pub fn find(mnemonic: &str, mode: AddressingMode) -> Option<u8> {
+        let matches = |opcode: &&Opcode| {
+            opcode.mode == mode && opcode.mnemonic.eq_ignore_ascii_case(mnemonic)
+        };
+        let position = |official: bool| {
+            OPCODES
+                .iter()
+                .position(|opcode| opcode.official == official && matches(&opcode))
+        };
+        position(true)
+            .or_else(|| position(false))
+            .map(|index| index as u8)
+    }
+
+    /// Whether any opcode has this mnemonic and addressing mode.
+    pub fn exists(mnemonic: &str, mode: AddressingMode) -> bool {
+        Self::find(mnemonic, mode).is_some()
+    }
+}
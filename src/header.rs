@@ -3,18 +3,64 @@ NOTE: These fields will be filled as the header type is implemented
     bytes 0-3: constant representing 'nes' + EOF byte
     byte 4: size of PRG rom in 16384 ($4000) byte units
     byte 5: size of CHR rom in 8192 ($2000) byte units
-    byte 6: -----t--
+    byte 6: llllftbm
+        l: low nibble of the mapper number
+        f: four screen nametables
         t: contains trainer
-    byte 7:
-    byte 8: size of PRG ram in 8192 byte units
-    byte 9:
-    byte 10:
-    byte 11:
-    byte 12:
+        b: battery backed PRG RAM
+        m: nametable mirroring, 0 = horizontal, 1 = vertical
+    byte 7: hhhhNN--
+        h: high nibble of the mapper number
+        NN: 0b10 if this is a NES 2.0 header
+    byte 8: size of PRG ram in 8192 byte units (iNES)
+            ssssmmmm (NES 2.0)
+        s: submapper
+        m: bits 8-11 of the mapper number
+    byte 9: -------p (iNES)
+        p: 1 for PAL
+            ccccpppp (NES 2.0)
+        c, p: high bits of the CHR and PRG ROM sizes
+    byte 10: nnnnvvvv (NES 2.0)
+        n, v: PRG NVRAM and RAM sizes, 64 << n bytes or none if 0
+    byte 11: nnnnvvvv (NES 2.0)
+        n, v: CHR NVRAM and RAM sizes, the same way
+    byte 12: ------rr (NES 2.0)
+        rr: timing, 0 = NTSC, 1 = PAL, 2 = multi-region, 3 = Dendy
     byte 13:
     byte 14:
-    byte 15:
+    byte 15: --dddddd (NES 2.0)
+        d: the default expansion device, what should be plugged in to play
 */
+/// How the 4 nametables the PPU addresses map onto the console's 2 KiB of VRAM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirroring {
+    /// $2000 = $2400 and $2800 = $2C00, for vertical scrolling
+    Horizontal,
+    /// $2000 = $2800 and $2400 = $2C00, for horizontal scrolling
+    Vertical,
+    /// The cartridge provides the extra VRAM for 4 separate nametables
+    FourScreen,
+    /// All 4 nametables are the first page of VRAM
+    SingleScreenLower,
+    /// All 4 nametables are the second page of VRAM
+    SingleScreenUpper,
+}
+
+impl Mirroring {
+    pub fn name(self) -> &'static str {
+        match self {
+            Mirroring::Horizontal => "horizontal",
+            Mirroring::Vertical => "vertical",
+            Mirroring::FourScreen => "four-screen",
+            Mirroring::SingleScreenLower => "single-screen-lower",
+            Mirroring::SingleScreenUpper => "single-screen-upper",
+        }
+    }
+}
+
+use crate::region::Region;
+
+#[derive(Clone)]
 pub struct INESHeader {
     data: [u8; 16],
 }
@@ -25,30 +71,134 @@ impl INESHeader {
     }
 
     pub fn get_prg_rom_size(&self) -> usize {
-        self.data[4] as usize * 16384
+        if self.is_nes2() {
+            nes2_rom_size(self.data[4], self.data[9] & 0b1111, 16384)
+        } else {
+            self.data[4] as usize * 16384
+        }
     }
 
     pub fn get_chr_rom_size(&self) -> usize {
-        self.data[5] as usize * 8192
+        if self.is_nes2() {
+            nes2_rom_size(self.data[5], self.data[9] >> 4, 8192)
+        } else {
+            self.data[5] as usize * 8192
+        }
     }
 
+    /// PRG RAM, including any battery backed part.  Old headers often leave
+    /// this as 0, so that means 8 KiB.
     pub fn get_prg_ram_size(&self) -> usize {
-        if self.data[8] == 0 {
+        let size = if self.is_nes2() {
+            nes2_ram_size(self.data[10] & 0b1111) + nes2_ram_size(self.data[10] >> 4)
+        } else {
+            self.data[8] as usize * 0x2000
+        };
+        if size == 0 {
             // 8 KiB
             0x2000
         } else {
-            self.data[8] as usize * 0x2000
+            size
         }
     }
 
+    /// CHR RAM, which iNES headers imply by having no CHR ROM.
+    pub fn get_chr_ram_size(&self) -> usize {
+        if self.is_nes2() {
+            nes2_ram_size(self.data[11] & 0b1111) + nes2_ram_size(self.data[11] >> 4)
+        } else if self.get_chr_rom_size() == 0 {
+            0x2000
+        } else {
+            0
+        }
+    }
+
+    pub fn has_battery(&self) -> bool {
+        (self.data[6] & 0b0000_0010) != 0
+    }
+
     pub fn contains_trainer(&self) -> bool {
         // if the trainer bit isn't 0
         (self.data[6] & 0b0000_0100) != 0
     }
 
-    pub fn get_mapper_id(&self) -> u8 {
-        // the mapper is retrieved by combining the first 4 bits of
-        // flag 7 with the first 4 bits of flag 6.
-        (self.data[7] % 0b1111_0000) + (self.data[6] >> 4)
+    pub fn get_mirroring(&self) -> Mirroring {
+        if self.data[6] & 0b0000_1000 != 0 {
+            Mirroring::FourScreen
+        } else if self.data[6] & 0b0000_0001 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        }
+    }
+
+    pub fn is_nes2(&self) -> bool {
+        self.data[7] & 0b0000_1100 == 0b0000_1000
+    }
+
+    /// The region the game was made for, multi-region games are treated as NTSC.
+    pub fn get_region(&self) -> Region {
+        if self.is_nes2() {
+            match self.data[12] & 0b11 {
+                1 => Region::Pal,
+                3 => Region::Dendy,
+                _ => Region::Ntsc,
+            }
+        } else if self.data[9] & 1 != 0 {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        }
+    }
+
+    pub fn get_mapper_id(&self) -> u16 {
+        // the mapper is retrieved by combining the high 4 bits of
+        // flag 7 with the high 4 bits of flag 6.
+        let id = (self.data[7] & 0b1111_0000) as u16 | (self.data[6] >> 4) as u16;
+        if self.is_nes2() {
+            id | ((self.data[8] & 0b1111) as u16) << 8
+        } else {
+            id
+        }
+    }
+
+    /// The NES 2.0 default expansion device, `None` for iNES headers or
+    /// when it's unspecified.
+    pub fn get_expansion_device(&self) -> Option<u8> {
+        match self.data[15] & 0b11_1111 {
+            _ if !self.is_nes2() => None,
+            0 => None,
+            id => Some(id),
+        }
+    }
+
+    /// Only NES 2.0 headers have submappers.
+    pub fn get_submapper_id(&self) -> Option<u8> {
+        if self.is_nes2() {
+            Some(self.data[8] >> 4)
+        } else {
+            None
+        }
+    }
+}
+
+/// NES 2.0 ROM sizes are `units` of `unit_size` with `msb` as the high bits,
+/// or when `msb` is 0xF, `units` is an exponent and multiplier instead.
+fn nes2_rom_size(units: u8, msb: u8, unit_size: usize) -> usize {
+    if msb == 0xF {
+        let exponent = units >> 2;
+        let multiplier = (units & 0b11) as usize * 2 + 1;
+        (1usize << exponent) * multiplier
+    } else {
+        ((msb as usize) << 8 | units as usize) * unit_size
+    }
+}
+
+/// NES 2.0 RAM sizes are 64 << shift bytes, or none for a shift of 0.
+fn nes2_ram_size(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
     }
 }
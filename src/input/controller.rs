@@ -0,0 +1,353 @@
+//! Standard controllers, and the adapters for plugging in four of them.
+
+use crate::input::device::{report_bit, InputDevice, InputState, ShiftCounter};
+use crate::input_macro::InputMacro;
+use crate::nes::Controller;
+use crate::ppu::ppu::Ppu;
+
+/// How many frames turbo buttons are pressed then released for, 15 presses
+/// a second on NTSC.
+pub const DEFAULT_TURBO_RATE: u8 = 2;
+
+/// The buttons on controllers 1 to 4, from what's held, turbo and macros.
+#[derive(Debug, Clone)]
+pub struct Controllers {
+    /// The bits the game sees for each controller, from `update`.
+    /// Order is RIGHT LEFT DOWN UP START SELECT B A
+    bits: [u8; 4],
+
+    /// The buttons held on each controller
+    held: [u8; 4],
+    /// The buttons held with turbo on each controller
+    turbo: [u8; 4],
+    /// How many frames turbo buttons are pressed then released for, by
+    /// controller and `Button::bit_index`
+    turbo_rates: [[u8; 8]; 4],
+    /// Frames since power on, for timing turbo
+    frame: u32,
+    /// The macro playing on each controller, and how many of its frames
+    /// have started
+    macros: [Option<(InputMacro, usize)>; 4],
+    /// The bits the game saw each frame on the controllers being recorded
+    recordings: [Option<Vec<u8>>; 4],
+}
+
+impl Default for Controllers {
+    fn default() -> Self {
+        Self {
+            bits: [0; 4],
+            held: [0; 4],
+            turbo: [0; 4],
+            turbo_rates: [[DEFAULT_TURBO_RATE; 8]; 4],
+            frame: 0,
+            macros: Default::default(),
+            recordings: Default::default(),
+        }
+    }
+}
+
+impl Controllers {
+    /// The buttons the game sees on a controller, see `Button::bit`.
+    pub fn bits(&self, controller: Controller) -> u8 {
+        self.bits[controller.index()]
+    }
+
+    pub fn set_held(&mut self, controller: Controller, bits: u8) {
+        self.held[controller.index()] = bits;
+        self.update();
+    }
+
+    pub fn set_turbo(&mut self, controller: Controller, bits: u8) {
+        self.turbo[controller.index()] = bits;
+        self.update();
+    }
+
+    pub fn set_turbo_rate(&mut self, controller: Controller, bit_index: usize, frames: u8) {
+        self.turbo_rates[controller.index()][bit_index] = frames.max(1);
+    }
+
+    pub fn play_macro(&mut self, controller: Controller, input_macro: InputMacro) {
+        self.macros[controller.index()] = Some((input_macro, 0));
+    }
+
+    pub fn start_recording(&mut self, controller: Controller) {
+        self.recordings[controller.index()] = Some(Vec::new());
+    }
+
+    pub fn stop_recording(&mut self, controller: Controller) -> Option<InputMacro> {
+        self.recordings[controller.index()]
+            .take()
+            .map(InputMacro::new)
+    }
+
+    pub fn is_recording(&self, controller: Controller) -> bool {
+        self.recordings[controller.index()].is_some()
+    }
+
+    /// Move macros and turbo on to the next frame and record the last one.
+    pub fn start_frame(&mut self) {
+        self.frame = self.frame.wrapping_add(1);
+        for slot in &mut self.macros {
+            if let Some((input_macro, started)) = slot {
+                if *started == input_macro.frames().len() {
+                    *slot = None;
+                } else {
+                    *started += 1;
+                }
+            }
+        }
+        self.update();
+
+        for (recording, &bits) in self.recordings.iter_mut().zip(&self.bits) {
+            if let Some(recording) = recording {
+                recording.push(bits);
+            }
+        }
+    }
+
+    /// Work out the bits the game sees from the held buttons, turbo and
+    /// macros.
+    fn update(&mut self) {
+        for (index, bits) in self.bits.iter_mut().enumerate() {
+            *bits = self.held[index];
+            for (bit, &rate) in self.turbo_rates[index].iter().enumerate() {
+                if (self.frame / rate as u32).is_multiple_of(2) {
+                    *bits |= self.turbo[index] & (1 << bit);
+                }
+            }
+            if let Some((input_macro, started)) = &self.macros[index] {
+                if *started > 0 {
+                    *bits |= input_macro.frames()[*started - 1];
+                }
+            }
+        }
+    }
+}
+
+/// A controller plugged into one of the front ports, sending its 8 buttons
+/// on D0.
+#[derive(Debug, Clone)]
+pub struct StandardController {
+    controller: Controller,
+    port: usize,
+    counter: ShiftCounter,
+}
+
+impl StandardController {
+    pub fn new(controller: Controller, port: usize) -> Self {
+        Self {
+            controller,
+            port,
+            counter: ShiftCounter::default(),
+        }
+    }
+}
+
+impl InputDevice for StandardController {
+    fn box_clone(&self) -> Box<dyn InputDevice> {
+        Box::new(self.clone())
+    }
+
+    fn write(&mut self, value: u8) {
+        self.counter.write(value);
+    }
+
+    fn read(&mut self, port: usize, state: &InputState, _ppu: &Ppu) -> u8 {
+        if port != self.port {
+            return 0;
+        }
+        let bits = state.controllers.bits(self.controller);
+        report_bit(bits as u32, 8, self.counter.next(port))
+    }
+}
+
+/// The 24 bits a 4 player adapter sends on a port: the controllers on it
+/// then a signature.
+fn four_player_report(state: &InputState, port: usize, signature: u8) -> u32 {
+    let first = state.controllers.bits(Controller::ALL[port]);
+    let second = state.controllers.bits(Controller::ALL[port + 2]);
+    (first as u32) << 16 | (second as u32) << 8 | signature as u32
+}
+
+/// The NES Four Score or Satellite in both front ports.  Port 1 sends
+/// controllers 1 and 3 and port 2 controllers 2 and 4 on D0.
+#[derive(Debug, Clone, Default)]
+pub struct FourScore {
+    counter: ShiftCounter,
+}
+
+impl InputDevice for FourScore {
+    fn box_clone(&self) -> Box<dyn InputDevice> {
+        Box::new(self.clone())
+    }
+
+    fn write(&mut self, value: u8) {
+        self.counter.write(value);
+    }
+
+    fn read(&mut self, port: usize, state: &InputState, _ppu: &Ppu) -> u8 {
+        let signature = if port == 0 { 0b0001_0000 } else { 0b0010_0000 };
+        let report = four_player_report(state, port, signature);
+        report_bit(report, 24, self.counter.next(port))
+    }
+}
+
+/// Hori's 4 Players Adapter on the Famicom expansion port in 4 player mode.
+/// Like the Four Score but on D1 and with the signatures swapped.
+#[derive(Debug, Clone, Default)]
+pub struct HoriAdapter {
+    counter: ShiftCounter,
+}
+
+impl InputDevice for HoriAdapter {
+    fn box_clone(&self) -> Box<dyn InputDevice> {
+        Box::new(self.clone())
+    }
+
+    fn write(&mut self, value: u8) {
+        self.counter.write(value);
+    }
+
+    fn read(&mut self, port: usize, state: &InputState, _ppu: &Ppu) -> u8 {
+        let signature = if port == 0 { 0b0010_0000 } else { 0b0001_0000 };
+        let report = four_player_report(state, port, signature);
+        report_bit(report, 24, self.counter.next(port)) << 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::device::{InputDevices, InputPorts};
+    use crate::nes::Button;
+
+    const A: u8 = 0b1000_0000;
+    const B: u8 = 0b0100_0000;
+
+    /// The bits the game sees on controller 1 over the next `frames` frames.
+    fn frames(controllers: &mut Controllers, frames: usize) -> Vec<u8> {
+        (0..frames)
+            .map(|_| {
+                controllers.start_frame();
+                controllers.bits(Controller::One)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn turbo() {
+        let mut controllers = Controllers::default();
+        controllers.set_held(Controller::One, B);
+        controllers.set_turbo(Controller::One, A);
+        assert_eq!(controllers.bits(Controller::One), A | B);
+        assert_eq!(frames(&mut controllers, 6), [A | B, B, B, A | B, A | B, B]);
+
+        controllers.set_turbo_rate(Controller::One, Button::A.bit_index(), 1);
+        assert_eq!(frames(&mut controllers, 4), [B, A | B, B, A | B]);
+        assert_eq!(controllers.bits(Controller::Two), 0);
+    }
+
+    #[test]
+    fn macros() {
+        let mut controllers = Controllers::default();
+        controllers.set_held(Controller::One, B);
+        controllers.play_macro(Controller::One, InputMacro::new(vec![A, 0, A]));
+        assert_eq!(
+            controllers.bits(Controller::One),
+            B,
+            "macros start next frame"
+        );
+        assert_eq!(frames(&mut controllers, 4), [A | B, B, A | B, B]);
+    }
+
+    #[test]
+    fn recording() {
+        let mut controllers = Controllers::default();
+        controllers.start_recording(Controller::One);
+        assert!(controllers.is_recording(Controller::One));
+        assert!(!controllers.is_recording(Controller::Two));
+        controllers.set_turbo(Controller::One, A);
+        frames(&mut controllers, 2);
+        controllers.set_held(Controller::One, B);
+        frames(&mut controllers, 2);
+
+        let recording = controllers.stop_recording(Controller::One);
+        assert_eq!(recording, Some(InputMacro::new(vec![A, 0, B, A | B])));
+        assert!(!controllers.is_recording(Controller::One));
+        assert_eq!(controllers.stop_recording(Controller::One), None);
+    }
+
+    /// Strobe, then read `len` bits from `port`.
+    fn read_port(ports: &mut InputPorts, port: usize, len: usize) -> Vec<u8> {
+        let ppu = Ppu::new();
+        ports.write(1);
+        ports.write(0);
+        (0..len).map(|_| ports.read(port, &ppu)).collect()
+    }
+
+    /// Bits from a read for each line `mask` keeps.
+    fn bits(reads: &[u8], mask: u8) -> String {
+        reads
+            .iter()
+            .map(|read| if read & mask != 0 { '1' } else { '0' })
+            .collect()
+    }
+
+    /// Controllers 1 to 4 holding A, B, Select and Start.
+    fn four_players(kind: InputDevices) -> InputPorts {
+        let mut ports = InputPorts::default();
+        ports.plug_in(kind);
+        let buttons = [Button::A, Button::B, Button::Select, Button::Start];
+        for (&controller, button) in Controller::ALL.iter().zip(&buttons) {
+            ports.state.controllers.set_held(controller, button.bit());
+        }
+        ports
+    }
+
+    #[test]
+    fn standard_controllers() {
+        let mut ports = four_players(InputDevices::Controllers);
+        let reads = read_port(&mut ports, 0, 10);
+        assert!(reads.iter().all(|read| read & 0xE0 == 0x40), "open bus");
+        assert_eq!(bits(&reads, 1), "1000000011");
+        assert_eq!(bits(&read_port(&mut ports, 1, 10), 1), "0100000011");
+
+        // the strobe holds the shift register at A
+        ports.write(1);
+        let ppu = Ppu::new();
+        assert_eq!(ports.read(0, &ppu) & 1, 1);
+        assert_eq!(ports.read(0, &ppu) & 1, 1);
+    }
+
+    #[test]
+    fn four_score() {
+        let mut ports = four_players(InputDevices::FourScore);
+        let port_1 = read_port(&mut ports, 0, 26);
+        assert_eq!(
+            bits(&port_1, 1),
+            "10000000_00100000_00010000_11".replace('_', "")
+        );
+        assert_eq!(bits(&port_1, 0b10), "0".repeat(26));
+        let port_2 = read_port(&mut ports, 1, 24);
+        assert_eq!(
+            bits(&port_2, 1),
+            "01000000_00010000_00100000".replace('_', "")
+        );
+    }
+
+    #[test]
+    fn hori_adapter() {
+        let mut ports = four_players(InputDevices::Hori);
+        let port_1 = read_port(&mut ports, 0, 24);
+        assert_eq!(
+            bits(&port_1, 0b10),
+            "10000000_00100000_00100000".replace('_', "")
+        );
+        assert_eq!(bits(&port_1, 1), "0".repeat(24));
+        let port_2 = read_port(&mut ports, 1, 24);
+        assert_eq!(
+            bits(&port_2, 0b10),
+            "01000000_00010000_00010000".replace('_', "")
+        );
+    }
+}
@@ -0,0 +1,264 @@
+//! Whatever is plugged into the two controller ports and the Famicom
+//! expansion port, which the CPU reads through $4016 and $4017.
+//!
+//! Each device drives some of the data lines D0-D4 of those reads, and the
+//! console ORs together what they all drive.  Devices only decide how the
+//! player's input is sent to the console, the input itself is fed into an
+//! `InputState` by the frontend whichever devices are plugged in.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::input::controller::{Controllers, FourScore, HoriAdapter, StandardController};
+use crate::input::keyboard::{FamilyKeyboard, KeyboardState};
+use crate::input::power_pad::{FamilyTrainer, PowerPad, PowerPadState};
+use crate::input::vaus::{Vaus, VausState};
+use crate::input::zapper::{Zapper, ZapperState};
+use crate::nes::Controller;
+use crate::ppu::ppu::Ppu;
+
+pub trait InputDevice: fmt::Debug {
+    /// A copy of the device, for save states.
+    fn box_clone(&self) -> Box<dyn InputDevice>;
+
+    /// A write to $4016.  Bit 0 is the strobe, which holds shift registers
+    /// at their first bit while it's high.
+    fn write(&mut self, value: u8);
+    /// The data lines this device drives in a read of $4016 for port 0 or
+    /// $4017 for port 1.
+    fn read(&mut self, port: usize, state: &InputState, ppu: &Ppu) -> u8;
+}
+
+/// What the player is doing with every kind of device.
+#[derive(Debug, Clone, Default)]
+pub struct InputState {
+    pub controllers: Controllers,
+    pub zapper: ZapperState,
+    pub vaus: VausState,
+    pub power_pad: PowerPadState,
+    pub keyboard: KeyboardState,
+}
+
+/// The combinations of devices that can be plugged in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputDevices {
+    /// Controllers 1 and 2 plugged straight in
+    #[default]
+    Controllers,
+    /// The NES Four Score or Satellite for controllers 1 to 4
+    FourScore,
+    /// Hori's 4 Players Adapter on the Famicom expansion port, in 4 player
+    /// mode, for controllers 1 to 4
+    Hori,
+    /// Controller 1 and a Zapper in port 2
+    Zapper,
+    /// Controller 1 and the NES Arkanoid controller in port 2
+    Arkanoid,
+    /// Controllers 1 and 2 and the Famicom Arkanoid controller on the
+    /// expansion port
+    ArkanoidFamicom,
+    /// Controller 1 and a Power Pad in port 2
+    PowerPad,
+    /// Controllers 1 and 2 and the Family Trainer mat on the expansion port
+    FamilyTrainer,
+    /// Controllers 1 and 2 and the Family BASIC keyboard on the expansion
+    /// port
+    FamilyKeyboard,
+}
+
+impl InputDevices {
+    pub const ALL: [InputDevices; 9] = [
+        InputDevices::Controllers,
+        InputDevices::FourScore,
+        InputDevices::Hori,
+        InputDevices::Zapper,
+        InputDevices::Arkanoid,
+        InputDevices::ArkanoidFamicom,
+        InputDevices::PowerPad,
+        InputDevices::FamilyTrainer,
+        InputDevices::FamilyKeyboard,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            InputDevices::Controllers => "controllers",
+            InputDevices::FourScore => "four-score",
+            InputDevices::Hori => "hori",
+            InputDevices::Zapper => "zapper",
+            InputDevices::Arkanoid => "arkanoid",
+            InputDevices::ArkanoidFamicom => "arkanoid-famicom",
+            InputDevices::PowerPad => "power-pad",
+            InputDevices::FamilyTrainer => "family-trainer",
+            InputDevices::FamilyKeyboard => "family-keyboard",
+        }
+    }
+
+    /// The devices for an NES 2.0 default expansion device number, `None`
+    /// if it's unspecified or not emulated.
+    pub fn from_expansion_id(id: u8) -> Option<Self> {
+        match id {
+            0x01 => Some(InputDevices::Controllers),
+            0x02 => Some(InputDevices::FourScore),
+            // the Famicom 4 player adapters
+            0x03 => Some(InputDevices::Hori),
+            0x08 => Some(InputDevices::Zapper),
+            // sides A and B of the mat are the same to the console
+            0x0B | 0x0C => Some(InputDevices::PowerPad),
+            0x0D | 0x0E => Some(InputDevices::FamilyTrainer),
+            0x0F => Some(InputDevices::Arkanoid),
+            0x10 => Some(InputDevices::ArkanoidFamicom),
+            // the data recorder that comes with it isn't emulated
+            0x23 => Some(InputDevices::FamilyKeyboard),
+            _ => None,
+        }
+    }
+
+    fn devices(self) -> Vec<Box<dyn InputDevice>> {
+        match self {
+            InputDevices::Controllers => vec![
+                Box::new(StandardController::new(Controller::One, 0)),
+                Box::new(StandardController::new(Controller::Two, 1)),
+            ],
+            InputDevices::FourScore => vec![Box::new(FourScore::default())],
+            InputDevices::Hori => vec![Box::new(HoriAdapter::default())],
+            InputDevices::Zapper => vec![
+                Box::new(StandardController::new(Controller::One, 0)),
+                Box::new(Zapper::new(1)),
+            ],
+            InputDevices::Arkanoid => vec![
+                Box::new(StandardController::new(Controller::One, 0)),
+                Box::new(Vaus::nes()),
+            ],
+            InputDevices::ArkanoidFamicom => vec![
+                Box::new(StandardController::new(Controller::One, 0)),
+                Box::new(StandardController::new(Controller::Two, 1)),
+                Box::new(Vaus::famicom()),
+            ],
+            InputDevices::PowerPad => vec![
+                Box::new(StandardController::new(Controller::One, 0)),
+                Box::new(PowerPad::default()),
+            ],
+            InputDevices::FamilyTrainer => vec![
+                Box::new(StandardController::new(Controller::One, 0)),
+                Box::new(StandardController::new(Controller::Two, 1)),
+                Box::new(FamilyTrainer::default()),
+            ],
+            InputDevices::FamilyKeyboard => vec![
+                Box::new(StandardController::new(Controller::One, 0)),
+                Box::new(StandardController::new(Controller::Two, 1)),
+                Box::new(FamilyKeyboard::default()),
+            ],
+        }
+    }
+}
+
+impl FromStr for InputDevices {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        InputDevices::ALL
+            .iter()
+            .copied()
+            .find(|devices| devices.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("Unknown input device {:?}", s))
+    }
+}
+
+/// The devices plugged in and the input they send.
+#[derive(Debug)]
+pub struct InputPorts {
+    kind: InputDevices,
+    devices: Vec<Box<dyn InputDevice>>,
+    pub state: InputState,
+}
+
+impl Clone for InputPorts {
+    fn clone(&self) -> Self {
+        Self {
+            kind: self.kind,
+            devices: self
+                .devices
+                .iter()
+                .map(|device| device.box_clone())
+                .collect(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl Default for InputPorts {
+    fn default() -> Self {
+        Self {
+            kind: InputDevices::default(),
+            devices: InputDevices::default().devices(),
+            state: InputState::default(),
+        }
+    }
+}
+
+impl InputPorts {
+    pub fn kind(&self) -> InputDevices {
+        self.kind
+    }
+
+    /// Unplug everything and plug in `kind`.  The input state is kept.
+    pub fn plug_in(&mut self, kind: InputDevices) {
+        self.kind = kind;
+        self.devices = kind.devices();
+    }
+
+    pub fn write(&mut self, value: u8) {
+        for device in &mut self.devices {
+            device.write(value);
+        }
+    }
+
+    /// Read $4016 for port 0 or $4017 for port 1.
+    pub fn read(&mut self, port: usize, ppu: &Ppu) -> u8 {
+        // the top 3 bits aren't connected, and we're reading from an address
+        // with 0x40 set so it's still on the bus
+        let state = &self.state;
+        let data = self
+            .devices
+            .iter_mut()
+            .fold(0, |data, device| data | device.read(port, state, ppu));
+        0x40 | (data & 0b1_1111)
+    }
+}
+
+/// Counts the bits read from each port since the strobe was last high, for
+/// devices that send a report through a shift register.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShiftCounter {
+    strobe: bool,
+    reads: [u8; 2],
+}
+
+impl ShiftCounter {
+    pub fn write(&mut self, value: u8) {
+        self.strobe = value & 1 != 0;
+        if self.strobe {
+            self.reads = [0; 2];
+        }
+    }
+
+    /// The index of the bit to read next from `port`, moving on to the one
+    /// after unless the strobe is high.
+    pub fn next(&mut self, port: usize) -> u8 {
+        let read = self.reads[port];
+        if !self.strobe {
+            self.reads[port] = read.saturating_add(1);
+        }
+        read
+    }
+}
+
+/// Bit `index` of a `len` bit report sent most significant bit first.
+/// Official devices send 1s once the whole report has been read.
+pub fn report_bit(report: u32, len: u8, index: u8) -> u8 {
+    if index >= len {
+        1
+    } else {
+        ((report >> (len - 1 - index)) & 1) as u8
+    }
+}
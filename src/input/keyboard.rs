@@ -0,0 +1,186 @@
+//! The Family BASIC keyboard on the Famicom expansion port.
+//!
+//! Its 72 keys are a matrix of 9 rows of 2 columns of 4 keys.  Writes to
+//! $4016 scan it: bit 2 enables the keyboard, bit 1 picks the column and
+//! bit 0 goes back to the first row.  Switching from column 1 back to column
+//! 0 moves on to the next row.  $4017 reads the 4 keys of the current row
+//! and column on D1-D4, low when pressed.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::input::device::{InputDevice, InputState};
+use crate::ppu::ppu::Ppu;
+
+const ROWS: usize = 9;
+
+/// Key names by row, column and data line from D1 to D4.
+const KEY_NAMES: [[[&str; 4]; 2]; ROWS] = [
+    [
+        ["f8", "return", "[", "]"],
+        ["kana", "right-shift", "yen", "stop"],
+    ],
+    [["f7", "@", ":", ";"], ["_", "/", "-", "^"]],
+    [["f6", "o", "l", "k"], [".", "comma", "p", "0"]],
+    [["f5", "i", "u", "j"], ["m", "n", "9", "8"]],
+    [["f4", "y", "g", "h"], ["b", "v", "7", "6"]],
+    [["f3", "t", "r", "d"], ["f", "c", "5", "4"]],
+    [["f2", "w", "s", "a"], ["x", "z", "e", "3"]],
+    [["f1", "esc", "q", "ctr"], ["left-shift", "grph", "1", "2"]],
+    [
+        ["clr-home", "up", "right", "left"],
+        ["down", "space", "del", "ins"],
+    ],
+];
+
+/// A key on the keyboard, by its place in the matrix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FamilyKey {
+    /// `row * 8 + column * 4 + line`, where line 0 is D1
+    index: u8,
+}
+
+impl FamilyKey {
+    /// All 72 keys in matrix order.
+    pub fn all() -> impl Iterator<Item = FamilyKey> {
+        (0..ROWS as u8 * 8).map(|index| FamilyKey { index })
+    }
+
+    /// The key's label in lower case, with `-` for spaces and `comma` for
+    /// `,` so keys can be listed with commas.
+    pub fn name(self) -> &'static str {
+        let index = self.index as usize;
+        KEY_NAMES[index / 8][index / 4 % 2][index % 4]
+    }
+}
+
+impl FromStr for FamilyKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        FamilyKey::all()
+            .find(|key| key.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("Unknown Family BASIC key {:?}", s))
+    }
+}
+
+impl fmt::Display for FamilyKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The keys held down.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyboardState {
+    /// Bit `FamilyKey::index` for each key
+    pressed: u128,
+}
+
+impl KeyboardState {
+    pub fn set(&mut self, key: FamilyKey, pressed: bool) {
+        if pressed {
+            self.pressed |= 1 << key.index;
+        } else {
+            self.pressed &= !(1 << key.index);
+        }
+    }
+
+    /// The 4 keys at a row and column, D1 in bit 0.
+    fn keys(self, row: usize, column: usize) -> u8 {
+        ((self.pressed >> (row * 8 + column * 4)) & 0b1111) as u8
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FamilyKeyboard {
+    enabled: bool,
+    row: usize,
+    column: usize,
+}
+
+impl InputDevice for FamilyKeyboard {
+    fn box_clone(&self) -> Box<dyn InputDevice> {
+        Box::new(self.clone())
+    }
+
+    fn write(&mut self, value: u8) {
+        self.enabled = value & 0b100 != 0;
+        let column = (value >> 1 & 1) as usize;
+        if value & 1 != 0 {
+            self.row = 0;
+        } else if self.enabled && self.column == 1 && column == 0 {
+            self.row += 1;
+        }
+        self.column = column;
+    }
+
+    fn read(&mut self, port: usize, state: &InputState, _ppu: &Ppu) -> u8 {
+        if port != 1 || !self.enabled {
+            return 0;
+        }
+        // past the last row nothing is pressed
+        let keys = if self.row < ROWS {
+            state.keyboard.keys(self.row, self.column)
+        } else {
+            0
+        };
+        (!keys & 0b1111) << 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::device::{InputDevices, InputPorts};
+
+    /// D1-D4 for each row and column, scanned the way Family BASIC does.
+    fn scan(ports: &mut InputPorts) -> Vec<u8> {
+        let ppu = Ppu::new();
+        ports.write(0b101);
+        let mut reads = Vec::new();
+        for _ in 0..ROWS + 1 {
+            ports.write(0b100);
+            reads.push(ports.read(1, &ppu) >> 1 & 0b1111);
+            ports.write(0b110);
+            reads.push(ports.read(1, &ppu) >> 1 & 0b1111);
+        }
+        reads
+    }
+
+    #[test]
+    fn key_names() {
+        assert_eq!(FamilyKey::all().count(), 72);
+        for key in FamilyKey::all() {
+            assert_eq!(key.name().parse(), Ok(key));
+        }
+        assert_eq!(
+            "Left-Shift".parse::<FamilyKey>().unwrap().to_string(),
+            "left-shift"
+        );
+        assert!("shift".parse::<FamilyKey>().is_err());
+    }
+
+    #[test]
+    fn matrix() {
+        let mut ports = InputPorts::default();
+        ports.plug_in(InputDevices::FamilyKeyboard);
+        let mut keyboard = KeyboardState::default();
+        for &key in &["a", "3", "f8", "ins", "q"] {
+            keyboard.set(key.parse().unwrap(), true);
+        }
+        keyboard.set("q".parse().unwrap(), false);
+        ports.state.keyboard = keyboard;
+
+        let mut expected = vec![0b1111; 20];
+        expected[0] = 0b1110; // f8
+        expected[12] = 0b0111; // a
+        expected[13] = 0b0111; // 3
+        expected[17] = 0b0111; // ins
+        assert_eq!(scan(&mut ports), expected);
+
+        // nothing reads as pressed while the keyboard is disabled
+        ports.write(0);
+        assert_eq!(ports.read(1, &Ppu::new()) & 0b1_1110, 0);
+    }
+}
@@ -0,0 +1,6 @@
+pub mod controller;
+pub mod device;
+pub mod keyboard;
+pub mod power_pad;
+pub mod vaus;
+pub mod zapper;
@@ -0,0 +1,161 @@
+//! Bandai's exercise mat, sold as the Power Pad for the NES and the Family
+//! Trainer for the Famicom.  It has 12 buttons in 3 rows of 4:
+//!
+//! ```text
+//!  1  2  3  4
+//!  5  6  7  8
+//!  9 10 11 12
+//! ```
+//!
+//! numbered as on side A.  Side B only has 8 of them labelled, and games
+//! for it just expect the mat to be turned over.
+
+use crate::input::device::{report_bit, InputDevice, InputState, ShiftCounter};
+use crate::ppu::ppu::Ppu;
+
+/// The order the Power Pad sends its buttons in on D3 and D4.
+const D3_BUTTONS: [u8; 8] = [2, 1, 5, 9, 6, 10, 11, 7];
+const D4_BUTTONS: [u8; 4] = [4, 3, 12, 8];
+
+/// The buttons pressed on the mat, bit `n - 1` for button `n`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PowerPadState {
+    pub buttons: u16,
+}
+
+impl PowerPadState {
+    pub fn is_pressed(self, button: u8) -> bool {
+        (1..=12).contains(&button) && self.buttons & 1 << (button - 1) != 0
+    }
+
+    /// The buttons in `order` as a report, the first most significant.
+    fn report(self, order: &[u8]) -> u32 {
+        order.iter().fold(0, |report, &button| {
+            report << 1 | self.is_pressed(button) as u32
+        })
+    }
+}
+
+/// The Power Pad in port 2.  It has two shift registers, sending 8 buttons
+/// on D3 and 4 on D4.
+#[derive(Debug, Clone, Default)]
+pub struct PowerPad {
+    counter: ShiftCounter,
+}
+
+impl InputDevice for PowerPad {
+    fn box_clone(&self) -> Box<dyn InputDevice> {
+        Box::new(self.clone())
+    }
+
+    fn write(&mut self, value: u8) {
+        self.counter.write(value);
+    }
+
+    fn read(&mut self, port: usize, state: &InputState, _ppu: &Ppu) -> u8 {
+        if port != 1 {
+            return 0;
+        }
+        let pad = state.power_pad;
+        let index = self.counter.next(port);
+        let d3 = report_bit(pad.report(&D3_BUTTONS), 8, index);
+        let d4 = report_bit(pad.report(&D4_BUTTONS), 4, index);
+        d4 << 4 | d3 << 3
+    }
+}
+
+/// The Family Trainer on the expansion port.  Instead of a shift register,
+/// writes to $4016 pick rows with bits 2, 1 and 0 low for the top, middle
+/// and bottom rows, and $4017 reads their buttons on D1-D4, low when
+/// pressed.  D4 is the leftmost button of a row.
+#[derive(Debug, Clone, Default)]
+pub struct FamilyTrainer {
+    /// The last write to $4016
+    rows: u8,
+}
+
+impl InputDevice for FamilyTrainer {
+    fn box_clone(&self) -> Box<dyn InputDevice> {
+        Box::new(self.clone())
+    }
+
+    fn write(&mut self, value: u8) {
+        self.rows = value;
+    }
+
+    fn read(&mut self, port: usize, state: &InputState, _ppu: &Ppu) -> u8 {
+        if port != 1 {
+            return 0;
+        }
+        let mut pressed = 0;
+        for row in 0..3 {
+            if self.rows & 0b100 >> row == 0 {
+                // the row's 4 buttons with the leftmost in bit 3
+                let buttons = (state.power_pad.buttons >> (row * 4)) & 0b1111;
+                pressed |= buttons.reverse_bits() >> 12;
+            }
+        }
+        (!(pressed as u8) << 1) & 0b1_1110
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::device::{InputDevices, InputPorts};
+
+    /// Buttons 2, 4 and 12 held.
+    fn ports(kind: InputDevices) -> InputPorts {
+        let mut ports = InputPorts::default();
+        ports.plug_in(kind);
+        ports.state.power_pad.buttons = 0b1000_0000_1010;
+        ports
+    }
+
+    #[test]
+    fn power_pad() {
+        let mut ports = ports(InputDevices::PowerPad);
+        let ppu = Ppu::new();
+        ports.write(1);
+        ports.write(0);
+        let reads: Vec<u8> = (0..10).map(|_| ports.read(1, &ppu)).collect();
+        let line = |bit: u8| -> String {
+            reads
+                .iter()
+                .map(|read| if read >> bit & 1 != 0 { '1' } else { '0' })
+                .collect()
+        };
+        // buttons 2, 1, 5, 9, 6, 10, 11, 7 on D3 and 4, 3, 12, 8 on D4
+        assert_eq!(line(3), "1000000011");
+        assert_eq!(line(4), "1010111111");
+        assert_eq!(ports.read(0, &ppu) & 0b1_1000, 0);
+    }
+
+    #[test]
+    fn family_trainer() {
+        let mut ports = ports(InputDevices::FamilyTrainer);
+        let ppu = Ppu::new();
+        let mut row = |select: u8| {
+            ports.write(select);
+            ports.read(1, &ppu) & 0b1_1110
+        };
+        // pressed buttons read low, with the leftmost on D4
+        assert_eq!(row(0b011), 0b1_0100);
+        assert_eq!(row(0b101), 0b1_1110);
+        assert_eq!(row(0b110), 0b1_1100);
+        assert_eq!(row(0b111), 0b1_1110);
+        assert_eq!(row(0b010), 0b1_0100 & 0b1_1100);
+    }
+
+    #[test]
+    fn is_pressed() {
+        let pad = PowerPadState {
+            buttons: 0b1000_0000_0001,
+        };
+        assert!(pad.is_pressed(1));
+        assert!(pad.is_pressed(12));
+        assert!(!pad.is_pressed(2));
+        assert!(!pad.is_pressed(0));
+        assert!(!pad.is_pressed(13));
+    }
+}
@@ -0,0 +1,136 @@
+//! Taito's Vaus controller for Arkanoid: a knob and a button.
+//!
+//! The knob turns a potentiometer whose 8 bit reading is sent through a
+//! shift register, most significant bit first and inverted.  The NES version
+//! plugs into port 2 and sends it on D3 with the button on D4.  The Famicom
+//! version plugs into the expansion port and sends it on D1 of $4017, with
+//! the button on D1 of $4016.
+
+use crate::input::device::{report_bit, InputDevice, InputState, ShiftCounter};
+use crate::ppu::ppu::Ppu;
+
+/// The knob readings Arkanoid expects, from the paddle at the far left to
+/// the far right.
+pub const MIN_POSITION: u8 = 98;
+pub const MAX_POSITION: u8 = 242;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VausState {
+    /// The knob's reading, see `MIN_POSITION` and `MAX_POSITION`
+    pub position: u8,
+    pub button: bool,
+}
+
+impl Default for VausState {
+    fn default() -> Self {
+        Self {
+            position: MIN_POSITION,
+            button: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Vaus {
+    /// The Famicom version rather than the NES one
+    famicom: bool,
+    counter: ShiftCounter,
+}
+
+impl Vaus {
+    pub fn nes() -> Self {
+        Self {
+            famicom: false,
+            counter: ShiftCounter::default(),
+        }
+    }
+
+    pub fn famicom() -> Self {
+        Self {
+            famicom: true,
+            counter: ShiftCounter::default(),
+        }
+    }
+}
+
+impl InputDevice for Vaus {
+    fn box_clone(&self) -> Box<dyn InputDevice> {
+        Box::new(self.clone())
+    }
+
+    fn write(&mut self, value: u8) {
+        self.counter.write(value);
+    }
+
+    fn read(&mut self, port: usize, state: &InputState, _ppu: &Ppu) -> u8 {
+        let vaus = state.vaus;
+        match (self.famicom, port) {
+            (false, 1) => {
+                let knob = report_bit(vaus.position as u32, 8, self.counter.next(port)) ^ 1;
+                (vaus.button as u8) << 4 | knob << 3
+            }
+            (true, 0) => (vaus.button as u8) << 1,
+            (true, _) => {
+                let knob = report_bit(vaus.position as u32, 8, self.counter.next(port)) ^ 1;
+                knob << 1
+            }
+            (false, _) => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::device::{InputDevices, InputPorts};
+
+    fn plug_in(kind: InputDevices, position: u8, button: bool) -> InputPorts {
+        let mut ports = InputPorts::default();
+        ports.plug_in(kind);
+        ports.state.vaus = VausState { position, button };
+        ports.write(1);
+        ports.write(0);
+        ports
+    }
+
+    /// Bit `line` of 10 reads of `port`.
+    fn bits(ports: &mut InputPorts, port: usize, line: u8) -> String {
+        let ppu = Ppu::new();
+        (0..10)
+            .map(|_| {
+                if ports.read(port, &ppu) >> line & 1 != 0 {
+                    '1'
+                } else {
+                    '0'
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nes() {
+        let mut ports = plug_in(InputDevices::Arkanoid, 0b1010_1100, true);
+        // inverted, and 0 once the reading is sent
+        assert_eq!(bits(&mut ports, 1, 3), "0101001100");
+        assert_eq!(bits(&mut ports, 1, 4), "1111111111");
+
+        let mut ports = plug_in(InputDevices::Arkanoid, MAX_POSITION, false);
+        assert_eq!(bits(&mut ports, 1, 4), "0000000000");
+        assert_eq!(bits(&mut ports, 0, 3), "0000000000");
+    }
+
+    #[test]
+    fn famicom() {
+        let mut ports = plug_in(InputDevices::ArkanoidFamicom, 0b1010_1100, true);
+        assert_eq!(bits(&mut ports, 1, 1), "0101001100");
+        assert_eq!(bits(&mut ports, 0, 1), "1111111111");
+        // the controllers are still plugged in
+        ports
+            .state
+            .controllers
+            .set_held(crate::nes::Controller::Two, 0xFF);
+        ports.write(1);
+        ports.write(0);
+        assert_eq!(bits(&mut ports, 1, 0), "1111111111");
+    }
+}
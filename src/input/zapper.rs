@@ -0,0 +1,140 @@
+//! The Zapper light gun.  D3 is the light sensor and D4 the trigger.
+//!
+//! The sensor sees a small patch of the screen, and only notices light for
+//! a short while after the beam draws it.  Games flash the targets white for
+//! a frame and read the sensor as the frame is drawn, so it's worked out
+//! from the pixels around the aimed point drawn over the last few scanlines.
+
+use crate::input::device::{InputDevice, InputState};
+use crate::ppu::ppu::Ppu;
+
+/// How many pixels around the aimed point the sensor sees in each direction.
+const SENSE_RADIUS: usize = 2;
+/// How many scanlines the sensor stays lit for after the beam passes.
+const SENSE_SCANLINES: usize = 20;
+/// The brightness out of 255 that counts as light.
+const LIGHT_THRESHOLD: u32 = 0x80;
+
+/// Where the Zapper is pointed and whether the trigger is pulled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ZapperState {
+    /// The screen pixel aimed at, `None` when pointed away from the screen
+    pub aim: Option<(u8, u8)>,
+    pub trigger: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Zapper {
+    port: usize,
+}
+
+impl Zapper {
+    pub fn new(port: usize) -> Self {
+        Self { port }
+    }
+}
+
+impl InputDevice for Zapper {
+    fn box_clone(&self) -> Box<dyn InputDevice> {
+        Box::new(self.clone())
+    }
+
+    fn write(&mut self, _value: u8) {}
+
+    fn read(&mut self, port: usize, state: &InputState, ppu: &Ppu) -> u8 {
+        if port != self.port {
+            return 0;
+        }
+        let zapper = state.zapper;
+        let mut data = 0;
+        if zapper.trigger {
+            data |= 0b1_0000;
+        }
+        // the sensor reads 0 when it sees light
+        if !zapper.aim.is_some_and(|aim| sees_light(aim, ppu)) {
+            data |= 0b0_1000;
+        }
+        data
+    }
+}
+
+/// Whether any bright pixels around `aim` were drawn recently.
+fn sees_light((x, y): (u8, u8), ppu: &Ppu) -> bool {
+    let (x, y) = (x as usize, y as usize);
+    let scanline = ppu.scanline() as usize;
+    (y.saturating_sub(SENSE_RADIUS)..=y + SENSE_RADIUS)
+        .filter(|&row| row <= scanline && scanline - row < SENSE_SCANLINES)
+        .any(|row| {
+            (x.saturating_sub(SENSE_RADIUS)..=x + SENSE_RADIUS).any(|col| {
+                ppu.drawn_pixel(col, row).is_some_and(|[r, g, b]| {
+                    (299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000 >= LIGHT_THRESHOLD
+                })
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::assembler::assemble;
+    use crate::input::device::InputDevices;
+    use crate::nes::Nes;
+
+    /// A console with a Zapper, showing a screen of one colour with the beam
+    /// halfway down it.
+    fn nes(colour: u8) -> Nes {
+        let mut nes = assemble(&format!(
+            "
+            reset:  lda #$3F
+                    sta $2006
+                    lda #0
+                    sta $2006
+                    lda #${:02X}
+                    sta $2007       ; the backdrop colour
+                    lda #0
+                    sta $2006       ; and point VRAM away from the palette
+                    sta $2006
+            done:   jmp done
+            ",
+            colour
+        ))
+        .unwrap()
+        .boot();
+        nes.set_input_devices(InputDevices::Zapper);
+        nes.run_frame();
+        nes.run_frame();
+        while nes.ppu.scanline() != 120 {
+            nes.step();
+        }
+        nes
+    }
+
+    /// D3 and D4 of a read of $4017.
+    fn read(nes: &mut Nes, aim: Option<(u8, u8)>, trigger: bool) -> u8 {
+        nes.set_zapper(aim, trigger);
+        nes.cpu_read(0x4017) & 0b1_1000
+    }
+
+    #[test]
+    fn light_sensor() {
+        let mut white = nes(0x30);
+        assert_eq!(read(&mut white, Some((128, 110)), false), 0);
+        // the beam hasn't got there yet, or passed too long ago
+        assert_eq!(read(&mut white, Some((128, 200)), false), 0b0_1000);
+        assert_eq!(read(&mut white, Some((128, 20)), false), 0b0_1000);
+        assert_eq!(read(&mut white, None, false), 0b0_1000);
+
+        let mut black = nes(0x0F);
+        assert_eq!(read(&mut black, Some((128, 110)), false), 0b0_1000);
+    }
+
+    #[test]
+    fn trigger() {
+        let mut nes = nes(0x0F);
+        assert_eq!(read(&mut nes, None, true), 0b1_1000);
+        assert_eq!(
+            nes.cpu_read(0x4016) & 0b1_1000,
+            0,
+            "only port 2 has the Zapper"
+        );
+    }
+}
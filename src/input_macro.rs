@@ -0,0 +1,158 @@
+//! Recorded controller input to replay, one controller's buttons per frame.
+//!
+//! A macro file has a line per frame with the buttons held, `-` for none,
+//! and an optional `*N` to repeat the line for N frames:
+//!
+//! ```text
+//! # jump while running right
+//! right b    *10
+//! right b a  *4
+//! -
+//! ```
+//!
+//! Blank lines and everything after a `#` are ignored.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::nes::Button;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputMacro {
+    /// The controller bits for each frame, see `Button::bit`
+    frames: Vec<u8>,
+}
+
+impl InputMacro {
+    pub fn new(frames: Vec<u8>) -> Self {
+        Self { frames }
+    }
+
+    pub fn load_from_file(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read macro {:?}: {}", path, e))?;
+        text.parse()
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save_to_file(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        fs::write(path, self.to_string())
+            .map_err(|e| format!("Failed to write macro {:?}: {}", path, e))
+    }
+
+    pub fn frames(&self) -> &[u8] {
+        &self.frames
+    }
+}
+
+impl FromStr for InputMacro {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut frames = Vec::new();
+        for (number, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (bits, repeat) =
+                parse_line(line).map_err(|e| format!("Line {}: {}", number + 1, e))?;
+            frames.extend((0..repeat).map(|_| bits));
+        }
+        Ok(Self { frames })
+    }
+}
+
+fn parse_line(line: &str) -> Result<(u8, usize), String> {
+    let mut bits = 0;
+    let mut repeat = 1;
+    let words = line
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|word| !word.is_empty());
+    for word in words {
+        if let Some(count) = word.strip_prefix('*') {
+            repeat = count
+                .parse()
+                .map_err(|e| format!("Invalid repeat count {:?}: {}", word, e))?;
+        } else if word != "-" {
+            bits |= word.parse::<Button>()?.bit();
+        }
+    }
+    Ok((bits, repeat))
+}
+
+impl fmt::Display for InputMacro {
+    /// Runs of the same buttons are written as one line with a repeat count.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut frames = self.frames.iter().peekable();
+        while let Some(&bits) = frames.next() {
+            let mut repeat = 1;
+            while frames.next_if_eq(&&bits).is_some() {
+                repeat += 1;
+            }
+
+            let names: Vec<&str> = Button::ALL
+                .iter()
+                .filter(|button| bits & button.bit() != 0)
+                .map(|button| button.name())
+                .collect();
+            if names.is_empty() {
+                write!(f, "-")?;
+            } else {
+                write!(f, "{}", names.join(" "))?;
+            }
+            if repeat > 1 {
+                write!(f, " *{}", repeat)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let input_macro: InputMacro = "
+            # jump while running right
+            right b    *3
+            Right,B,A  # comment
+            -
+            "
+        .parse()
+        .unwrap();
+        let run = Button::Right.bit() | Button::B.bit();
+        assert_eq!(
+            input_macro.frames(),
+            [run, run, run, run | Button::A.bit(), 0]
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let a = Button::A.bit();
+        let input_macro = InputMacro::new(vec![0, a, a, 0xFF, 0, 0, 0]);
+        let text = input_macro.to_string();
+        assert_eq!(text, "-\na *2\na b select start up down left right\n- *3\n");
+        assert_eq!(text.parse(), Ok(input_macro));
+    }
+
+    #[test]
+    fn errors() {
+        assert!("a *x"
+            .parse::<InputMacro>()
+            .unwrap_err()
+            .starts_with("Line 1: "));
+        assert!("-\njump"
+            .parse::<InputMacro>()
+            .unwrap_err()
+            .starts_with("Line 2: "));
+    }
+}
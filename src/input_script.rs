@@ -0,0 +1,683 @@
+//! Scripted controller input for headless runs.
+//!
+//! A script is a text file with one change per line:
+//!
+//! ```text
+//! # frame  controller  buttons
+//! 60       1           start
+//! 62       1
+//! 120      1           right a
+//! 300      2           left,b
+//! ```
+//!
+//! From the start of `frame`, numbered from 0, the controller holds exactly
+//! the listed buttons until a later line changes it.  A line with no buttons
+//! releases everything.  Blank lines and everything after a `#` are ignored.
+//! Controllers 3 and 4 need a 4 player adapter from `--input-device`.
+//!
+//! Buttons like `turbo-a` are held with turbo, and `macro FILE` plays an
+//! `input_macro` file, relative to the script, on top of the held buttons.
+//! `turbo-rate N` makes turbo buttons press and release every N frames, or
+//! e.g. `turbo-rate-b N` for one button.  `record` records what the game
+//! sees on a controller until `stop-recording FILE` saves it as a macro:
+//!
+//! ```text
+//! 400      1           right turbo-b
+//! 450      1           turbo-rate-b 4
+//! 500      1           macro jump.txt
+//! 500      2           record
+//! 560      2           stop-recording p2.txt
+//! ```
+//!
+//! With a Zapper plugged in, `zapper X Y` aims it at a screen pixel and
+//! `zapper X Y fire` pulls the trigger there too, until a later `zapper`
+//! line.  `zapper off` points it away from the screen:
+//!
+//! ```text
+//! 600      zapper      128 96 fire
+//! 602      zapper      off
+//! ```
+//!
+//! The other devices work the same way.  `vaus POSITION` turns the Arkanoid
+//! controller's knob, with `fire` to press its button, `power-pad` lists
+//! the mat's buttons to hold by number and `keyboard` the Family BASIC keys
+//! by name, like `keyboard left-shift a`:
+//!
+//! ```text
+//! 700      vaus        170 fire
+//! 800      power-pad   1 5 9
+//! 900      keyboard    r u n return
+//! ```
+//!
+//! Lastly `reset` presses the reset button, and `save-state` and
+//! `load-state` snapshot the console and go back to the snapshot, like the
+//! window's hotkeys:
+//!
+//! ```text
+//! 1000     save-state
+//! 1200     reset
+//! 1400     load-state
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::input::device::InputDevices;
+use crate::input::keyboard::{FamilyKey, KeyboardState};
+use crate::input::vaus::{self, VausState};
+use crate::input::zapper::ZapperState;
+use crate::input_macro::InputMacro;
+use crate::nes::{Button, Controller, Nes, SaveState};
+
+#[derive(Debug, Clone)]
+enum Change {
+    /// The buttons to hold, and to hold with turbo
+    Buttons {
+        controller: Controller,
+        bits: u8,
+        turbo: u8,
+    },
+    Macro(Controller, InputMacro),
+    /// Set the turbo rate of the buttons in `buttons`
+    TurboRate {
+        controller: Controller,
+        buttons: u8,
+        frames: u8,
+    },
+    Record(Controller),
+    /// Stop recording and save the macro to a file
+    StopRecording(Controller, PathBuf),
+    Zapper(ZapperState),
+    Vaus(VausState),
+    /// The Power Pad buttons to hold, see `Nes::set_power_pad`
+    PowerPad(u16),
+    /// The Family BASIC keys to hold
+    Keyboard(KeyboardState),
+    Reset,
+    SaveState,
+    LoadState,
+}
+
+#[derive(Debug, Clone)]
+struct InputEvent {
+    frame: u32,
+    change: Change,
+}
+
+#[derive(Clone, Default)]
+pub struct InputScript {
+    /// Sorted by frame
+    events: Vec<InputEvent>,
+    /// Index of the first event that hasn't been applied
+    next: usize,
+    /// The last `save-state`
+    saved: Option<SaveState>,
+}
+
+impl InputScript {
+    pub fn load_from_file(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read input script {:?}: {}", path, e))?;
+        // macros are relative to the script
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        Self::parse(&text, dir)
+    }
+
+    fn parse(text: &str, dir: &Path) -> Result<Self, String> {
+        let mut events = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event = parse_line(line, dir).map_err(|e| format!("Line {}: {}", number + 1, e))?;
+            events.push(event);
+        }
+        // stable, so changes on the same frame keep their order
+        events.sort_by_key(|event| event.frame);
+        Ok(Self {
+            events,
+            next: 0,
+            saved: None,
+        })
+    }
+
+    /// Apply every change up to and including `frame`.  Call it before
+    /// running each frame.  Only saving a recording can fail.
+    pub fn apply(&mut self, frame: u32, nes: &mut Nes) -> Result<(), String> {
+        while let Some(event) = self.events.get(self.next) {
+            if event.frame > frame {
+                break;
+            }
+            if let Some((name, devices)) = device(&event.change) {
+                if !devices.contains(&nes.input_devices()) {
+                    warn!(
+                        "Frame {}: there's no {} plugged in, see --input-device",
+                        event.frame, name
+                    );
+                }
+            }
+            match &event.change {
+                Change::Buttons {
+                    controller,
+                    bits,
+                    turbo,
+                } => {
+                    nes.set_controller_bits(*controller, *bits);
+                    nes.set_turbo_bits(*controller, *turbo);
+                }
+                Change::Macro(controller, input_macro) => {
+                    nes.play_macro(*controller, input_macro.clone())
+                }
+                Change::TurboRate {
+                    controller,
+                    buttons,
+                    frames,
+                } => {
+                    for &button in Button::ALL.iter() {
+                        if buttons & button.bit() != 0 {
+                            nes.set_turbo_rate(*controller, button, *frames);
+                        }
+                    }
+                }
+                Change::Record(controller) => {
+                    if nes.is_recording_macro(*controller) {
+                        warn!(
+                            "Frame {}: controller {} is already recording, starting again",
+                            event.frame,
+                            controller.index() + 1
+                        );
+                    }
+                    nes.start_macro_recording(*controller);
+                }
+                Change::StopRecording(controller, path) => {
+                    match nes.stop_macro_recording(*controller) {
+                        Some(input_macro) => input_macro.save_to_file(path)?,
+                        None => warn!(
+                            "Frame {}: controller {} isn't recording",
+                            event.frame,
+                            controller.index() + 1
+                        ),
+                    }
+                }
+                Change::Zapper(zapper) => nes.set_zapper(zapper.aim, zapper.trigger),
+                Change::Vaus(vaus) => nes.set_vaus(vaus.position, vaus.button),
+                Change::PowerPad(buttons) => nes.set_power_pad(*buttons),
+                Change::Keyboard(keyboard) => nes.set_keyboard(*keyboard),
+                Change::Reset => nes.reset(),
+                Change::SaveState => self.saved = Some(nes.save_state()),
+                Change::LoadState => match &self.saved {
+                    Some(state) => nes.load_state(state),
+                    None => warn!("Frame {}: there's no saved state to load", event.frame),
+                },
+            }
+            self.next += 1;
+        }
+        Ok(())
+    }
+
+    /// The frame of the last change, if there are any.
+    pub fn last_frame(&self) -> Option<u32> {
+        self.events.last().map(|event| event.frame)
+    }
+}
+
+impl std::str::FromStr for InputScript {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s, Path::new(""))
+    }
+}
+
+fn parse_line(line: &str, dir: &Path) -> Result<InputEvent, String> {
+    let mut words = line
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|word| !word.is_empty());
+
+    let frame = words.next().unwrap_or_default();
+    let frame = frame
+        .parse()
+        .map_err(|e| format!("Invalid frame {:?}: {}", frame, e))?;
+    let device = words.next();
+    let change = match device {
+        Some("zapper") => Some(Change::Zapper(parse_zapper(words.by_ref())?)),
+        Some("vaus") => Some(Change::Vaus(parse_vaus(words.by_ref())?)),
+        Some("power-pad") => Some(Change::PowerPad(parse_power_pad(words.by_ref())?)),
+        Some("keyboard") => Some(Change::Keyboard(parse_keyboard(words.by_ref())?)),
+        Some("reset") => Some(nothing_after(Change::Reset, words.by_ref())?),
+        Some("save-state") => Some(nothing_after(Change::SaveState, words.by_ref())?),
+        Some("load-state") => Some(nothing_after(Change::LoadState, words.by_ref())?),
+        _ => None,
+    };
+    if let Some(change) = change {
+        return Ok(InputEvent { frame, change });
+    }
+
+    let controller = match device {
+        Some(number) => number
+            .parse()
+            .ok()
+            .and_then(Controller::from_number)
+            .ok_or_else(|| format!("Expected controller 1 to 4 or a device, got {:?}", number))?,
+        None => return Err("Expected a controller after the frame".to_string()),
+    };
+    let mut words = words.peekable();
+    if words.next_if_eq(&"macro").is_some() {
+        let file = words
+            .next()
+            .ok_or_else(|| "Expected a file after macro".to_string())?;
+        return Ok(InputEvent {
+            frame,
+            change: Change::Macro(controller, InputMacro::load_from_file(dir.join(file))?),
+        });
+    }
+    if words.next_if_eq(&"record").is_some() {
+        return Ok(InputEvent {
+            frame,
+            change: nothing_after(Change::Record(controller), words)?,
+        });
+    }
+    if words.next_if_eq(&"stop-recording").is_some() {
+        let file = words
+            .next()
+            .ok_or_else(|| "Expected a file after stop-recording".to_string())?;
+        return Ok(InputEvent {
+            frame,
+            change: nothing_after(Change::StopRecording(controller, dir.join(file)), words)?,
+        });
+    }
+    if let Some(button) = words
+        .peek()
+        .and_then(|word| word.strip_prefix("turbo-rate"))
+    {
+        words.next();
+        return Ok(InputEvent {
+            frame,
+            change: parse_turbo_rate(controller, button, words)?,
+        });
+    }
+
+    let mut bits = 0;
+    let mut turbo = 0;
+    for word in words {
+        match word.strip_prefix("turbo-") {
+            Some(button) => turbo |= button.parse::<Button>()?.bit(),
+            None => bits |= word.parse::<Button>()?.bit(),
+        }
+    }
+
+    Ok(InputEvent {
+        frame,
+        change: Change::Buttons {
+            controller,
+            bits,
+            turbo,
+        },
+    })
+}
+
+/// The device a change is for and the ways of plugging it in, `None` for
+/// changes that don't need one.
+fn device(change: &Change) -> Option<(&'static str, &'static [InputDevices])> {
+    match change {
+        Change::Zapper(_) => Some(("Zapper", &[InputDevices::Zapper])),
+        Change::Vaus(_) => Some((
+            "Arkanoid controller",
+            &[InputDevices::Arkanoid, InputDevices::ArkanoidFamicom],
+        )),
+        Change::PowerPad(_) => Some((
+            "Power Pad",
+            &[InputDevices::PowerPad, InputDevices::FamilyTrainer],
+        )),
+        Change::Keyboard(_) => Some(("Family BASIC keyboard", &[InputDevices::FamilyKeyboard])),
+        _ => None,
+    }
+}
+
+/// For changes like `reset` that are the end of the line.
+fn nothing_after<'a>(
+    change: Change,
+    mut words: impl Iterator<Item = &'a str>,
+) -> Result<Change, String> {
+    match words.next() {
+        Some(word) => Err(format!("Unexpected {:?} at the end of the line", word)),
+        None => Ok(change),
+    }
+}
+
+/// `turbo-rate N` for every button or `turbo-rate-a N` for one, with the
+/// `turbo-rate` already taken off.
+fn parse_turbo_rate<'a>(
+    controller: Controller,
+    button: &str,
+    mut words: impl Iterator<Item = &'a str>,
+) -> Result<Change, String> {
+    let buttons = match button.strip_prefix('-') {
+        Some(button) => button.parse::<Button>()?.bit(),
+        None if button.is_empty() => 0xFF,
+        None => return Err(format!("Unknown button \"turbo-rate{}\"", button)),
+    };
+    let frames = words.next().unwrap_or_default();
+    let frames = match frames.parse() {
+        Ok(frames) if frames > 0 => frames,
+        _ => {
+            return Err(format!(
+                "Expected a turbo rate of 1 or more frames, got {:?}",
+                frames
+            ))
+        }
+    };
+    nothing_after(
+        Change::TurboRate {
+            controller,
+            buttons,
+            frames,
+        },
+        words,
+    )
+}
+
+/// `X Y`, `X Y fire` or `off`.
+fn parse_zapper<'a>(mut words: impl Iterator<Item = &'a str>) -> Result<ZapperState, String> {
+    let mut zapper = ZapperState::default();
+    match words.next() {
+        Some("off") => (),
+        Some(x) => {
+            let y = words
+                .next()
+                .ok_or_else(|| "Expected zapper X Y".to_string())?;
+            let x = x
+                .parse()
+                .map_err(|e| format!("Invalid zapper x {:?}: {}", x, e))?;
+            let y = y
+                .parse()
+                .map_err(|e| format!("Invalid zapper y {:?}: {}", y, e))?;
+            if y >= 240 {
+                return Err(format!("Zapper y {} is below the screen", y));
+            }
+            zapper.aim = Some((x, y));
+            if let Some(word) = words.next() {
+                if word != "fire" {
+                    return Err(format!("Expected fire after zapper X Y, got {:?}", word));
+                }
+                zapper.trigger = true;
+            }
+        }
+        None => return Err("Expected zapper X Y or zapper off".to_string()),
+    }
+    match words.next() {
+        Some(word) => Err(format!("Unexpected {:?} after the zapper", word)),
+        None => Ok(zapper),
+    }
+}
+
+/// `POSITION` or `POSITION fire`.
+fn parse_vaus<'a>(mut words: impl Iterator<Item = &'a str>) -> Result<VausState, String> {
+    let position = words
+        .next()
+        .ok_or_else(|| "Expected vaus POSITION".to_string())?;
+    let position = position
+        .parse()
+        .map_err(|e| format!("Invalid vaus position {:?}: {}", position, e))?;
+    if !(vaus::MIN_POSITION..=vaus::MAX_POSITION).contains(&position) {
+        return Err(format!(
+            "Vaus positions are {} to {}, got {}",
+            vaus::MIN_POSITION,
+            vaus::MAX_POSITION,
+            position
+        ));
+    }
+    let button = match words.next() {
+        Some("fire") => true,
+        Some(word) => return Err(format!("Expected fire after vaus POSITION, got {:?}", word)),
+        None => false,
+    };
+    Ok(VausState { position, button })
+}
+
+/// Key names like `left-shift`.
+fn parse_keyboard<'a>(words: impl Iterator<Item = &'a str>) -> Result<KeyboardState, String> {
+    let mut keyboard = KeyboardState::default();
+    for word in words {
+        keyboard.set(word.parse::<FamilyKey>()?, true);
+    }
+    Ok(keyboard)
+}
+
+/// Button numbers from 1 to 12.
+fn parse_power_pad<'a>(words: impl Iterator<Item = &'a str>) -> Result<u16, String> {
+    let mut buttons = 0;
+    for word in words {
+        let button: u16 = word
+            .parse()
+            .map_err(|e| format!("Invalid power pad button {:?}: {}", word, e))?;
+        if !(1..=12).contains(&button) {
+            return Err(format!("Power pad buttons are 1 to 12, got {}", button));
+        }
+        buttons |= 1 << (button - 1);
+    }
+    Ok(buttons)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Vec<InputEvent> {
+        text.parse::<InputScript>().unwrap().events
+    }
+
+    fn parse_err(text: &str) -> String {
+        match text.parse::<InputScript>() {
+            Ok(_) => panic!("{:?} parsed", text),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn buttons() {
+        let events = parse(
+            "
+            # frame  controller  buttons
+            120      1           right a   # comment
+            60       2           Start
+            60       1           left,b
+            62       1
+            ",
+        );
+        let changes: Vec<(u32, Controller, u8)> = events
+            .iter()
+            .map(|event| match event.change {
+                Change::Buttons {
+                    controller,
+                    bits,
+                    turbo: 0,
+                } => (event.frame, controller, bits),
+                ref change => panic!("{:?}", change),
+            })
+            .collect();
+        // sorted by frame, in file order within a frame
+        assert_eq!(
+            changes,
+            [
+                (60, Controller::Two, Button::Start.bit()),
+                (60, Controller::One, Button::Left.bit() | Button::B.bit()),
+                (62, Controller::One, 0),
+                (120, Controller::One, Button::Right.bit() | Button::A.bit()),
+            ]
+        );
+    }
+
+    #[test]
+    fn turbo() {
+        let events = parse(
+            "
+            10 1 turbo-a b
+            20 2 turbo-rate 3
+            30 3 turbo-rate-start 1
+            ",
+        );
+        assert!(matches!(
+            events[0].change,
+            Change::Buttons {
+                controller: Controller::One,
+                bits: 0b0100_0000,
+                turbo: 0b1000_0000,
+            }
+        ));
+        assert!(matches!(
+            events[1].change,
+            Change::TurboRate {
+                controller: Controller::Two,
+                buttons: 0xFF,
+                frames: 3,
+            }
+        ));
+        assert!(matches!(
+            events[2].change,
+            Change::TurboRate {
+                controller: Controller::Three,
+                buttons: 0b0001_0000,
+                frames: 1,
+            }
+        ));
+
+        assert!(parse_err("10 1 turbo-rate 0").contains("1 or more frames"));
+        assert!(parse_err("10 1 turbo-rate").contains("1 or more frames"));
+        assert!(parse_err("10 1 turbo-rate 2 a").contains("Unexpected"));
+        assert!(parse_err("10 1 turbo-ratez 2").contains("turbo-ratez"));
+        assert!(parse_err("10 1 turbo-jump").contains("jump"));
+    }
+
+    #[test]
+    fn macros_and_recording() {
+        let dir = std::env::temp_dir().join(format!("nerust-script-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("jump.txt"), "a *2\n").unwrap();
+        let path = dir.join("script.txt");
+        fs::write(
+            &path,
+            "10 1 macro jump.txt\n20 2 record\n30 2 stop-recording p2.txt\n",
+        )
+        .unwrap();
+        let script = InputScript::load_from_file(&path);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let events = script.unwrap().events;
+        match &events[0].change {
+            Change::Macro(Controller::One, input_macro) => {
+                assert_eq!(input_macro.frames(), [0b1000_0000; 2])
+            }
+            change => panic!("{:?}", change),
+        }
+        assert!(matches!(events[1].change, Change::Record(Controller::Two)));
+        match &events[2].change {
+            Change::StopRecording(Controller::Two, file) => assert_eq!(*file, dir.join("p2.txt")),
+            change => panic!("{:?}", change),
+        }
+
+        assert!(parse_err("10 1 macro").contains("Expected a file"));
+        assert!(parse_err("10 1 record now").contains("Unexpected"));
+        assert!(parse_err("10 1 stop-recording").contains("Expected a file"));
+    }
+
+    #[test]
+    fn zapper() {
+        let events = parse("600 zapper 128 96 fire\n602 zapper 0 239\n604 zapper off");
+        let zappers: Vec<ZapperState> = events
+            .iter()
+            .map(|event| match event.change {
+                Change::Zapper(zapper) => zapper,
+                ref change => panic!("{:?}", change),
+            })
+            .collect();
+        assert_eq!(
+            zappers,
+            [
+                ZapperState {
+                    aim: Some((128, 96)),
+                    trigger: true,
+                },
+                ZapperState {
+                    aim: Some((0, 239)),
+                    trigger: false,
+                },
+                ZapperState::default(),
+            ]
+        );
+
+        assert!(parse_err("1 zapper").contains("Expected zapper X Y"));
+        assert!(parse_err("1 zapper 10").contains("Expected zapper X Y"));
+        assert!(parse_err("1 zapper 256 10").contains("Invalid zapper x"));
+        assert!(parse_err("1 zapper 10 240").contains("below the screen"));
+        assert!(parse_err("1 zapper 10 10 shoot").contains("Expected fire"));
+        assert!(parse_err("1 zapper off fire").contains("Unexpected"));
+    }
+
+    #[test]
+    fn other_devices() {
+        let events = parse(
+            "
+            700 vaus 170 fire
+            701 vaus 98
+            800 power-pad 1 5,12
+            801 power-pad
+            900 keyboard left-shift A
+            ",
+        );
+        assert!(matches!(
+            events[0].change,
+            Change::Vaus(VausState {
+                position: 170,
+                button: true,
+            })
+        ));
+        assert!(matches!(
+            events[1].change,
+            Change::Vaus(VausState {
+                position: 98,
+                button: false,
+            })
+        ));
+        assert!(matches!(
+            events[2].change,
+            Change::PowerPad(0b1000_0001_0001)
+        ));
+        assert!(matches!(events[3].change, Change::PowerPad(0)));
+        let mut keyboard = KeyboardState::default();
+        keyboard.set("left-shift".parse().unwrap(), true);
+        keyboard.set("a".parse().unwrap(), true);
+        match events[4].change {
+            Change::Keyboard(keys) => assert_eq!(keys, keyboard),
+            ref change => panic!("{:?}", change),
+        }
+
+        assert!(parse_err("1 vaus").contains("Expected vaus POSITION"));
+        assert!(parse_err("1 vaus 97").contains("Vaus positions are 98 to 242"));
+        assert!(parse_err("1 vaus 243").contains("Vaus positions are 98 to 242"));
+        assert!(parse_err("1 vaus 100 press").contains("Expected fire"));
+        assert!(parse_err("1 power-pad 0").contains("1 to 12"));
+        assert!(parse_err("1 power-pad 13").contains("1 to 12"));
+        assert!(parse_err("1 power-pad x").contains("Invalid power pad button"));
+        assert!(parse_err("1 keyboard shift").contains("Unknown Family BASIC key"));
+    }
+
+    #[test]
+    fn last_frame() {
+        let script: InputScript = "10 1 a\n5 4 b".parse().unwrap();
+        assert_eq!(script.last_frame(), Some(10));
+        let script: InputScript = "# nothing\n".parse().unwrap();
+        assert_eq!(script.last_frame(), None);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse_err("start 1 a").starts_with("Line 1: Invalid frame"));
+        assert!(parse_err("\n10").starts_with("Line 2: Expected a controller"));
+        assert!(parse_err("10 5 a").contains("Expected controller 1 to 4"));
+        assert!(parse_err("10 1 jump").contains("jump"));
+        assert!(parse_err("-1 1 a").contains("Invalid frame"));
+    }
+}
@@ -1,16 +1,145 @@
+//! The `log` backend: the browser console on wasm, stdout or a file natively.
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use ::log::{LevelFilter, Metadata};
+
+/// The frame being emulated, for `LogPrefix::Frame`.
+static FRAME: AtomicU64 = AtomicU64::new(0);
+
+/// Record the frame being emulated for log lines.
+pub fn set_frame(frame: u64) {
+    FRAME.store(frame, Ordering::Relaxed);
+}
+
+/// What goes at the start of each native log line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogPrefix {
+    None,
+    /// Seconds since the logger was attached
+    Time,
+    /// The frame being emulated
+    Frame,
+}
+
+impl FromStr for LogPrefix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(LogPrefix::None),
+            "time" => Ok(LogPrefix::Time),
+            "frame" => Ok(LogPrefix::Frame),
+            _ => Err(format!(
+                "Unknown log prefix {:?}, expected none, time or frame",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogConfig {
+    /// The level for targets without a filter
+    pub level: LevelFilter,
+    /// Levels for targets like `cpu` or `apu::dmc` and everything under them,
+    /// the most specific one wins
+    pub filters: Vec<(String, LevelFilter)>,
+    /// Write to this file instead of stdout
+    pub file: Option<PathBuf>,
+    pub prefix: LogPrefix,
+    /// How many times a second each warning or error is logged before
+    /// repeats are dropped, 0 for no limit
+    pub rate_limit: u32,
+}
+
+impl LogConfig {
+    pub fn new(level: LevelFilter) -> Self {
+        Self {
+            level,
+            filters: Vec::new(),
+            file: None,
+            prefix: LogPrefix::None,
+            rate_limit: 0,
+        }
+    }
+
+    /// Add filters like `cpu=trace,apu=off`.  A bare level sets the level
+    /// for everything else.
+    pub fn add_filters(&mut self, s: &str) -> Result<(), String> {
+        let parse_level = |level: &str| {
+            LevelFilter::from_str(level.trim())
+                .map_err(|_| format!("Unknown log level {:?}", level))
+        };
+        for filter in s.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            match filter.split_once('=') {
+                Some((target, level)) => {
+                    let level = parse_level(level)?;
+                    self.filters.push((target.trim().to_string(), level));
+                }
+                None => self.level = parse_level(filter)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// The level for a target like `NEruSt::cpu::cpu`.
+    fn level_for(&self, target: &str) -> LevelFilter {
+        // filters are relative to the crate
+        let target = target.split_once("::").map_or(target, |(_, path)| path);
+        self.filters
+            .iter()
+            .filter(|(prefix, _)| {
+                target == prefix
+                    || target
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.starts_with("::"))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.level, |&(_, level)| level)
+    }
+
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_for(metadata.target())
+    }
+
+    /// The most verbose level anything is logged at.
+    fn max_level(&self) -> LevelFilter {
+        self.filters
+            .iter()
+            .map(|&(_, level)| level)
+            .fold(self.level, Ord::max)
+    }
+}
+
 #[cfg(target_arch = "wasm32")]
 mod log {
+    use super::LogConfig;
     use crate::wasm;
     use ::log::{Level, Log, Metadata, Record};
 
-    pub struct Logger;
+    pub struct Logger {
+        config: LogConfig,
+    }
+
+    impl Logger {
+        /// The console has its own timestamps, and can't log to a file.
+        pub fn new(config: LogConfig) -> Result<Self, String> {
+            Ok(Self { config })
+        }
+    }
 
     impl Log for Logger {
-        fn enabled(&self, _metadata: &Metadata) -> bool {
-            true
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            self.config.enabled(metadata)
         }
 
         fn log(&self, record: &Record) {
+            if !self.enabled(record.metadata()) {
+                return;
+            }
             match record.level() {
                 Level::Error => wasm::error(&format!("{}", record.args())),
                 Level::Warn => wasm::warn(&format!("{}", record.args())),
@@ -26,34 +155,240 @@ mod log {
 
 #[cfg(not(target_arch = "wasm32"))]
 mod log {
-    use ::log::{Log, Metadata, Record};
+    use super::{LogConfig, LogPrefix, FRAME};
+    use ::log::{Level, Log, Metadata, Record};
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::io::{self, BufWriter, Write};
+    use std::sync::atomic::Ordering;
+    use std::sync::Mutex;
+    use std::time::Instant;
 
-    pub struct Logger;
+    /// How often one log call has been hit in the current second.
+    struct Repeats {
+        second: u64,
+        count: u32,
+        dropped: u32,
+    }
+
+    pub struct Logger {
+        config: LogConfig,
+        out: Mutex<Box<dyn Write + Send>>,
+        start: Instant,
+        /// By target and line
+        repeats: Mutex<HashMap<(String, u32), Repeats>>,
+    }
+
+    impl Logger {
+        pub fn new(config: LogConfig) -> Result<Self, String> {
+            let out: Box<dyn Write + Send> = match &config.file {
+                Some(path) => {
+                    Box::new(BufWriter::new(File::create(path).map_err(|e| {
+                        format!("Failed to create log file {:?}: {}", path, e)
+                    })?))
+                }
+                None => Box::new(io::stdout()),
+            };
+            Ok(Self {
+                config,
+                out: Mutex::new(out),
+                start: Instant::now(),
+                repeats: Mutex::new(HashMap::new()),
+            })
+        }
+
+        fn prefix(&self) -> String {
+            match self.config.prefix {
+                LogPrefix::None => String::new(),
+                LogPrefix::Time => {
+                    format!("[{:10.3}] ", self.start.elapsed().as_secs_f64())
+                }
+                LogPrefix::Frame => format!("[frame {}] ", FRAME.load(Ordering::Relaxed)),
+            }
+        }
+
+        /// Whether to write a warning or error, and how many repeats of it
+        /// were dropped in the last second it was logged.
+        fn rate_limit(&self, record: &Record) -> (bool, u32) {
+            let limit = self.config.rate_limit;
+            if limit == 0 || record.level() > Level::Warn {
+                return (true, 0);
+            }
+
+            let second = self.start.elapsed().as_secs();
+            let key = (
+                record.target().to_string(),
+                record.line().unwrap_or_default(),
+            );
+            let mut repeats = self.repeats.lock().unwrap_or_else(|e| e.into_inner());
+            let repeats = repeats.entry(key).or_insert(Repeats {
+                second,
+                count: 0,
+                dropped: 0,
+            });
+            let mut dropped = 0;
+            if repeats.second != second {
+                dropped = repeats.dropped;
+                *repeats = Repeats {
+                    second,
+                    count: 0,
+                    dropped: 0,
+                };
+            }
+            repeats.count += 1;
+            if repeats.count > limit {
+                repeats.dropped += 1;
+                return (false, 0);
+            }
+            (true, dropped)
+        }
+    }
 
     impl Log for Logger {
-        fn enabled(&self, _metadata: &Metadata) -> bool {
-            true
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            self.config.enabled(metadata)
         }
 
         fn log(&self, record: &Record) {
-            println!(
-                "{}:{} -- {}",
+            if !self.enabled(record.metadata()) {
+                return;
+            }
+            let (write, dropped) = self.rate_limit(record);
+            if !write {
+                return;
+            }
+
+            let prefix = self.prefix();
+            let mut out = self.out.lock().unwrap_or_else(|e| e.into_inner());
+            // there's nowhere left to report a failed log write
+            if dropped > 0 {
+                let _ = writeln!(
+                    out,
+                    "{}{}:{} -- (dropped {} repeats of the next message)",
+                    prefix,
+                    record.level(),
+                    record.target(),
+                    dropped
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}{}:{} -- {}",
+                prefix,
                 record.level(),
                 record.target(),
                 record.args()
             );
+            // so problems aren't lost if we're killed or crash
+            if record.level() <= Level::Warn {
+                let _ = out.flush();
+            }
         }
 
-        fn flush(&self) {}
+        fn flush(&self) {
+            let mut out = self.out.lock().unwrap_or_else(|e| e.into_inner());
+            let mut repeats = self.repeats.lock().unwrap_or_else(|e| e.into_inner());
+            for ((target, line), repeats) in repeats.iter_mut() {
+                if repeats.dropped > 0 {
+                    let _ = writeln!(
+                        out,
+                        "{}{}:{} -- (dropped {} repeats of the message from line {})",
+                        self.prefix(),
+                        Level::Warn,
+                        target,
+                        repeats.dropped,
+                        line
+                    );
+                    repeats.dropped = 0;
+                }
+            }
+            let _ = out.flush();
+        }
     }
 }
 
 pub use self::log::*;
 
-pub fn attach_logger(max_level: ::log::LevelFilter) {
-    match ::log::set_boxed_logger(Box::new(Logger)) {
+pub fn attach_logger(config: LogConfig) -> Result<(), String> {
+    let max_level = config.max_level();
+    let logger = Logger::new(config)?;
+    match ::log::set_boxed_logger(Box::new(logger)) {
         Ok(()) => (),
         Err(_) => error!("Failed to attach logger! Was it already attached?"),
     }
     ::log::set_max_level(max_level);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::log::{Level, Log, Record};
+    use std::fs;
+
+    #[test]
+    fn filters() {
+        let mut config = LogConfig::new(LevelFilter::Info);
+        config
+            .add_filters("cpu=trace, apu=off,apu::dmc=debug")
+            .unwrap();
+        assert_eq!(config.level_for("NEruSt::cpu"), LevelFilter::Trace);
+        assert_eq!(config.level_for("NEruSt::cpu::cpu"), LevelFilter::Trace);
+        assert_eq!(config.level_for("NEruSt::cpu_bus"), LevelFilter::Info);
+        assert_eq!(config.level_for("NEruSt::apu::apu"), LevelFilter::Off);
+        assert_eq!(config.level_for("NEruSt::apu::dmc"), LevelFilter::Debug);
+        assert_eq!(config.level_for("NEruSt::ppu::ppu"), LevelFilter::Info);
+        assert_eq!(config.max_level(), LevelFilter::Trace);
+
+        config.add_filters("warn").unwrap();
+        assert_eq!(config.level_for("NEruSt::ppu::ppu"), LevelFilter::Warn);
+
+        assert!(config.add_filters("cpu=loud").is_err());
+        assert!(config.add_filters("loud").is_err());
+    }
+
+    #[test]
+    fn prefixes() {
+        assert_eq!("Frame".parse(), Ok(LogPrefix::Frame));
+        assert!("date".parse::<LogPrefix>().is_err());
+    }
+
+    #[test]
+    fn rate_limit() {
+        let path = std::env::temp_dir().join(format!("nerust-test-{}.log", std::process::id()));
+        let mut config = LogConfig::new(LevelFilter::Info);
+        config.file = Some(path.clone());
+        config.rate_limit = 2;
+        let logger = Logger::new(config).unwrap();
+        let log = |level: Level, line: u32| {
+            logger.log(
+                &Record::builder()
+                    .args(format_args!("line {}", line))
+                    .level(level)
+                    .target("NEruSt::apu")
+                    .line(Some(line))
+                    .build(),
+            )
+        };
+        for _ in 0..5 {
+            log(Level::Warn, 1);
+            log(Level::Info, 2);
+        }
+        log(Level::Error, 3);
+        log(Level::Debug, 4);
+        logger.flush();
+        let lines = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let count = |message: &str| lines.lines().filter(|line| line.ends_with(message)).count();
+        assert_eq!(count("-- line 1"), 2);
+        assert_eq!(
+            count("-- line 2"),
+            5,
+            "only warnings and errors are limited"
+        );
+        assert_eq!(count("-- line 3"), 1);
+        assert_eq!(count("-- line 4"), 0);
+        assert_eq!(count("(dropped 3 repeats of the message from line 1)"), 1);
+    }
 }
@@ -3,32 +3,339 @@ extern crate log;
 
 mod args;
 //mod cpu;
-//mod window;
 
+#[allow(clippy::module_inception)]
 mod apu;
+#[cfg(any(feature = "sdl", test))]
+mod bindings;
 mod cartridge;
+mod checksum;
+#[allow(clippy::module_inception)]
 mod cpu;
 mod header;
+mod input;
+mod input_macro;
+mod input_script;
 mod logging;
+mod mapper;
 mod nes;
+mod nsf;
+mod png;
+#[allow(clippy::module_inception)]
 mod ppu;
+mod region;
+mod rom_info;
 #[cfg(target_arch = "wasm32")]
 mod wasm;
+mod wav;
+#[cfg(feature = "sdl")]
+mod window;
 
-use crate::args::Settings;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process;
+#[cfg(not(feature = "sdl"))]
+use std::thread;
+#[cfg(not(feature = "sdl"))]
+use std::time::{Duration, Instant};
+
+use crate::args::{Command, DisasmSettings, RunSettings, Settings};
 use crate::cartridge::Cartridge;
-use crate::nes::{Controller, Nes};
+use crate::cpu::assembler;
+use crate::cpu::disassembler::{self, Labels};
+use crate::input_script::InputScript;
+use crate::nes::Nes;
+use crate::nsf::{Nsf, NsfPlayer};
+use crate::ppu::palette::Palette;
+use crate::rom_info::RomInfo;
+#[cfg(feature = "sdl")]
+use crate::window::EmuWindow;
+
+/// The size of a frame.
+const SCREEN_WIDTH: u32 = 256;
+const SCREEN_HEIGHT: u32 = 240;
+
+/// How long NSF tracks play for when neither the file nor the command line says.
+const DEFAULT_TRACK_LENGTH_MS: u32 = 150_000;
+const DEFAULT_TRACK_FADE_MS: u32 = 5_000;
 
 fn main() {
-    logging::attach_logger(::log::LevelFilter::Trace);
     let settings = Settings::new();
+    if let Err(e) = logging::attach_logger(settings.log.clone()) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
 
-    let cart = Cartridge::load_from_file(&settings.rom_file);
+    let result = match &settings.command {
+        Command::Run(run) => run_headless(&settings, run),
+        Command::Info { json } => RomInfo::load_from_file(&settings.rom_file).map(|info| {
+            if *json {
+                println!("{}", info.to_json());
+            } else {
+                println!("{}", info);
+            }
+        }),
+        Command::Disasm(disasm) => print_disassembly(&settings, disasm),
+        Command::Asm { source } => assemble_rom(&settings, source),
+        Command::Play => {
+            if Nsf::is_nsf_file(&settings.rom_file) {
+                render_nsf(&settings)
+            } else {
+                play(&settings)
+            }
+        }
+    };
+    if let Err(e) = &result {
+        error!("{}", e);
+    }
+    // the log file is buffered
+    ::log::logger().flush();
+    if result.is_err() {
+        process::exit(1);
+    }
+}
+
+fn play(settings: &Settings) -> Result<(), String> {
+    let cart = Cartridge::load_from_file(&settings.rom_file)?;
     let mut nes = Nes::new(cart);
+    configure(&mut nes, settings)?;
+
+    // audio is only produced once there's a sample rate, so leave it unset
+    // unless the window plays it or it's recorded
+    if cfg!(feature = "sdl") || settings.wav_file.is_some() {
+        nes.set_sample_rate(settings.sample_rate);
+    }
+    if let Some(wav_file) = &settings.wav_file {
+        nes.start_wav_recording(wav_file, settings.record_channels)
+            .map_err(|e| format!("Failed to start recording to {:?}: {}", wav_file, e))?;
+    }
+
+    run_frontend(&mut nes, settings)
+}
+
+#[cfg(feature = "sdl")]
+fn run_frontend(nes: &mut Nes, settings: &Settings) -> Result<(), String> {
+    let mut window = EmuWindow::new(settings)?;
+    window.run(nes)?;
+    nes.stop_wav_recording()
+        .map_err(|e| format!("Failed to finish the recording: {}", e))
+}
+
+/// Without a frontend, just run at the region's frame rate so the log and
+/// any recording can be used.
+#[cfg(not(feature = "sdl"))]
+fn run_frontend(nes: &mut Nes, _settings: &Settings) -> Result<(), String> {
+    let frame_time = Duration::from_secs_f64(1.0 / nes.region().frame_rate());
+    let mut next_frame = Instant::now();
+    loop {
+        nes.run_frame();
+        next_frame += frame_time;
+        let now = Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+        }
+    }
+}
+
+/// Run a ROM for a fixed number of frames with no frontend, saving
+/// screenshots along the way.  Emulator panics are caught and returned as
+/// errors so the process can exit with a failure code.
+fn run_headless(settings: &Settings, run: &RunSettings) -> Result<(), String> {
+    let mut input = match &run.input_script {
+        Some(path) => InputScript::load_from_file(path)?,
+        None => InputScript::default(),
+    };
+    if let Some(last) = input.last_frame() {
+        if last >= run.frames {
+            warn!(
+                "The input script goes on to frame {}, past the last frame {}",
+                last,
+                run.frames.saturating_sub(1)
+            );
+        }
+    }
+
+    let cart = Cartridge::load_from_file(&settings.rom_file)?;
+    let mut nes = Nes::new(cart);
+    configure(&mut nes, settings)?;
+    if let Some(wav_file) = &settings.wav_file {
+        nes.set_sample_rate(settings.sample_rate);
+        nes.start_wav_recording(wav_file, settings.record_channels)
+            .map_err(|e| format!("Failed to start recording to {:?}: {}", wav_file, e))?;
+    }
+
+    let mut rgb = vec![0; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize * 3];
+    for frame in 0..run.frames {
+        input.apply(frame, &mut nes)?;
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            nes.run_frame();
+        }))
+        .map_err(|_| format!("The emulator panicked during frame {}", frame))?;
+
+        let (screenshot, every) = match (&run.screenshot, run.screenshot_every) {
+            (Some(screenshot), every) => (screenshot, every),
+            (None, _) => continue,
+        };
+        let path = match every {
+            Some(every) if (frame + 1) % every == 0 => numbered_path(screenshot, frame + 1),
+            None if frame + 1 == run.frames => screenshot.clone(),
+            _ => continue,
+        };
+        nes.frame_to_rgb(&mut rgb);
+        png::write_rgb(&path, SCREEN_WIDTH, SCREEN_HEIGHT, &rgb)
+            .map_err(|e| format!("Failed to write {:?}: {}", path, e))?;
+    }
+
+    nes.stop_wav_recording()
+        .map_err(|e| format!("Failed to finish the recording: {}", e))
+}
 
-    for it in 0.. {
-        nes.step();
+fn print_disassembly(settings: &Settings, disasm: &DisasmSettings) -> Result<(), String> {
+    if let Some((start, end)) = disasm.range {
+        let cart = Cartridge::load_from_file(&settings.rom_file)?;
+        let mut nes = Nes::new(cart);
+        for _ in 0..disasm.frames {
+            nes.run_frame();
+        }
+        let mut labels = Labels::default();
+        if disasm.labels {
+            labels = Labels::hardware();
+            labels.add_vector_targets(|addr| nes.cpu_peek(addr));
+        }
+        for line in disassembler::disassemble(start, end, &labels, |addr| nes.cpu_peek(addr)) {
+            println!("{}", line);
+        }
+        return Ok(());
     }
+
+    let data = fs::read(&settings.rom_file)
+        .map_err(|e| format!("Failed to read {:?}: {}", settings.rom_file, e))?;
+    let (_, prg, _) = rom_info::split_rom(&data)?;
+    if prg.is_empty() {
+        return Err("The ROM has no PRG data".to_string());
+    }
+    let bank_size = disasm.bank_size.min(prg.len());
+    let bank_count = prg.len() / bank_size;
+    let banks = match disasm.bank {
+        Some(bank) if bank >= bank_count => {
+            return Err(format!(
+                "There are only {} {} KiB PRG banks",
+                bank_count,
+                bank_size / 1024
+            ))
+        }
+        Some(bank) => bank..bank + 1,
+        None => 0..bank_count,
+    };
+
+    for bank in banks {
+        let data = &prg[bank * bank_size..(bank + 1) * bank_size];
+        // the last bank is usually fixed at the end so the vectors are in it
+        let origin = disasm.origin.unwrap_or(if bank == bank_count - 1 {
+            (0x10000 - bank_size) as u16
+        } else {
+            0x8000
+        });
+        let end = (origin as usize + bank_size - 1).min(0xFFFF) as u16;
+        let peek = |addr: u16| data[(addr - origin) as usize];
+
+        let mut labels = Labels::default();
+        if disasm.labels {
+            labels = Labels::hardware();
+            if end == 0xFFFF {
+                labels.add_vector_targets(peek);
+            }
+        }
+        println!("; bank {} at ${:04X}", bank, origin);
+        for line in disassembler::disassemble(origin, end, &labels, peek) {
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+/// Assemble `source` and write it as an NROM ROM to the ROM file.
+fn assemble_rom(settings: &Settings, source: &Path) -> Result<(), String> {
+    let text =
+        fs::read_to_string(source).map_err(|e| format!("Failed to read {:?}: {}", source, e))?;
+    let image = assembler::assemble(&text)
+        .and_then(|assembly| assembly.to_nrom())
+        .map_err(|e| format!("{}: {}", source.display(), e))?;
+    fs::write(&settings.rom_file, image)
+        .map_err(|e| format!("Failed to write {:?}: {}", settings.rom_file, e))
+}
+
+/// `out.png` becomes `out-000060.png` for frame 60.
+fn numbered_path(path: &Path, frame: u32) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}-{:06}.{}", stem, frame, extension))
+}
+
+/// Apply the settings shared by games and NSFs.
+fn configure(nes: &mut Nes, settings: &Settings) -> Result<(), String> {
+    if let Some(palette_file) = &settings.palette_file {
+        nes.ppu.set_palette(Palette::load_from_file(palette_file)?);
+    }
+    if let Some(region) = settings.region {
+        nes.set_region(region);
+    }
+    if let Some(devices) = settings.input_devices {
+        nes.set_input_devices(devices);
+    }
+
+    let mixer = nes.apu.mixer_mut();
+    if let Some(channel) = settings.solo_channel {
+        mixer.solo(channel);
+    }
+    for &channel in &settings.muted_channels {
+        mixer.set_enabled(channel, false);
+    }
+    for &(channel, volume) in &settings.channel_volumes {
+        mixer.set_volume(channel, volume);
+    }
+    Ok(())
+}
+
+/// Render an NSF track to a WAV file.
+fn render_nsf(settings: &Settings) -> Result<(), String> {
+    let nsf = Nsf::load_from_file(&settings.rom_file)?;
+    info!(
+        "{} by {} ({}), {} tracks",
+        nsf.title, nsf.artist, nsf.copyright, nsf.song_count
+    );
+
+    let track = match settings.track {
+        Some(track) if track == 0 || track > nsf.song_count => {
+            return Err(format!(
+                "Track {} doesn't exist, the tracks are numbered 1 to {}",
+                track, nsf.song_count
+            ))
+        }
+        Some(track) => track - 1,
+        None => nsf.starting_song,
+    };
+    let length = settings
+        .track_length
+        .or_else(|| nsf.track_length(track))
+        .unwrap_or(DEFAULT_TRACK_LENGTH_MS);
+    let fade = settings
+        .track_fade
+        .or_else(|| nsf.track_fade(track))
+        .unwrap_or(DEFAULT_TRACK_FADE_MS);
+    let wav_file = settings
+        .wav_file
+        .clone()
+        .unwrap_or_else(|| settings.rom_file.with_extension("wav"));
+
+    let mut player = NsfPlayer::new(nsf)?;
+    configure(&mut player.nes, settings)?;
+    player.nes.set_sample_rate(settings.sample_rate);
+    info!("Rendering track {} to {:?}", track + 1, wav_file);
+    player
+        .render_to_wav(track, &wav_file, length, fade)
+        .map_err(|e| format!("Failed to render to {:?}: {}", wav_file, e))
 }
 
 #[cfg(target_arch = "wasm32")]
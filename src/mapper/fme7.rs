@@ -0,0 +1,156 @@
+//! Mapper 69, Sunsoft's FME-7.  The 5B variant adds a sound chip.
+
+use crate::apu::sunsoft5b::Sunsoft5bAudio;
+use crate::header::Mirroring;
+use crate::mapper::{bank_index, Chr, Mapper};
+
+#[derive(Clone)]
+pub struct Fme7 {
+    prg_rom: Box<[u8]>,
+    prg_ram: Box<[u8]>,
+    chr: Chr,
+
+    /// Register selected by $8000
+    command: u8,
+    /// 1 KiB banks
+    chr_banks: [u8; 8],
+    /// 8 KiB banks at $6000, $8000, $A000 and $C000.  $E000 is fixed to the
+    /// last bank.
+    prg_banks: [u8; 4],
+    /// $6000 maps RAM instead of ROM
+    prg_ram_selected: bool,
+    prg_ram_enabled: bool,
+    mirroring: Mirroring,
+
+    irq_enabled: bool,
+    irq_counter_enabled: bool,
+    irq_counter: u16,
+    irq_pending: bool,
+
+    audio: Sunsoft5bAudio,
+}
+
+impl Fme7 {
+    pub fn new(prg_rom: Box<[u8]>, prg_ram: Box<[u8]>, chr_rom: Box<[u8]>) -> Self {
+        Self {
+            prg_rom,
+            prg_ram,
+            chr: Chr::new(chr_rom),
+            command: 0,
+            chr_banks: [0; 8],
+            prg_banks: [0; 4],
+            prg_ram_selected: false,
+            prg_ram_enabled: false,
+            mirroring: Mirroring::Vertical,
+            irq_enabled: false,
+            irq_counter_enabled: false,
+            irq_counter: 0,
+            irq_pending: false,
+            audio: Sunsoft5bAudio::default(),
+        }
+    }
+
+    fn write_parameter(&mut self, value: u8) {
+        match self.command {
+            0..=7 => self.chr_banks[self.command as usize] = value,
+            8 => {
+                self.prg_banks[0] = value & 0b11_1111;
+                self.prg_ram_selected = value & 0b0100_0000 != 0;
+                self.prg_ram_enabled = value & 0b1000_0000 != 0;
+            }
+            9..=11 => self.prg_banks[self.command as usize - 8] = value & 0b11_1111,
+            12 => {
+                self.mirroring = match value & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                }
+            }
+            13 => {
+                self.irq_enabled = value & 0b0000_0001 != 0;
+                self.irq_counter_enabled = value & 0b1000_0000 != 0;
+                self.irq_pending = false;
+            }
+            14 => self.irq_counter = (self.irq_counter & 0xFF00) | value as u16,
+            _ => self.irq_counter = (self.irq_counter & 0xFF) | (value as u16) << 8,
+        }
+    }
+}
+
+impl Mapper for Fme7 {
+    fn box_clone(&self) -> Box<dyn Mapper> {
+        Box::new(self.clone())
+    }
+
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        self.cpu_peek(addr)
+    }
+
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        let offset = addr as usize;
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_selected && self.prg_ram_enabled => {
+                self.prg_ram[(offset - 0x6000) % self.prg_ram.len()]
+            }
+            // disabled RAM is open bus
+            0x6000..=0x7FFF if self.prg_ram_selected => 0,
+            0x6000..=0xDFFF => {
+                let bank = self.prg_banks[(offset - 0x6000) / 0x2000] as usize;
+                self.prg_rom[bank_index(&self.prg_rom, bank, 0x2000, offset)]
+            }
+            0xE000..=0xFFFF => {
+                let last = self.prg_rom.len() / 0x2000 - 1;
+                self.prg_rom[bank_index(&self.prg_rom, last, 0x2000, offset)]
+            }
+            // open bus
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_selected && self.prg_ram_enabled => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr as usize - 0x6000) % len] = value;
+            }
+            0x8000..=0x9FFF => self.command = value & 0b1111,
+            0xA000..=0xBFFF => self.write_parameter(value),
+            0xC000..=0xDFFF => self.audio.write_select(value),
+            0xE000..=0xFFFF => self.audio.write_data(value),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        let bank = self.chr_banks[(addr >> 10) as usize & 0b111] as usize;
+        self.chr.read(bank, 0x400, addr as usize)
+    }
+
+    fn ppu_write(&mut self, addr: u16, value: u8) {
+        let bank = self.chr_banks[(addr >> 10) as usize & 0b111] as usize;
+        self.chr.write(bank, 0x400, addr as usize, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn clock(&mut self) {
+        if self.irq_counter_enabled {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+            if self.irq_counter == 0xFFFF && self.irq_enabled {
+                self.irq_pending = true;
+            }
+        }
+        self.audio.clock();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+}
@@ -0,0 +1,118 @@
+//! The circuitry on the cartridge that decides what the CPU and PPU see, and
+//! any extra hardware like IRQ counters and sound chips.
+
+pub mod fme7;
+pub mod namco163;
+pub mod nrom;
+pub mod nsf;
+pub mod vrc6;
+
+use crate::header::Mirroring;
+
+pub trait Mapper {
+    /// A copy of the whole board, for save states.
+    fn box_clone(&self) -> Box<dyn Mapper>;
+
+    /// Read $4020-$FFFF.
+    fn cpu_read(&mut self, addr: u16) -> u8;
+    /// What `cpu_read` would return, without any of the side effects reads
+    /// can have, for debugging tools.
+    fn cpu_peek(&self, addr: u16) -> u8;
+    /// Write $4020-$FFFF.
+    fn cpu_write(&mut self, addr: u16, value: u8);
+    /// Read the pattern tables, $0000-$1FFF.
+    fn ppu_read(&mut self, addr: u16) -> u8;
+    /// Write the pattern tables, $0000-$1FFF.
+    fn ppu_write(&mut self, addr: u16, value: u8);
+
+    fn mirroring(&self) -> Mirroring;
+
+    /// Run for one CPU cycle, for IRQ counters and sound chips.
+    fn clock(&mut self) {}
+
+    /// Whether the cartridge is asserting the CPU's IRQ line.
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// The level of the cartridge's sound chip, on the APU mixer's scale
+    /// (see `apu::mixer::PULSE_STEP`).
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
+}
+
+/// The usual name for an iNES mapper number's boards, for the well known
+/// ones whether or not we support them.
+pub fn board_name(id: u16) -> Option<&'static str> {
+    Some(match id {
+        0 => "NROM",
+        1 => "MMC1",
+        2 => "UxROM",
+        3 => "CNROM",
+        4 => "MMC3",
+        5 => "MMC5",
+        7 => "AxROM",
+        9 => "MMC2",
+        10 => "MMC4",
+        11 => "Color Dreams",
+        13 => "CPROM",
+        16 => "Bandai FCG",
+        18 => "Jaleco SS88006",
+        19 => "Namco 163",
+        21 | 23 | 25 => "VRC2/VRC4",
+        22 => "VRC2a",
+        24 => "VRC6a",
+        26 => "VRC6b",
+        34 => "BNROM/NINA-001",
+        66 => "GxROM",
+        69 => "Sunsoft FME-7",
+        71 => "Camerica",
+        85 => "VRC7",
+        206 => "Namco 118",
+        _ => return None,
+    })
+}
+
+/// Index into `memory` for `offset` into a `bank_size` bank, wrapping banks
+/// past the end.
+pub fn bank_index(memory: &[u8], bank: usize, bank_size: usize, offset: usize) -> usize {
+    (bank * bank_size + offset % bank_size) % memory.len()
+}
+
+/// Pattern table memory, ROM or RAM.
+#[derive(Clone)]
+pub struct Chr {
+    pub data: Box<[u8]>,
+    pub is_ram: bool,
+}
+
+impl Chr {
+    /// 8 KiB of CHR RAM is used if the cartridge has no CHR ROM.
+    pub fn new(chr_rom: Box<[u8]>) -> Self {
+        if chr_rom.is_empty() {
+            Self {
+                data: vec![0; 0x2000].into_boxed_slice(),
+                is_ram: true,
+            }
+        } else {
+            Self {
+                data: chr_rom,
+                is_ram: false,
+            }
+        }
+    }
+
+    pub fn read(&self, bank: usize, bank_size: usize, offset: usize) -> u8 {
+        self.data[bank_index(&self.data, bank, bank_size, offset)]
+    }
+
+    pub fn write(&mut self, bank: usize, bank_size: usize, offset: usize, value: u8) {
+        if self.is_ram {
+            let index = bank_index(&self.data, bank, bank_size, offset);
+            self.data[index] = value;
+        } else {
+            warn!("Ignoring write to CHR ROM with value {}", value);
+        }
+    }
+}
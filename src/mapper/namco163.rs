@@ -0,0 +1,181 @@
+//! Mapper 19, the Namco 163 with its wavetable sound.
+
+use crate::apu::namco163::Namco163Audio;
+use crate::header::Mirroring;
+use crate::mapper::{bank_index, Chr, Mapper};
+
+#[derive(Clone)]
+pub struct Namco163 {
+    prg_rom: Box<[u8]>,
+    prg_ram: Box<[u8]>,
+    chr: Chr,
+
+    /// 8 KiB banks at $8000, $A000 and $C000, $E000 is fixed to the last
+    prg_banks: [u8; 3],
+    /// 1 KiB banks, selected by registers 2 KiB apart at $8000-$BFFF
+    chr_banks: [u8; 8],
+    /// $C000-$DFFF, nametable sources.  Values of $E0 and up pick a page of
+    /// the console's VRAM by their low bit.
+    nametable_banks: [u8; 4],
+
+    /// 15 bit counter, the IRQ fires when it reaches $7FFF
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_pending: bool,
+
+    audio: Namco163Audio,
+    audio_enabled: bool,
+}
+
+impl Namco163 {
+    pub fn new(prg_rom: Box<[u8]>, prg_ram: Box<[u8]>, chr_rom: Box<[u8]>) -> Self {
+        Self {
+            prg_rom,
+            prg_ram,
+            chr: Chr::new(chr_rom),
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            nametable_banks: [0xE0, 0xE1, 0xE0, 0xE1],
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            audio: Namco163Audio::default(),
+            audio_enabled: true,
+        }
+    }
+}
+
+impl Mapper for Namco163 {
+    fn box_clone(&self) -> Box<dyn Mapper> {
+        Box::new(self.clone())
+    }
+
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x4800..=0x4FFF => self.audio.read_data(),
+            _ => self.cpu_peek(addr),
+        }
+    }
+
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        let offset = addr as usize;
+        match addr {
+            0x4800..=0x4FFF => self.audio.peek_data(),
+            0x5000..=0x57FF => self.irq_counter as u8,
+            0x5800..=0x5FFF => (self.irq_enabled as u8) << 7 | (self.irq_counter >> 8) as u8,
+            0x6000..=0x7FFF => self.prg_ram[(offset - 0x6000) % self.prg_ram.len()],
+            0x8000..=0xDFFF => {
+                let bank = self.prg_banks[(offset - 0x8000) / 0x2000] as usize;
+                self.prg_rom[bank_index(&self.prg_rom, bank, 0x2000, offset)]
+            }
+            0xE000..=0xFFFF => {
+                let last = self.prg_rom.len() / 0x2000 - 1;
+                self.prg_rom[bank_index(&self.prg_rom, last, 0x2000, offset)]
+            }
+            // open bus
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4800..=0x4FFF => self.audio.write_data(value),
+            0x5000..=0x57FF => {
+                self.irq_counter = (self.irq_counter & 0x7F00) | value as u16;
+                self.irq_pending = false;
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter = (self.irq_counter & 0xFF) | (value as u16 & 0x7F) << 8;
+                self.irq_enabled = value & 0b1000_0000 != 0;
+                self.irq_pending = false;
+            }
+            0x6000..=0x7FFF => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr as usize - 0x6000) % len] = value;
+            }
+            0x8000..=0xBFFF => self.chr_banks[(addr as usize - 0x8000) / 0x800] = value,
+            0xC000..=0xDFFF => self.nametable_banks[(addr as usize - 0xC000) / 0x800] = value,
+            0xE000..=0xE7FF => {
+                self.prg_banks[0] = value & 0b11_1111;
+                self.audio_enabled = value & 0b0100_0000 == 0;
+            }
+            0xE800..=0xEFFF => self.prg_banks[1] = value & 0b11_1111,
+            0xF000..=0xF7FF => self.prg_banks[2] = value & 0b11_1111,
+            0xF800..=0xFFFF => self.audio.write_address(value),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        let bank = self.chr_banks[(addr >> 10) as usize & 0b111] as usize;
+        self.chr.read(bank, 0x400, addr as usize)
+    }
+
+    fn ppu_write(&mut self, addr: u16, value: u8) {
+        let bank = self.chr_banks[(addr >> 10) as usize & 0b111] as usize;
+        self.chr.write(bank, 0x400, addr as usize, value);
+    }
+
+    /// Only nametables from the console's VRAM are supported, in the
+    /// layouts the standard mirroring modes can describe.
+    fn mirroring(&self) -> Mirroring {
+        let pages = self.nametable_banks.map(|bank| bank & 1);
+        match pages {
+            [0, 0, 1, 1] => Mirroring::Horizontal,
+            [0, 0, 0, 0] => Mirroring::SingleScreenLower,
+            [1, 1, 1, 1] => Mirroring::SingleScreenUpper,
+            _ => Mirroring::Vertical,
+        }
+    }
+
+    fn clock(&mut self) {
+        if self.irq_enabled && self.irq_counter < 0x7FFF {
+            self.irq_counter += 1;
+            if self.irq_counter == 0x7FFF {
+                self.irq_pending = true;
+            }
+        }
+        self.audio.clock();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn audio_output(&self) -> f32 {
+        if self.audio_enabled {
+            self.audio.output()
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapper() -> Namco163 {
+        // each 1 KiB CHR bank is filled with its own number
+        let chr_rom: Vec<u8> = (0..8).flat_map(|bank| vec![bank; 0x400]).collect();
+        Namco163::new(
+            vec![0; 0x8000].into_boxed_slice(),
+            vec![0; 0x2000].into_boxed_slice(),
+            chr_rom.into_boxed_slice(),
+        )
+    }
+
+    #[test]
+    fn chr_bank_registers() {
+        let mut mapper = mapper();
+        for addr in 0x8000..=0xBFFF {
+            mapper.cpu_write(addr, 0);
+        }
+        for register in 0..8 {
+            mapper.cpu_write(0x8000 + register * 0x800, 7 - register as u8);
+        }
+        for bank in 0..8 {
+            assert_eq!(mapper.ppu_read(bank * 0x400), 7 - bank as u8);
+        }
+    }
+}
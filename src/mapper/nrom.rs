@@ -0,0 +1,71 @@
+//! Mapper 0, no bankswitching at all.
+
+use crate::header::Mirroring;
+use crate::mapper::{Chr, Mapper};
+
+#[derive(Clone)]
+pub struct Nrom {
+    prg_rom: Box<[u8]>,
+    /// Battery-backed save/work RAM
+    prg_ram: Box<[u8]>,
+    chr: Chr,
+    mirroring: Mirroring,
+}
+
+impl Nrom {
+    pub fn new(
+        prg_rom: Box<[u8]>,
+        prg_ram: Box<[u8]>,
+        chr_rom: Box<[u8]>,
+        mirroring: Mirroring,
+    ) -> Self {
+        Self {
+            prg_rom,
+            prg_ram,
+            chr: Chr::new(chr_rom),
+            mirroring,
+        }
+    }
+}
+
+impl Mapper for Nrom {
+    fn box_clone(&self) -> Box<dyn Mapper> {
+        Box::new(self.clone())
+    }
+
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        self.cpu_peek(addr)
+    }
+
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        let rom_wrap = self.prg_rom.len().saturating_sub(1);
+        let ram_wrap = self.prg_ram.len() - 1;
+        match addr {
+            // open bus
+            0x4020..=0x5FFF => 0,
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize & ram_wrap],
+            0x8000..=0xFFFF => self.prg_rom[(addr - 0x8000) as usize & rom_wrap],
+            e => panic!("Invalid address lookup in Cartridge for CPU: {:x}", e),
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, value: u8) {
+        // there's nothing at $4020-$5FFF and the ROM can't be written
+        if let 0x6000..=0x7FFF = addr {
+            let ram_wrap = self.prg_ram.len() - 1;
+            self.prg_ram[(addr - 0x6000) as usize & ram_wrap] = value;
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr.read(0, 0x2000, addr as usize)
+    }
+
+    fn ppu_write(&mut self, addr: u16, value: u8) {
+        self.chr.write(0, 0x2000, addr as usize, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
@@ -0,0 +1,227 @@
+//! The memory map NSF music expects: 4 KiB PRG banks switched by writing
+//! $5FF8-$5FFF, 8 KiB of RAM at $6000, and whichever sound chips the music
+//! was written for.
+
+use crate::apu::fds::FdsAudio;
+use crate::apu::mmc5::Mmc5Audio;
+use crate::apu::namco163::Namco163Audio;
+use crate::apu::sunsoft5b::Sunsoft5bAudio;
+use crate::apu::vrc6::Vrc6Audio;
+use crate::header::Mirroring;
+use crate::mapper::{bank_index, Chr, Mapper};
+use crate::nsf::Nsf;
+
+/// Bits of `Nsf::expansion_audio`.
+const VRC6: u8 = 0b00_0001;
+const VRC7: u8 = 0b00_0010;
+const FDS: u8 = 0b00_0100;
+const MMC5: u8 = 0b00_1000;
+const NAMCO_163: u8 = 0b01_0000;
+const SUNSOFT_5B: u8 = 0b10_0000;
+
+#[derive(Clone)]
+pub struct NsfBoard {
+    /// The music code and data in 4 KiB banks
+    prg_rom: Box<[u8]>,
+    /// Banks for $8000-$FFFF
+    banks: [u8; 8],
+    /// $6000-$7FFF, or $6000-$FFFF for FDS music which runs from RAM
+    ram: Box<[u8]>,
+    /// FDS music has its banks copied into RAM rather than mapped
+    fds_ram: bool,
+    chr: Chr,
+
+    vrc6: Option<Vrc6Audio>,
+    fds: Option<FdsAudio>,
+    mmc5: Option<Mmc5Audio>,
+    /// The MMC5's 1 KiB of extra RAM at $5C00, used as work RAM
+    mmc5_exram: Box<[u8]>,
+    /// Factors for the MMC5's multiplier at $5205/$5206
+    mmc5_multiplicands: [u8; 2],
+    namco163: Option<Namco163Audio>,
+    sunsoft5b: Option<Sunsoft5bAudio>,
+}
+
+impl NsfBoard {
+    pub fn new(nsf: &Nsf) -> Result<Self, String> {
+        let chips = nsf.expansion_audio;
+        if chips & VRC7 != 0 {
+            warn!("VRC7 FM sound isn't emulated, it will be silent");
+        }
+
+        let fds_ram = chips & FDS != 0;
+        let mut ram = vec![0; if fds_ram { 0xA000 } else { 0x2000 }].into_boxed_slice();
+        let prg_rom = if fds_ram && nsf.banks.is_none() {
+            // no banks, so the music is copied straight into RAM
+            if nsf.load_addr < 0x6000 {
+                return Err(format!(
+                    "FDS NSF load address ${:04X} is below $6000",
+                    nsf.load_addr
+                ));
+            }
+            let start = (nsf.load_addr - 0x6000) as usize;
+            let len = nsf.data.len().min(ram.len() - start);
+            ram[start..start + len].copy_from_slice(&nsf.data[..len]);
+            vec![0; 0x1000]
+        } else {
+            nsf.prg_rom()?
+        };
+
+        Ok(Self {
+            prg_rom: prg_rom.into_boxed_slice(),
+            // NSFs without bankswitching are laid out as if the banks were in order
+            banks: [0, 1, 2, 3, 4, 5, 6, 7],
+            ram,
+            fds_ram,
+            chr: Chr::new(Box::new([])),
+            vrc6: Some(Vrc6Audio::default()).filter(|_| chips & VRC6 != 0),
+            fds: Some(FdsAudio::default()).filter(|_| chips & FDS != 0),
+            mmc5: Some(Mmc5Audio::default()).filter(|_| chips & MMC5 != 0),
+            mmc5_exram: vec![0; 0x400].into_boxed_slice(),
+            mmc5_multiplicands: [0; 2],
+            namco163: Some(Namco163Audio::default()).filter(|_| chips & NAMCO_163 != 0),
+            sunsoft5b: Some(Sunsoft5bAudio::default()).filter(|_| chips & SUNSOFT_5B != 0),
+        })
+    }
+
+    /// Switch a 4 KiB bank, `slot` 0 is $6000.
+    fn switch_bank(&mut self, slot: usize, bank: u8) {
+        if self.fds_ram {
+            // FDS music is copied into RAM instead
+            let start = bank_index(&self.prg_rom, bank as usize, 0x1000, 0);
+            let len = 0x1000.min(self.prg_rom.len() - start);
+            let dest = slot * 0x1000;
+            self.ram[dest..dest + len].copy_from_slice(&self.prg_rom[start..start + len]);
+        } else if slot >= 2 {
+            self.banks[slot - 2] = bank;
+        }
+    }
+}
+
+impl Mapper for NsfBoard {
+    fn box_clone(&self) -> Box<dyn Mapper> {
+        Box::new(self.clone())
+    }
+
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        let value = match addr {
+            0x4800..=0x4FFF => self.namco163.as_mut().map_or(0, |n163| n163.read_data()),
+            0x5010 | 0x5015 => self.mmc5.as_mut().map_or(0, |mmc5| mmc5.read(addr)),
+            _ => self.cpu_peek(addr),
+        };
+        if let Some(mmc5) = &mut self.mmc5 {
+            mmc5.cpu_read(addr, value);
+        }
+        value
+    }
+
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        let offset = addr as usize;
+        match addr {
+            0x4040..=0x4092 => self.fds.as_ref().map_or(0, |fds| fds.read(addr)),
+            0x4800..=0x4FFF => self.namco163.as_ref().map_or(0, |n163| n163.peek_data()),
+            0x5010 | 0x5015 => self.mmc5.as_ref().map_or(0, |mmc5| mmc5.peek(addr)),
+            0x5205 | 0x5206 => {
+                let product = self.mmc5_multiplicands[0] as u16 * self.mmc5_multiplicands[1] as u16;
+                (product >> ((addr - 0x5205) * 8)) as u8
+            }
+            0x5C00..=0x5FF5 => self.mmc5_exram[offset - 0x5C00],
+            0x6000..=0xFFFF if self.fds_ram => self.ram[offset - 0x6000],
+            0x6000..=0x7FFF => self.ram[offset - 0x6000],
+            0x8000..=0xFFFF => {
+                let bank = self.banks[(offset - 0x8000) / 0x1000] as usize;
+                self.prg_rom[bank_index(&self.prg_rom, bank, 0x1000, offset)]
+            }
+            // open bus
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, value: u8) {
+        // a tune can use several chips whose registers overlap, like the
+        // 5B's data port and the N163's address port, so each chip on the
+        // board sees every write
+        if let Some(fds) = &mut self.fds {
+            if let 0x4040..=0x408A = addr {
+                fds.write(addr, value);
+            }
+        }
+        if let Some(n163) = &mut self.namco163 {
+            match addr {
+                0x4800..=0x4FFF => n163.write_data(value),
+                0xF800..=0xFFFF => n163.write_address(value),
+                _ => (),
+            }
+        }
+        if let Some(mmc5) = &mut self.mmc5 {
+            if let 0x5000..=0x5015 = addr {
+                mmc5.write(addr, value);
+            }
+        }
+        if let Some(vrc6) = &mut self.vrc6 {
+            if let 0x9000..=0x9003 | 0xA000..=0xA002 | 0xB000..=0xB002 = addr {
+                vrc6.write(addr, value);
+            }
+        }
+        if let Some(sunsoft5b) = &mut self.sunsoft5b {
+            match addr {
+                0xC000..=0xDFFF => sunsoft5b.write_select(value),
+                0xE000..=0xFFFF => sunsoft5b.write_data(value),
+                _ => (),
+            }
+        }
+
+        let offset = addr as usize;
+        match addr {
+            0x5205 | 0x5206 => self.mmc5_multiplicands[offset - 0x5205] = value,
+            0x5C00..=0x5FF5 => self.mmc5_exram[offset - 0x5C00] = value,
+            0x5FF6..=0x5FFF => self.switch_bank(offset - 0x5FF6, value),
+            0x6000..=0xFFFF if self.fds_ram => self.ram[offset - 0x6000] = value,
+            0x6000..=0x7FFF => self.ram[offset - 0x6000] = value,
+            // sloppy rips write to ROM, which does nothing
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr.read(0, 0x2000, addr as usize)
+    }
+
+    fn ppu_write(&mut self, addr: u16, value: u8) {
+        self.chr.write(0, 0x2000, addr as usize, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::Vertical
+    }
+
+    fn clock(&mut self) {
+        if let Some(vrc6) = &mut self.vrc6 {
+            vrc6.clock();
+        }
+        if let Some(fds) = &mut self.fds {
+            fds.clock();
+        }
+        if let Some(mmc5) = &mut self.mmc5 {
+            mmc5.clock();
+        }
+        if let Some(n163) = &mut self.namco163 {
+            n163.clock();
+        }
+        if let Some(sunsoft5b) = &mut self.sunsoft5b {
+            sunsoft5b.clock();
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.mmc5.as_ref().is_some_and(Mmc5Audio::irq)
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.vrc6.as_ref().map_or(0.0, Vrc6Audio::output)
+            + self.fds.as_ref().map_or(0.0, FdsAudio::output)
+            + self.mmc5.as_ref().map_or(0.0, Mmc5Audio::output)
+            + self.namco163.as_ref().map_or(0.0, Namco163Audio::output)
+            + self.sunsoft5b.as_ref().map_or(0.0, Sunsoft5bAudio::output)
+    }
+}
@@ -0,0 +1,216 @@
+//! Mappers 24 and 26, Konami's VRC6 with its extra sound channels.
+
+use crate::apu::vrc6::Vrc6Audio;
+use crate::header::Mirroring;
+use crate::mapper::{bank_index, Chr, Mapper};
+
+/// The IRQ counter shared by Konami's VRC chips.  It counts CPU cycles, or
+/// scanlines by dividing them by 341/3.
+#[derive(Debug, Clone)]
+pub struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    enabled: bool,
+    enable_after_ack: bool,
+    cycle_mode: bool,
+    prescaler: i16,
+    pub pending: bool,
+}
+
+impl Default for VrcIrq {
+    fn default() -> Self {
+        Self {
+            latch: 0,
+            counter: 0,
+            enabled: false,
+            enable_after_ack: false,
+            cycle_mode: false,
+            prescaler: 341,
+            pending: false,
+        }
+    }
+}
+
+impl VrcIrq {
+    pub fn write_latch(&mut self, value: u8) {
+        self.latch = value;
+    }
+
+    pub fn write_control(&mut self, value: u8) {
+        self.enable_after_ack = value & 0b001 != 0;
+        self.enabled = value & 0b010 != 0;
+        self.cycle_mode = value & 0b100 != 0;
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = 341;
+        }
+        self.pending = false;
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enabled = self.enable_after_ack;
+    }
+
+    /// Clocked every CPU cycle.
+    pub fn clock(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        if !self.cycle_mode {
+            self.prescaler -= 3;
+            if self.prescaler > 0 {
+                return;
+            }
+            self.prescaler += 341;
+        }
+
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Vrc6 {
+    prg_rom: Box<[u8]>,
+    prg_ram: Box<[u8]>,
+    prg_ram_enabled: bool,
+    chr: Chr,
+
+    /// Mapper 26 has A0 and A1 swapped
+    swap_lines: bool,
+
+    /// 16 KiB bank at $8000
+    prg_bank_16: u8,
+    /// 8 KiB bank at $C000, $E000 is fixed to the last bank
+    prg_bank_8: u8,
+    /// 1 KiB banks
+    chr_banks: [u8; 8],
+    mirroring: Mirroring,
+
+    irq: VrcIrq,
+    audio: Vrc6Audio,
+}
+
+impl Vrc6 {
+    pub fn new(
+        prg_rom: Box<[u8]>,
+        prg_ram: Box<[u8]>,
+        chr_rom: Box<[u8]>,
+        swap_lines: bool,
+    ) -> Self {
+        Self {
+            prg_rom,
+            prg_ram,
+            prg_ram_enabled: false,
+            chr: Chr::new(chr_rom),
+            swap_lines,
+            prg_bank_16: 0,
+            prg_bank_8: 0,
+            chr_banks: [0; 8],
+            mirroring: Mirroring::Vertical,
+            irq: VrcIrq::default(),
+            audio: Vrc6Audio::default(),
+        }
+    }
+}
+
+impl Mapper for Vrc6 {
+    fn box_clone(&self) -> Box<dyn Mapper> {
+        Box::new(self.clone())
+    }
+
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        self.cpu_peek(addr)
+    }
+
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        let offset = addr as usize;
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled => {
+                self.prg_ram[(offset - 0x6000) % self.prg_ram.len()]
+            }
+            0x8000..=0xBFFF => {
+                let bank = self.prg_bank_16 as usize;
+                self.prg_rom[bank_index(&self.prg_rom, bank, 0x4000, offset)]
+            }
+            0xC000..=0xDFFF => {
+                let bank = self.prg_bank_8 as usize;
+                self.prg_rom[bank_index(&self.prg_rom, bank, 0x2000, offset)]
+            }
+            0xE000..=0xFFFF => {
+                let last = self.prg_rom.len() / 0x2000 - 1;
+                self.prg_rom[bank_index(&self.prg_rom, last, 0x2000, offset)]
+            }
+            // open bus
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, value: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            if self.prg_ram_enabled {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr as usize - 0x6000) % len] = value;
+            }
+            return;
+        }
+
+        let addr = if self.swap_lines {
+            (addr & !0b11) | (addr & 0b01) << 1 | (addr & 0b10) >> 1
+        } else {
+            addr
+        };
+        match addr & 0xF003 {
+            0x8000..=0x8003 => self.prg_bank_16 = value & 0b1111,
+            0x9000..=0x9003 | 0xA000..=0xA002 | 0xB000..=0xB002 => self.audio.write(addr, value),
+            0xB003 => {
+                self.prg_ram_enabled = value & 0b1000_0000 != 0;
+                self.mirroring = match (value >> 2) & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+            }
+            0xC000..=0xC003 => self.prg_bank_8 = value & 0b1_1111,
+            0xD000..=0xD003 => self.chr_banks[(addr & 0b11) as usize] = value,
+            0xE000..=0xE003 => self.chr_banks[4 + (addr & 0b11) as usize] = value,
+            0xF000 => self.irq.write_latch(value),
+            0xF001 => self.irq.write_control(value),
+            0xF002 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        let bank = self.chr_banks[(addr >> 10) as usize & 0b111] as usize;
+        self.chr.read(bank, 0x400, addr as usize)
+    }
+
+    fn ppu_write(&mut self, addr: u16, value: u8) {
+        let bank = self.chr_banks[(addr >> 10) as usize & 0b111] as usize;
+        self.chr.write(bank, 0x400, addr as usize, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn clock(&mut self) {
+        self.irq.clock();
+        self.audio.clock();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+}
@@ -1,7 +1,25 @@
-use crate::apu::Apu;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::apu::apu::Apu;
+use crate::apu::mixer::Channel;
 use crate::cartridge::Cartridge;
 use crate::cpu::cpu::Cpu;
+use crate::header::Mirroring;
+use crate::input::device::{InputDevices, InputPorts};
+use crate::input::keyboard::KeyboardState;
+use crate::input::power_pad::PowerPadState;
+use crate::input::vaus::VausState;
+use crate::input::zapper::ZapperState;
+use crate::input_macro::InputMacro;
+use crate::logging;
 use crate::ppu::ppu::Ppu;
+use crate::region::Region;
+use crate::wav::WavWriter;
+
+/// The sample rate used for recording if none was set.
+const DEFAULT_SAMPLE_RATE: u32 = 44_100;
 
 pub struct Nes {
     pub cart: Cartridge,
@@ -9,7 +27,24 @@ pub struct Nes {
     pub cpu_ram: [u8; 0x800],
     pub ppu_ram: [u8; 0x4000],
 
-    controller_device: ControllerDevice,
+    input: InputPorts,
+
+    /// Set by a write to $4014, the page to copy into OAM
+    oam_dma_page: Option<u8>,
+    /// Whether OAM DMA is running, DMC DMA steals fewer cycles if so
+    oam_dma_active: bool,
+
+    /// CPU cycles of the current step that have already been run
+    step_cycles: u16,
+
+    region: Region,
+    /// Leftover fraction of a PPU dot from previous CPU cycles, for PAL's
+    /// 3.2 dots per cycle
+    ppu_dot_remainder: u16,
+
+    /// Audio samples not yet taken by `take_audio_samples`
+    audio: Vec<f32>,
+    recording: Option<AudioRecording>,
 
     pub cpu: Cpu,
     pub ppu: Ppu,
@@ -18,71 +53,293 @@ pub struct Nes {
 
 impl Nes {
     pub fn new(cart: Cartridge) -> Self {
-        Self {
+        let region = cart.header.get_region();
+        let mut nes = Self {
             cart,
             cpu_ram: [0u8; 0x800],
             ppu_ram: [0u8; 0x4000],
 
-            controller_device: ControllerDevice::default(),
+            input: InputPorts::default(),
+
+            oam_dma_page: None,
+            oam_dma_active: false,
+            step_cycles: 0,
+
+            region,
+            ppu_dot_remainder: 0,
+
+            audio: Vec::new(),
+            recording: None,
 
             cpu: Cpu::new(),
             ppu: Ppu::new(),
             apu: Apu::new(),
+        };
+        nes.set_region(region);
+        if let Some(id) = nes.cart.header.get_expansion_device() {
+            match InputDevices::from_expansion_id(id) {
+                Some(devices) => nes.set_input_devices(devices),
+                None => warn!(
+                    "Expansion device {:#04X} from the header isn't emulated, plugging in controllers",
+                    id
+                ),
+            }
         }
+        nes
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// Change the console's timing, by default it's picked from the ROM header.
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu.set_region(region);
+        self.apu.set_region(region);
+    }
+
+    /// Press the reset button.  The CPU jumps to the reset vector and the
+    /// APU channels are silenced, everything else carries on.
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+        self.apu.write(0x4015, 0);
+    }
+
+    /// Snapshot everything emulated, to go back to with `load_state`.
+    pub fn save_state(&self) -> SaveState {
+        SaveState {
+            cart: self.cart.clone(),
+            cpu_ram: self.cpu_ram,
+            ppu_ram: Box::new(self.ppu_ram),
+            input: self.input.clone(),
+            oam_dma_page: self.oam_dma_page,
+            oam_dma_active: self.oam_dma_active,
+            step_cycles: self.step_cycles,
+            region: self.region,
+            ppu_dot_remainder: self.ppu_dot_remainder,
+            cpu: self.cpu.clone(),
+            ppu: self.ppu.clone(),
+            apu: self.apu.clone(),
+        }
+    }
+
+    /// Go back to a snapshot from `save_state`.  Audio settings and any
+    /// recording carry on as they are.
+    pub fn load_state(&mut self, state: &SaveState) {
+        self.cart = state.cart.clone();
+        self.cpu_ram = state.cpu_ram;
+        self.ppu_ram = *state.ppu_ram;
+        self.input = state.input.clone();
+        self.oam_dma_page = state.oam_dma_page;
+        self.oam_dma_active = state.oam_dma_active;
+        self.step_cycles = state.step_cycles;
+        self.region = state.region;
+        self.ppu_dot_remainder = state.ppu_dot_remainder;
+        self.cpu = state.cpu.clone();
+        self.ppu = state.ppu.clone();
+        self.apu.load_state(&state.apu);
     }
 
     pub fn step(&mut self) {
         // run cpu
-        let cpu_cyc = self.step_cpu() as usize;
+        let cpu_cyc = self.step_cpu() as u16;
+        self.catch_up(cpu_cyc);
 
-        // run ppu
-        let vblank = self.step_ppu(3 * cpu_cyc as u8);
-        if vblank {
-            self.cpu.nmi();
+        // the DMA starts after the instruction that wrote $4014 finishes
+        if let Some(page) = self.oam_dma_page.take() {
+            let stall = self.oam_dma(page);
+            self.catch_up(stall);
         }
     }
 
-    pub fn set_controller_bits(&mut self, controller: Controller, bits: u8) {
-        match controller {
-            Controller::One => {
-                self.controller_device.controller1 = bits;
-            }
-            Controller::Two => {
-                self.controller_device.controller2 = bits;
+    /// Run one CPU cycle's worth of everything else.  This happens on every
+    /// bus access so that PPU registers are accessed on the right dot.
+    fn tick(&mut self) {
+        self.step_cycles += 1;
+        self.run_cycle();
+
+        // the DMC halts the CPU to fetch its samples
+        if let Some(addr) = self.apu.dmc_dma_address() {
+            // it takes 4 cycles, but only 2 when it lands in the middle of OAM DMA
+            let stall = if self.oam_dma_active { 2 } else { 4 };
+            for _ in 1..stall {
+                self.run_cycle();
             }
+            let value = self.bus_read(addr);
+            self.run_cycle();
+            self.apu.finish_dmc_dma(value);
+        }
+    }
+
+    /// Run the PPU and APU for one CPU cycle.
+    fn run_cycle(&mut self) {
+        self.cpu.cycles += 1;
+
+        let (dots, cycles) = self.region.ppu_dots_per_cpu_cycle();
+        self.ppu_dot_remainder += dots;
+        let ppu_dots = self.ppu_dot_remainder / cycles;
+        self.ppu_dot_remainder %= cycles;
+        self.step_ppu(ppu_dots);
+
+        self.cart.clock();
+        self.apu.set_expansion_output(self.cart.audio_output());
+        self.apu.step();
+        self.cpu.irq = self.apu.irq() || self.cart.irq();
+    }
+
+    /// Run a cycle without the CPU, for the NSF player while it waits
+    /// between calls.
+    pub fn run_idle_cycle(&mut self) {
+        self.tick();
+        self.step_cycles = 0;
+    }
+
+    /// Run whatever part of `cycles` wasn't already run by bus accesses.
+    fn catch_up(&mut self, cycles: u16) {
+        while self.step_cycles < cycles {
+            self.tick();
+        }
+        self.step_cycles = 0;
+    }
+
+    /// Copies a page of CPU memory into OAM.
+    /// Returns the number of cycles the CPU is stalled for.
+    fn oam_dma(&mut self, page: u8) -> u16 {
+        // 1 dummy cycle, 1 more to align to a read cycle if we started on an
+        // odd cycle, then 256 reads and 256 writes
+        let stall = 513 + (self.cpu.cycles & 1) as u16;
+
+        self.oam_dma_active = true;
+        let base = (page as u16) << 8;
+        for offset in 0..0x100 {
+            let value = self.cpu_read(base | offset);
+            self.ppu.write_oam_data(value);
         }
+        self.oam_dma_active = false;
+        stall
+    }
+
+    pub fn input_devices(&self) -> InputDevices {
+        self.input.kind()
+    }
+
+    /// Unplug whatever is in the controller and expansion ports and plug in
+    /// `devices` instead.
+    pub fn set_input_devices(&mut self, devices: InputDevices) {
+        self.input.plug_in(devices);
+    }
+
+    /// Hold exactly the buttons in `bits`, see `Button::bit`.  Controllers 3
+    /// and 4 need a 4 player adapter plugged in to be seen.
+    pub fn set_controller_bits(&mut self, controller: Controller, bits: u8) {
+        self.input.state.controllers.set_held(controller, bits);
+    }
+
+    /// Hold the buttons in `bits` with turbo, so they press and release
+    /// themselves.  They're on top of `set_controller_bits`.
+    pub fn set_turbo_bits(&mut self, controller: Controller, bits: u8) {
+        self.input.state.controllers.set_turbo(controller, bits);
+    }
+
+    /// Make a turbo button stay pressed for `frames` frames then released
+    /// for as many.  The default is
+    /// `controller::DEFAULT_TURBO_RATE`.
+    pub fn set_turbo_rate(&mut self, controller: Controller, button: Button, frames: u8) {
+        self.input
+            .state
+            .controllers
+            .set_turbo_rate(controller, button.bit_index(), frames);
+    }
+
+    /// Play `input_macro` on a controller from the next frame, on top of
+    /// the buttons being held.  Any macro already playing on it stops.
+    pub fn play_macro(&mut self, controller: Controller, input_macro: InputMacro) {
+        self.input
+            .state
+            .controllers
+            .play_macro(controller, input_macro);
+    }
+
+    /// Record the buttons the game sees on a controller each frame, from
+    /// the next frame until `stop_macro_recording`.
+    pub fn start_macro_recording(&mut self, controller: Controller) {
+        self.input.state.controllers.start_recording(controller);
+    }
+
+    /// `None` if the controller wasn't being recorded.
+    pub fn stop_macro_recording(&mut self, controller: Controller) -> Option<InputMacro> {
+        self.input.state.controllers.stop_recording(controller)
+    }
+
+    pub fn is_recording_macro(&self, controller: Controller) -> bool {
+        self.input.state.controllers.is_recording(controller)
+    }
+
+    /// Point the Zapper at a screen pixel, or away from the screen with
+    /// `None`, and pull or release the trigger.
+    pub fn set_zapper(&mut self, aim: Option<(u8, u8)>, trigger: bool) {
+        self.input.state.zapper = ZapperState { aim, trigger };
+    }
+
+    /// Turn the Arkanoid controller's knob to `position`, from
+    /// `vaus::MIN_POSITION` at the left to `vaus::MAX_POSITION` at the
+    /// right, and press or release its button.
+    pub fn set_vaus(&mut self, position: u8, button: bool) {
+        self.input.state.vaus = VausState { position, button };
+    }
+
+    /// Press exactly the Power Pad or Family Trainer buttons in `buttons`,
+    /// bit `n - 1` for button `n` from 1 to 12.
+    pub fn set_power_pad(&mut self, buttons: u16) {
+        self.input.state.power_pad = PowerPadState { buttons };
+    }
+
+    /// Press exactly the Family BASIC keys pressed in `keyboard`.
+    pub fn set_keyboard(&mut self, keyboard: KeyboardState) {
+        self.input.state.keyboard = keyboard;
     }
 }
 
 impl Nes {
     pub fn cpu_read(&mut self, addr: u16) -> u8 {
+        self.tick();
+        self.bus_read(addr)
+    }
+
+    /// What the CPU would read at `addr`, without taking a cycle or any
+    /// side effects, for debugging tools.  The PPU and APU registers read as
+    /// 0 since reading them changes their state.
+    pub fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.cpu_ram[(addr & 0x7FF) as usize],
+            0x2000..=0x401F => 0,
+            0x4020..=0xFFFF => self.cart.cpu_peek(addr),
+        }
+    }
+
+    /// A read that doesn't take a cycle, for DMA.
+    fn bus_read(&mut self, addr: u16) -> u8 {
         match addr {
             0x0000..=0x1FFF => self.cpu_ram[(addr & 0x7FF) as usize],
             0x2000..=0x3FFF => self.ppu_read_reg(addr),
-            0x4014 => {
-                warn!("OAM DMA not yet implemented");
-                0
-            }
+            // write only
+            0x4014 => 0,
             0x4000..=0x4013 | 0x4015 => self.apu.read(addr),
-            0x4016 => self.controller_device.read_p1_next_bit(),
-            0x4017 => self.controller_device.read_p2_next_bit(),
+            0x4016 => self.input.read(0, &self.ppu),
+            0x4017 => self.input.read(1, &self.ppu),
             0x4018..=0x401F => unimplemented!(),
             0x4020..=0xFFFF => self.cart.cpu_view().get(addr),
         }
     }
     pub fn cpu_write(&mut self, addr: u16, v: u8) {
+        self.tick();
         match addr {
-            0x0000..=0x1FFF => self.cpu_ram[addr as usize] = v,
+            0x0000..=0x1FFF => self.cpu_ram[(addr & 0x7FF) as usize] = v,
             0x2000..=0x3FFF => self.ppu_write_reg(addr, v),
-            0x4014 => warn!("OAM DMA not yet implemented"),
+            0x4014 => self.oam_dma_page = Some(v),
             0x4000..=0x4013 | 0x4015 | 0x4017 => self.apu.write(addr, v),
-            0x4016 => {
-                if v & 1 == 1 {
-                    self.controller_device.set_loading_bits();
-                } else {
-                    self.controller_device.load_bits();
-                }
-            }
+            0x4016 => self.input.write(v),
             0x4018..=0x401F => unimplemented!(),
             0x4020..=0xFFFF => self.cart.cpu_view().set(addr, v),
         }
@@ -90,114 +347,319 @@ impl Nes {
 }
 
 impl Nes {
+    /// Map a nametable address into `ppu_ram` according to the cartridge's mirroring.
+    fn nametable_index(&self, addr: u16) -> usize {
+        // $3000-$3EFF mirrors $2000-$2EFF
+        let addr = 0x2000 | (addr as usize & 0x0FFF);
+        let offset = addr & 0x03FF;
+        let table = (addr >> 10) & 0b11;
+        let table = match self.cart.mirroring() {
+            Mirroring::Horizontal => table >> 1,
+            Mirroring::Vertical => table & 1,
+            Mirroring::FourScreen => table,
+            Mirroring::SingleScreenLower => 0,
+            Mirroring::SingleScreenUpper => 1,
+        };
+        0x2000 | (table << 10) | offset
+    }
+
     pub fn ppu_read(&mut self, addr: u16) -> u8 {
         match addr {
             0x0000..=0x1FFF => self.cart.ppu_view().get(addr),
-            0x2000..=0x2FFF => self.ppu_ram[addr as usize],
-            0x3000..=0x3EFF => self.ppu_ram[addr as usize - 0x1000], // mirror
-            0x3F00..=0x3FFF => todo!("Palette RAM index get"),
+            0x2000..=0x3EFF => self.ppu_ram[self.nametable_index(addr)],
+            0x3F00..=0x3FFF => self.ppu.read_palette(addr),
             _ => unreachable!(),
         }
     }
     pub fn ppu_write(&mut self, addr: u16, v: u8) {
         match addr {
-            0x0000..=0x1FFF => unreachable!("Maybe?"),
-            0x2000..=0x2FFF => self.ppu_ram[addr as usize] = v,
-            0x3000..=0x3Eff => self.ppu_ram[addr as usize - 0x1000] = v, // mirror
-            0x3F00..=0x3FFF => todo!("Palette RAM index set"),
+            0x0000..=0x1FFF => self.cart.ppu_view().set(addr, v),
+            0x2000..=0x3EFF => self.ppu_ram[self.nametable_index(addr)] = v,
+            0x3F00..=0x3FFF => self.ppu.write_palette(addr, v),
             _ => unreachable!(),
         }
     }
 }
 
 impl Nes {
-    pub fn draw_screen(&mut self, screen: &mut [u8]) {
-        let color1 = [255, 0, 0];
-        let color2 = [0, 255, 0];
-        let color3 = [0, 0, 255];
-        let color4 = [255, 255, 255];
-        let colors = [color1, color2, color3, color4];
-
-        let mut s_idx = 0;
-        // draw 32 tiles per line, do 1 line for now
-        for i in 0..512 {
-            // 8 bytes per tile
-            for j in 0..8 {
-                let idx = (i * 16) + j;
-                // 8 bits per byte
-                for k in (0..8usize).rev() {
-                    let color_bit1 = (self.ppu_read(idx) >> k) & 1;
-                    let color_bit2 = (self.ppu_read(idx + 8) >> k) & 1;
-                    let color_idx = color_bit1 | (color_bit2 << 1);
-                    for byte in &colors[color_idx as usize] {
-                        screen[s_idx] = *byte;
-                        s_idx += 1;
-                    }
-                }
-            }
+    /// Run until the PPU finishes a frame, then return it.
+    pub fn run_frame(&mut self) -> &[u8] {
+        logging::set_frame(self.ppu.frame_count());
+        self.input.state.controllers.start_frame();
+        while !self.ppu.take_frame_complete() {
+            self.step();
+        }
+        if self.recording.is_some() {
+            self.record_audio();
         }
+        self.frame()
     }
-}
 
-#[derive(Debug, Clone, Default)]
-struct ControllerDevice {
-    /// The bits for controller 1.
-    /// Order is RIGHT LEFT DOWN UP START SELECT B A
-    /// Mapped to 0x4016
-    pub controller1: u8,
-    /// The bits for controller 2.
-    /// Mapped to 0x4017
-    pub controller2: u8,
+    /// The last finished frame, one palette index (0-63) per pixel, 256x240.
+    pub fn frame(&self) -> &[u8] {
+        self.ppu.finished_frame()
+    }
 
-    c1_idx: u8,
-    c2_idx: u8,
+    /// Convert the last finished frame to 8 bit RGB, 3 bytes per pixel.
+    pub fn frame_to_rgb(&self, screen: &mut [u8]) {
+        self.ppu.write_rgb(screen);
+    }
 
-    loading_bits: bool,
-}
+    /// Convert the last finished frame to 8 bit RGBA, 4 bytes per pixel.
+    #[cfg(target_arch = "wasm32")]
+    pub fn frame_to_rgba(&self, screen: &mut [u8]) {
+        self.ppu.write_rgba(screen);
+    }
 
-impl ControllerDevice {
-    fn read_p1_next_bit(&mut self) -> u8 {
-        // official NES controllers return 1 after all buttons have been read
-        let cur_bit = if self.c1_idx > 7 {
-            1
-        } else {
-            (self.controller1 >> (7 - self.c1_idx)) & 1
-        };
-        self.c1_idx += 1;
-        // D1-D4 are not useful for us right now.
-        // we use 0x40 because the top 3 pins are not connected and we're
-        // reading from an address with those bits set so they're still on the bus.
-        0x40 | cur_bit
-    }
-
-    fn read_p2_next_bit(&mut self) -> u8 {
-        // official NES controllers return 1 after all buttons have been read
-        let cur_bit = if self.c2_idx > 7 {
-            1
-        } else {
-            (self.controller2 >> (7 - self.c2_idx)) & 1
+    /// Start producing audio at `sample_rate` Hz, see `take_audio_samples`.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.apu.set_sample_rate(sample_rate);
+    }
+
+    /// Append the mono audio samples produced since the last call to `out`.
+    pub fn take_audio_samples(&mut self, out: &mut Vec<f32>) {
+        self.apu.take_samples(&mut self.audio);
+        out.append(&mut self.audio);
+    }
+
+    /// Record the audio to `path` until `stop_wav_recording` is called or
+    /// the `Nes` is dropped.  With `per_channel`, each APU channel is also
+    /// recorded on its own next to it, e.g. `out-triangle.wav`.
+    pub fn start_wav_recording(&mut self, path: &Path, per_channel: bool) -> io::Result<()> {
+        let sample_rate = match self.apu.sample_rate() {
+            Some(sample_rate) => sample_rate,
+            None => {
+                self.set_sample_rate(DEFAULT_SAMPLE_RATE);
+                DEFAULT_SAMPLE_RATE
+            }
         };
-        self.c2_idx += 1;
-        // D1-D4 are not useful for us right now.
-        // we use 0x40 because the top 3 pins are not connected and we're
-        // reading from an address with those bits set so they're still on the bus.
-        0x40 | cur_bit
+        // don't record audio from before the recording started
+        self.apu.take_samples(&mut self.audio);
+
+        let mut channels = Vec::new();
+        if per_channel {
+            for &channel in Channel::ALL.iter() {
+                let channel_path = channel_wav_path(path, channel);
+                channels.push((channel, WavWriter::create(channel_path, sample_rate)?));
+            }
+        }
+        self.apu.set_channel_samples_enabled(per_channel);
+        self.recording = Some(AudioRecording {
+            mixed: WavWriter::create(path, sample_rate)?,
+            channels,
+        });
+        Ok(())
     }
 
-    fn set_loading_bits(&mut self) {
-        self.loading_bits = true;
+    /// Write out the rest of the recording and close the files.
+    pub fn stop_wav_recording(&mut self) -> io::Result<()> {
+        self.record_audio();
+        self.apu.set_channel_samples_enabled(false);
+        match self.recording.take() {
+            Some(recording) => recording.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// Write the audio produced since the last call to the recording.
+    fn record_audio(&mut self) {
+        let start = self.audio.len();
+        self.apu.take_samples(&mut self.audio);
+
+        if let Some(recording) = &mut self.recording {
+            if let Err(e) = recording.write(&mut self.apu, &self.audio[start..]) {
+                error!("Stopped recording audio: {}", e);
+                self.recording = None;
+                self.apu.set_channel_samples_enabled(false);
+            }
+        }
+
+        // nobody is taking the samples, so don't keep more than a second
+        let max_len = self.apu.sample_rate().unwrap_or(DEFAULT_SAMPLE_RATE) as usize;
+        if self.audio.len() > max_len {
+            let excess = self.audio.len() - max_len;
+            self.audio.drain(..excess);
+        }
+    }
+}
+
+/// Everything emulated at one point in time, see `Nes::save_state`.
+#[derive(Clone)]
+pub struct SaveState {
+    cart: Cartridge,
+    cpu_ram: [u8; 0x800],
+    ppu_ram: Box<[u8; 0x4000]>,
+    input: InputPorts,
+    oam_dma_page: Option<u8>,
+    oam_dma_active: bool,
+    step_cycles: u16,
+    region: Region,
+    ppu_dot_remainder: u16,
+    cpu: Cpu,
+    ppu: Ppu,
+    apu: Apu,
+}
+
+struct AudioRecording {
+    mixed: WavWriter,
+    channels: Vec<(Channel, WavWriter)>,
+}
+
+impl AudioRecording {
+    fn write(&mut self, apu: &mut Apu, mixed: &[f32]) -> io::Result<()> {
+        self.mixed.write_samples(mixed)?;
+        let mut samples = Vec::new();
+        for (channel, writer) in &mut self.channels {
+            samples.clear();
+            apu.take_channel_samples(*channel, &mut samples);
+            writer.write_samples(&samples)?;
+        }
+        Ok(())
     }
-    fn load_bits(&mut self) {
-        if self.loading_bits {
-            self.c1_idx = 0;
-            self.c2_idx = 0;
-            self.loading_bits = false;
+
+    fn finish(self) -> io::Result<()> {
+        self.mixed.finish()?;
+        for (_, writer) in self.channels {
+            writer.finish()?;
         }
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// `out.wav` becomes `out-pulse1.wav` etc.
+fn channel_wav_path(path: &Path, channel: Channel) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}-{}.wav", stem, channel.name()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Controller {
     One,
     Two,
+    /// Only read through a 4 player adapter
+    Three,
+    Four,
+}
+
+impl Controller {
+    pub const ALL: [Controller; 4] = [
+        Controller::One,
+        Controller::Two,
+        Controller::Three,
+        Controller::Four,
+    ];
+
+    /// From 0 for controller 1 to 3 for controller 4.
+    pub fn index(self) -> usize {
+        match self {
+            Controller::One => 0,
+            Controller::Two => 1,
+            Controller::Three => 2,
+            Controller::Four => 3,
+        }
+    }
+
+    /// Controller 1 to 4, `None` for any other number.
+    pub fn from_number(number: usize) -> Option<Self> {
+        Controller::ALL.get(number.checked_sub(1)?).copied()
+    }
+}
+
+/// A button on a standard controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    A,
+    B,
+    Select,
+    Start,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+        Button::Up,
+        Button::Down,
+        Button::Left,
+        Button::Right,
+    ];
+
+    /// The button's bit in the bits passed to `Nes::set_controller_bits`.
+    pub fn bit(self) -> u8 {
+        match self {
+            Button::A => 0b1000_0000,
+            Button::B => 0b0100_0000,
+            Button::Select => 0b0010_0000,
+            Button::Start => 0b0001_0000,
+            Button::Up => 0b0000_1000,
+            Button::Down => 0b0000_0100,
+            Button::Left => 0b0000_0010,
+            Button::Right => 0b0000_0001,
+        }
+    }
+
+    /// Which bit `bit` sets, 7 for A down to 0 for Right.
+    pub fn bit_index(self) -> usize {
+        self.bit().trailing_zeros() as usize
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Button::A => "a",
+            Button::B => "b",
+            Button::Select => "select",
+            Button::Start => "start",
+            Button::Up => "up",
+            Button::Down => "down",
+            Button::Left => "left",
+            Button::Right => "right",
+        }
+    }
+}
+
+impl FromStr for Button {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Button::ALL
+            .iter()
+            .copied()
+            .find(|button| button.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("Unknown button {:?}", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::assembler::assemble;
+
+    #[test]
+    fn dmc_dma_stalls_the_cpu() {
+        let mut nes = assemble(
+            "
+            reset:  lda #$0F
+                    sta $4010
+                    lda #0
+                    sta $4012       ; a 1 byte sample at $C000
+                    sta $4013
+                    lda #$10
+                    sta $4015
+            done:   jmp done
+        ",
+        )
+        .unwrap()
+        .boot();
+        for _ in 0..28 {
+            nes.step();
+        }
+        // reset, LDA, STA, LDA, STA, STA, LDA, STA, and 20 JMPs, plus the
+        // 4 cycles for the one sample byte
+        assert_eq!(nes.cpu.cycles, 7 + 2 + 4 + 2 + 4 + 4 + 2 + 4 + 20 * 3 + 4);
+    }
 }
@@ -0,0 +1,569 @@
+//! NSF and NSFe music rips, and a player that runs them on the emulated CPU
+//! and APU.
+//!
+//! An NSF is just the music code and data from a game, with the addresses of
+//! an INIT routine to call when a song starts and a PLAY routine to call
+//! every frame.  Instead of a driver ROM, the player calls these directly by
+//! pointing the CPU at them with a return address it watches for, and lets
+//! the rest of the console run on its own in between.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::cartridge::Cartridge;
+use crate::nes::Nes;
+use crate::region::Region;
+use crate::wav::WavWriter;
+
+/// Where routines return to.  The CPU never actually runs code here, the
+/// player stops it as soon as it gets there.
+const RETURN_ADDR: u16 = 0x4100;
+
+/// Bits of the expansion audio byte.
+pub const EXPANSION_CHIPS: [(u8, &str); 6] = [
+    (0b00_0001, "VRC6"),
+    (0b00_0010, "VRC7"),
+    (0b00_0100, "FDS"),
+    (0b00_1000, "MMC5"),
+    (0b01_0000, "Namco 163"),
+    (0b10_0000, "Sunsoft 5B"),
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct Nsf {
+    pub song_count: u8,
+    /// 0 based
+    pub starting_song: u8,
+    pub load_addr: u16,
+    pub init_addr: u16,
+    pub play_addr: u16,
+
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+
+    /// Microseconds between PLAY calls
+    pub ntsc_speed: u16,
+    pub pal_speed: u16,
+    /// Bit 0 set for PAL, bit 1 set if it supports both
+    pub region_flags: u8,
+    /// See `EXPANSION_CHIPS`
+    pub expansion_audio: u8,
+
+    /// The initial 4 KiB banks for $8000-$FFFF, if the NSF uses bankswitching
+    pub banks: Option<[u8; 8]>,
+    pub data: Vec<u8>,
+
+    /// Per track lengths and fade outs in milliseconds, from NSFe files
+    pub track_lengths: Vec<Option<u32>>,
+    pub track_fades: Vec<Option<u32>>,
+}
+
+impl Nsf {
+    /// Whether a file starts like an NSF or NSFe.
+    pub fn is_nsf_file(path: &Path) -> bool {
+        match fs::read(path) {
+            Ok(bytes) => bytes.starts_with(b"NESM\x1A") || bytes.starts_with(b"NSFE"),
+            Err(_) => false,
+        }
+    }
+
+    pub fn load_from_file(path: &Path) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.starts_with(b"NESM\x1A") {
+            Self::from_nsf_bytes(bytes)
+        } else if bytes.starts_with(b"NSFE") {
+            Self::from_nsfe_bytes(bytes)
+        } else {
+            Err("Not an NSF or NSFe file".to_string())
+        }
+    }
+
+    fn from_nsf_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 0x80 {
+            return Err(format!("NSF header is truncated, {} bytes", bytes.len()));
+        }
+        let word = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+
+        let mut banks = [0; 8];
+        banks.copy_from_slice(&bytes[0x70..0x78]);
+
+        Ok(Self {
+            song_count: bytes[0x06],
+            starting_song: bytes[0x07].saturating_sub(1),
+            load_addr: word(0x08),
+            init_addr: word(0x0A),
+            play_addr: word(0x0C),
+            title: read_string(&bytes[0x0E..0x2E]),
+            artist: read_string(&bytes[0x2E..0x4E]),
+            copyright: read_string(&bytes[0x4E..0x6E]),
+            ntsc_speed: word(0x6E),
+            pal_speed: word(0x78),
+            region_flags: bytes[0x7A],
+            expansion_audio: bytes[0x7B],
+            // all zeros means no bankswitching
+            banks: if banks == [0; 8] { None } else { Some(banks) },
+            data: bytes[0x80..].to_vec(),
+            track_lengths: Vec::new(),
+            track_fades: Vec::new(),
+        })
+    }
+
+    fn from_nsfe_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut nsf = Self {
+            // NSFe doesn't store the speed, so assume the usual 60/50 Hz
+            ntsc_speed: 16_639,
+            pal_speed: 19_997,
+            song_count: 1,
+            ..Self::default()
+        };
+        let mut found_info = false;
+
+        let mut rest = &bytes[4..];
+        while rest.len() >= 8 {
+            let len = u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            let id = &rest[4..8];
+            rest = &rest[8..];
+            if rest.len() < len {
+                return Err(format!(
+                    "NSFe chunk {} is truncated",
+                    String::from_utf8_lossy(id)
+                ));
+            }
+            let chunk = &rest[..len];
+            rest = &rest[len..];
+
+            match id {
+                b"INFO" => {
+                    if chunk.len() < 8 {
+                        return Err("NSFe INFO chunk is too short".to_string());
+                    }
+                    let word = |i: usize| u16::from_le_bytes([chunk[i], chunk[i + 1]]);
+                    nsf.load_addr = word(0);
+                    nsf.init_addr = word(2);
+                    nsf.play_addr = word(4);
+                    nsf.region_flags = chunk[6];
+                    nsf.expansion_audio = chunk[7];
+                    nsf.song_count = chunk.get(8).copied().unwrap_or(1);
+                    nsf.starting_song = chunk.get(9).copied().unwrap_or(0);
+                    found_info = true;
+                }
+                b"DATA" => nsf.data = chunk.to_vec(),
+                b"BANK" => {
+                    let mut banks = [0; 8];
+                    for (bank, &value) in banks.iter_mut().zip(chunk) {
+                        *bank = value;
+                    }
+                    nsf.banks = Some(banks);
+                }
+                b"RATE" => {
+                    if chunk.len() >= 2 {
+                        nsf.ntsc_speed = u16::from_le_bytes([chunk[0], chunk[1]]);
+                    }
+                    if chunk.len() >= 4 {
+                        nsf.pal_speed = u16::from_le_bytes([chunk[2], chunk[3]]);
+                    }
+                }
+                b"auth" => {
+                    let mut strings = chunk.split(|&b| b == 0).map(read_string);
+                    nsf.title = strings.next().unwrap_or_default();
+                    nsf.artist = strings.next().unwrap_or_default();
+                    nsf.copyright = strings.next().unwrap_or_default();
+                }
+                b"time" => nsf.track_lengths = read_track_times(chunk),
+                b"fade" => nsf.track_fades = read_track_times(chunk),
+                b"NEND" => break,
+                // chunks starting with a capital letter must be understood
+                _ if id[0].is_ascii_uppercase() => {
+                    return Err(format!(
+                        "Unsupported NSFe chunk {}",
+                        String::from_utf8_lossy(id)
+                    ));
+                }
+                _ => debug!("Skipping NSFe chunk {}", String::from_utf8_lossy(id)),
+            }
+        }
+
+        if !found_info {
+            return Err("NSFe file has no INFO chunk".to_string());
+        }
+        Ok(nsf)
+    }
+
+    /// The region to play in, dual region rips are played as NTSC.
+    pub fn region(&self) -> Region {
+        if self.region_flags & 0b11 == 1 {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        }
+    }
+
+    /// Names of the expansion audio chips the music uses.
+    pub fn expansion_chips(&self) -> Vec<&'static str> {
+        EXPANSION_CHIPS
+            .iter()
+            .filter(|(bit, _)| self.expansion_audio & bit != 0)
+            .map(|&(_, name)| name)
+            .collect()
+    }
+
+    /// The length of a track in milliseconds, if the file says.
+    pub fn track_length(&self, track: u8) -> Option<u32> {
+        self.track_lengths.get(track as usize).copied().flatten()
+    }
+
+    /// The fade out of a track in milliseconds, if the file says.
+    pub fn track_fade(&self, track: u8) -> Option<u32> {
+        self.track_fades.get(track as usize).copied().flatten()
+    }
+
+    /// The PRG image for $8000-$FFFF, in 4 KiB banks when bankswitched.
+    pub fn prg_rom(&self) -> Result<Vec<u8>, String> {
+        if self.banks.is_some() {
+            // the data starts partway into its first bank
+            let padding = (self.load_addr & 0x0FFF) as usize;
+            let mut prg = vec![0; padding];
+            prg.extend_from_slice(&self.data);
+            let size = prg.len().next_power_of_two().max(0x8000);
+            prg.resize(size, 0);
+            Ok(prg)
+        } else {
+            if self.load_addr < 0x8000 {
+                return Err(format!(
+                    "NSF load address ${:04X} is below $8000",
+                    self.load_addr
+                ));
+            }
+            let start = (self.load_addr - 0x8000) as usize;
+            let mut prg = vec![0; 0x8000];
+            let len = self.data.len().min(0x8000 - start);
+            prg[start..start + len].copy_from_slice(&self.data[..len]);
+            Ok(prg)
+        }
+    }
+}
+
+/// A fixed size string field, padded with zeros.
+fn read_string(bytes: &[u8]) -> String {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+/// Little endian i32 milliseconds per track, negative for the default.
+fn read_track_times(chunk: &[u8]) -> Vec<Option<u32>> {
+    chunk
+        .chunks_exact(4)
+        .map(|time| {
+            let time = i32::from_le_bytes([time[0], time[1], time[2], time[3]]);
+            if time < 0 {
+                None
+            } else {
+                Some(time as u32)
+            }
+        })
+        .collect()
+}
+
+/// Runs an NSF's INIT and PLAY routines on a `Nes`.
+pub struct NsfPlayer {
+    pub nsf: Nsf,
+    pub nes: Nes,
+    /// CPU cycles between PLAY calls
+    play_period: u64,
+    /// The CPU cycle PLAY is due on next
+    next_play: u64,
+}
+
+impl NsfPlayer {
+    pub fn new(nsf: Nsf) -> Result<Self, String> {
+        let chips = nsf.expansion_chips();
+        if !chips.is_empty() {
+            info!("This NSF uses {}", chips.join(", "));
+        }
+
+        let cart = Cartridge::from_nsf(&nsf)?;
+        let mut nes = Nes::new(cart);
+        nes.set_region(nsf.region());
+        Ok(Self {
+            nsf,
+            nes,
+            play_period: 0,
+            next_play: 0,
+        })
+    }
+
+    /// Reset the console and call INIT for a track, 0 based.  Set the region
+    /// before this, it's passed to INIT and decides the PLAY rate.
+    pub fn start_track(&mut self, track: u8) {
+        let region = self.nes.region();
+        let speed = match region {
+            Region::Pal | Region::Dendy => self.nsf.pal_speed,
+            Region::Ntsc => self.nsf.ntsc_speed,
+        };
+        self.play_period = (speed as f64 * region.cpu_clock_hz() / 1_000_000.0) as u64;
+
+        self.nes.cpu_ram = [0; 0x800];
+        // a fresh board clears the RAM and sound chips
+        self.nes.cart = Cartridge::from_nsf(&self.nsf).expect("NSF loaded before");
+        if let Some(banks) = self.nsf.banks {
+            // FDS music also maps the last two banks at $6000 and $7000
+            if self.nsf.expansion_audio & 0b100 != 0 {
+                self.nes.cpu_write(0x5FF6, banks[6]);
+                self.nes.cpu_write(0x5FF7, banks[7]);
+            }
+            for (i, &bank) in banks.iter().enumerate() {
+                self.nes.cpu_write(0x5FF8 + i as u16, bank);
+            }
+        }
+
+        // silence the APU the way the NSF spec asks
+        for addr in 0x4000..=0x4013 {
+            self.nes.cpu_write(addr, 0);
+        }
+        self.nes.cpu_write(0x4015, 0x0F);
+        self.nes.cpu_write(0x4017, 0x40);
+
+        self.nes.cpu.reset = false;
+        self.nes.cpu.s = 0xFD;
+        self.nes.cpu.p = 0x24;
+        self.nes.cpu.x = match region {
+            Region::Pal | Region::Dendy => 1,
+            Region::Ntsc => 0,
+        };
+        self.nes.cpu.acc = track;
+        self.call(self.nsf.init_addr);
+        self.next_play = self.nes.cpu.cycles + self.play_period;
+    }
+
+    /// Point the CPU at a routine which returns to `RETURN_ADDR`.
+    fn call(&mut self, addr: u16) {
+        let cpu = &mut self.nes.cpu;
+        let ret = RETURN_ADDR - 1;
+        self.nes.cpu_ram[0x100 | cpu.s as usize] = (ret >> 8) as u8;
+        self.nes.cpu_ram[0x100 | cpu.s.wrapping_sub(1) as usize] = ret as u8;
+        cpu.s = cpu.s.wrapping_sub(2);
+        cpu.pc = addr;
+    }
+
+    /// Run an instruction, or a cycle if the last routine has returned.
+    pub fn step(&mut self) {
+        if self.nes.cpu.pc != RETURN_ADDR {
+            self.nes.step();
+        } else if self.nes.cpu.cycles >= self.next_play {
+            self.next_play += self.play_period;
+            self.call(self.nsf.play_addr);
+        } else {
+            self.nes.run_idle_cycle();
+        }
+    }
+
+    /// Run until the PPU finishes a frame, about 1/60th of a second.
+    pub fn run_frame(&mut self) {
+        while !self.nes.ppu.take_frame_complete() {
+            self.step();
+        }
+    }
+}
+
+impl NsfPlayer {
+    /// Play a track into a WAV file for `length` milliseconds, the last
+    /// `fade` of which fade out.  The sample rate must already be set.
+    pub fn render_to_wav(
+        &mut self,
+        track: u8,
+        path: &Path,
+        length: u32,
+        fade: u32,
+    ) -> io::Result<()> {
+        let sample_rate = self.nes.apu.sample_rate().unwrap_or(44_100);
+        self.nes.set_sample_rate(sample_rate);
+        let mut wav = WavWriter::create(path, sample_rate)?;
+
+        let total = (length as u64 * sample_rate as u64 / 1000) as usize;
+        let fade = (fade.min(length) as u64 * sample_rate as u64 / 1000) as usize;
+        let fade_start = total - fade;
+
+        self.start_track(track);
+        let mut samples = Vec::new();
+        let mut written = 0;
+        while written < total {
+            self.run_frame();
+            samples.clear();
+            self.nes.take_audio_samples(&mut samples);
+            samples.truncate(total - written);
+            for (i, sample) in samples.iter_mut().enumerate() {
+                let pos = written + i;
+                if pos >= fade_start {
+                    *sample *= (total - pos) as f32 / fade as f32;
+                }
+            }
+            wav.write_samples(&samples)?;
+            written += samples.len();
+        }
+        wav.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// INIT at $8000 stores the track in $00, PLAY at $8003 counts in $01.
+    const CODE: [u8; 6] = [0x85, 0x00, 0x60, 0xE6, 0x01, 0x60];
+
+    fn nsf_file() -> Vec<u8> {
+        let mut bytes = vec![0; 0x80];
+        bytes[..5].copy_from_slice(b"NESM\x1A");
+        bytes[0x05] = 1;
+        bytes[0x06] = 3;
+        bytes[0x07] = 2;
+        bytes[0x08..0x0E].copy_from_slice(&[0x00, 0x80, 0x00, 0x80, 0x03, 0x80]);
+        bytes[0x0E..0x13].copy_from_slice(b"Title");
+        bytes[0x2E..0x34].copy_from_slice(b"Artist");
+        bytes[0x4E..0x52].copy_from_slice(b"2024");
+        bytes[0x6E..0x70].copy_from_slice(&16_639u16.to_le_bytes());
+        bytes[0x78..0x7A].copy_from_slice(&19_997u16.to_le_bytes());
+        bytes[0x7B] = 0b01_0001;
+        bytes.extend_from_slice(&CODE);
+        bytes
+    }
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_le_bytes().to_vec();
+        chunk.extend_from_slice(id);
+        chunk.extend_from_slice(data);
+        chunk
+    }
+
+    fn nsfe_file(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = b"NSFE".to_vec();
+        for chunk in chunks {
+            bytes.extend_from_slice(chunk);
+        }
+        bytes
+    }
+
+    fn info() -> Vec<u8> {
+        chunk(
+            b"INFO",
+            &[0x00, 0x80, 0x00, 0x80, 0x03, 0x80, 0x01, 0x00, 4, 1],
+        )
+    }
+
+    #[test]
+    fn nsf_header() {
+        let nsf = Nsf::from_bytes(&nsf_file()).unwrap();
+        assert_eq!(nsf.song_count, 3);
+        assert_eq!(nsf.starting_song, 1);
+        assert_eq!(nsf.load_addr, 0x8000);
+        assert_eq!(nsf.init_addr, 0x8000);
+        assert_eq!(nsf.play_addr, 0x8003);
+        assert_eq!(nsf.title, "Title");
+        assert_eq!(nsf.artist, "Artist");
+        assert_eq!(nsf.copyright, "2024");
+        assert_eq!(nsf.ntsc_speed, 16_639);
+        assert_eq!(nsf.pal_speed, 19_997);
+        assert_eq!(nsf.region(), Region::Ntsc);
+        assert_eq!(nsf.expansion_chips(), ["VRC6", "Namco 163"]);
+        assert_eq!(nsf.banks, None);
+        assert_eq!(nsf.data, CODE);
+    }
+
+    #[test]
+    fn nsf_bad_files() {
+        assert!(Nsf::from_bytes(&nsf_file()[..0x7F]).is_err());
+        assert!(Nsf::from_bytes(b"NES\x1A").is_err());
+    }
+
+    #[test]
+    fn nsf_banks() {
+        let mut bytes = nsf_file();
+        bytes[0x08] = 0x10;
+        bytes[0x70..0x78].copy_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+        let nsf = Nsf::from_bytes(&bytes).unwrap();
+        assert_eq!(nsf.banks, Some([0, 0, 0, 0, 0, 0, 0, 1]));
+
+        // the data starts at the load address's offset in the first bank
+        let prg = nsf.prg_rom().unwrap();
+        assert_eq!(prg.len(), 0x8000);
+        assert_eq!(prg[0x10..0x16], CODE);
+    }
+
+    #[test]
+    fn nsf_prg_rom() {
+        let mut nsf = Nsf::from_bytes(&nsf_file()).unwrap();
+        nsf.load_addr = 0xC000;
+        let prg = nsf.prg_rom().unwrap();
+        assert_eq!(prg.len(), 0x8000);
+        assert_eq!(prg[0x4000..0x4006], CODE);
+
+        nsf.load_addr = 0x6000;
+        assert!(nsf.prg_rom().is_err());
+    }
+
+    #[test]
+    fn nsfe_chunks() {
+        let mut times = Vec::new();
+        for time in &[90_000i32, -1] {
+            times.extend_from_slice(&time.to_le_bytes());
+        }
+        let bytes = nsfe_file(&[
+            info(),
+            chunk(b"DATA", &CODE),
+            chunk(b"BANK", &[1, 2]),
+            chunk(b"RATE", &1000u16.to_le_bytes()),
+            chunk(b"auth", b"Title\0Artist\0Copyright\0Ripper\0"),
+            chunk(b"time", &times),
+            chunk(b"fade", &times[..4]),
+            chunk(b"text", b"skipped"),
+            chunk(b"NEND", &[]),
+            chunk(b"JUNK", &[]),
+        ]);
+        let nsf = Nsf::from_bytes(&bytes).unwrap();
+        assert_eq!(nsf.play_addr, 0x8003);
+        assert_eq!(nsf.region(), Region::Pal);
+        assert_eq!(nsf.song_count, 4);
+        assert_eq!(nsf.starting_song, 1);
+        assert_eq!(nsf.data, CODE);
+        assert_eq!(nsf.banks, Some([1, 2, 0, 0, 0, 0, 0, 0]));
+        assert_eq!(nsf.ntsc_speed, 1000);
+        assert_eq!(nsf.pal_speed, 19_997);
+        assert_eq!(nsf.title, "Title");
+        assert_eq!(nsf.artist, "Artist");
+        assert_eq!(nsf.copyright, "Copyright");
+        assert_eq!(nsf.track_length(0), Some(90_000));
+        assert_eq!(nsf.track_length(1), None);
+        assert_eq!(nsf.track_length(2), None);
+        assert_eq!(nsf.track_fade(0), Some(90_000));
+        assert_eq!(nsf.track_fade(1), None);
+    }
+
+    #[test]
+    fn nsfe_bad_files() {
+        let data = chunk(b"DATA", &CODE);
+        assert!(Nsf::from_bytes(&nsfe_file(std::slice::from_ref(&data))).is_err());
+        assert!(Nsf::from_bytes(&nsfe_file(&[info(), chunk(b"JUNK", &[])])).is_err());
+        assert!(Nsf::from_bytes(&nsfe_file(&[chunk(b"INFO", &[0; 7])])).is_err());
+
+        let mut truncated = nsfe_file(&[info(), data]);
+        truncated.pop();
+        assert!(Nsf::from_bytes(&truncated).is_err());
+    }
+
+    #[test]
+    fn player_calls_init_and_play() {
+        let mut player = NsfPlayer::new(Nsf::from_bytes(&nsf_file()).unwrap()).unwrap();
+        player.start_track(2);
+        for _ in 0..10 {
+            player.run_frame();
+        }
+        assert_eq!(player.nes.cpu_ram[0x00], 2);
+        assert!((9..=10).contains(&player.nes.cpu_ram[0x01]));
+    }
+}
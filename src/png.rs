@@ -0,0 +1,89 @@
+//! Writing 8 bit RGB PNG files.
+//!
+//! The image data is stored uncompressed in the zlib stream, which keeps
+//! this tiny at the cost of bigger files.  A 256x240 frame is about 180 KiB.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::checksum::{adler32, crc32, update_crc32};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// The most a stored deflate block can hold.
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+/// Write `rgb`, 3 bytes per pixel row by row, as a PNG.
+pub fn write_rgb(path: impl AsRef<Path>, width: u32, height: u32, rgb: &[u8]) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(&encode_rgb(width, height, rgb))?;
+    file.flush()
+}
+
+/// Encode `rgb`, 3 bytes per pixel row by row, as a PNG.
+pub fn encode_rgb(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+    let stride = width as usize * 3;
+    assert_eq!(
+        rgb.len(),
+        stride * height as usize,
+        "RGB data doesn't match a {}x{} image",
+        width,
+        height
+    );
+
+    let mut png = SIGNATURE.to_vec();
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, truecolour, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+
+    // every row starts with its filter type, 0 for none
+    let mut raw = Vec::with_capacity((stride + 1) * height as usize);
+    for row in rgb.chunks(stride) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    // the CRC covers the type and the data, not the length
+    let crc = update_crc32(crc32(kind), data);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wrap `data` in a zlib stream of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let blocks = (data.len() / MAX_STORED_BLOCK).max(1) + 1;
+    let mut out = Vec::with_capacity(data.len() + blocks * 5 + 6);
+    // deflate with a 32 KiB window, and a check value that makes the header
+    // a multiple of 31
+    out.extend_from_slice(&[0x78, 0x01]);
+
+    let mut chunks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if chunks.peek().is_none() {
+        // an empty stream still needs a final block
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let last = chunks.peek().is_none();
+        let len = chunk.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
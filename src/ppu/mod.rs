@@ -1 +1,2 @@
+pub mod palette;
 pub mod ppu;
@@ -0,0 +1,125 @@
+//! The master palette used to turn the PPU's 6-bit colour indices into RGB.
+//!
+//! The PPU never outputs RGB itself: it outputs a colour index (0-63) plus the
+//! three colour emphasis bits from PPUMASK, and the TV decodes that into a
+//! colour.  We approximate that with a lookup table of 512 entries, one for
+//! every combination of colour index and emphasis bits.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Number of colours the PPU can output.
+pub const NUM_COLORS: usize = 64;
+
+/// Combinations of colour and emphasis bits.
+const NUM_ENTRIES: usize = NUM_COLORS * 8;
+
+/// How much the non-emphasized channels are dimmed when an emphasis bit is set.
+const EMPHASIS_ATTENUATION: f32 = 0.816_328;
+
+/// The default 2C02 palette.
+#[rustfmt::skip]
+const NTSC_COLORS: [[u8; 3]; NUM_COLORS] = [
+    // 0x00
+    [84, 84, 84], [0, 30, 116], [8, 16, 144], [48, 0, 136],
+    [68, 0, 100], [92, 0, 48], [84, 4, 0], [60, 24, 0],
+    [32, 42, 0], [8, 58, 0], [0, 64, 0], [0, 60, 0],
+    [0, 50, 60], [0, 0, 0], [0, 0, 0], [0, 0, 0],
+    // 0x10
+    [152, 150, 152], [8, 76, 196], [48, 50, 236], [92, 30, 228],
+    [136, 20, 176], [160, 20, 100], [152, 34, 32], [120, 60, 0],
+    [84, 90, 0], [40, 114, 0], [8, 124, 0], [0, 118, 40],
+    [0, 102, 120], [0, 0, 0], [0, 0, 0], [0, 0, 0],
+    // 0x20
+    [236, 238, 236], [76, 154, 236], [120, 124, 236], [176, 98, 236],
+    [228, 84, 236], [236, 88, 180], [236, 106, 100], [212, 136, 32],
+    [160, 170, 0], [116, 196, 0], [76, 208, 32], [56, 204, 108],
+    [56, 180, 204], [60, 60, 60], [0, 0, 0], [0, 0, 0],
+    // 0x30
+    [236, 238, 236], [168, 204, 236], [188, 188, 236], [212, 178, 236],
+    [236, 174, 236], [236, 174, 212], [236, 180, 176], [228, 196, 144],
+    [204, 210, 120], [180, 222, 120], [168, 226, 144], [152, 226, 180],
+    [160, 214, 228], [160, 162, 160], [0, 0, 0], [0, 0, 0],
+];
+
+#[derive(Clone)]
+pub struct Palette {
+    /// Indexed by `emphasis << 6 | color`.
+    colors: Box<[[u8; 3]]>,
+}
+
+impl Palette {
+    /// The built-in NTSC palette.
+    pub fn ntsc() -> Self {
+        Self::from_base_colors(&NTSC_COLORS)
+    }
+
+    /// Parse the contents of a `.pal` file.
+    ///
+    /// 192 byte files contain the 64 base colours and the emphasized colours
+    /// are derived from them; 1536 byte files contain all 512 colours.
+    pub fn from_pal_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let colors: Vec<[u8; 3]> = bytes.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect();
+        match bytes.len() {
+            192 => Ok(Self::from_base_colors(&colors)),
+            1536 => Ok(Self {
+                colors: colors.into_boxed_slice(),
+            }),
+            n => Err(format!(
+                "Palette files must be 192 or 1536 bytes long, found {} bytes",
+                n
+            )),
+        }
+    }
+
+    /// Load a `.pal` file from disk.
+    pub fn load_from_file(path: &Path) -> Result<Self, String> {
+        let bytes = fs::read(path)
+            .map_err(|e| format!("Could not read palette {}: {}", path.display(), e))?;
+        Self::from_pal_bytes(&bytes)
+    }
+
+    /// Build the full table by applying every emphasis combination to the 64 base colours.
+    fn from_base_colors(base: &[[u8; 3]]) -> Self {
+        let mut colors = Vec::with_capacity(NUM_ENTRIES);
+        for emphasis in 0..8u8 {
+            for color in base {
+                let mut out = *color;
+                if emphasis != 0 {
+                    // bit 0 emphasizes red, bit 1 green and bit 2 blue, which
+                    // is done by dimming the other two channels.
+                    for (channel, value) in out.iter_mut().enumerate() {
+                        if emphasis & (1 << channel) == 0 {
+                            *value = (*value as f32 * EMPHASIS_ATTENUATION) as u8;
+                        }
+                    }
+                }
+                colors.push(out);
+            }
+        }
+        Self {
+            colors: colors.into_boxed_slice(),
+        }
+    }
+
+    /// Look up the RGB value of a colour index with the given emphasis bits
+    /// (PPUMASK bits 5-7 shifted down to bits 0-2).
+    pub fn rgb(&self, color: u8, emphasis: u8) -> [u8; 3] {
+        self.colors[((emphasis as usize & 0x7) << 6) | (color as usize & 0x3F)]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::ntsc()
+    }
+}
+
+impl fmt::Debug for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Palette")
+            .field("colors", &self.colors.len())
+            .finish()
+    }
+}
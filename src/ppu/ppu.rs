@@ -3,134 +3,764 @@
 //! Used to draw to the screen.
 
 use crate::nes::Nes;
+use crate::ppu::palette::Palette;
+use crate::region::Region;
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+
+/// Open bus bits decay to 0 after roughly 600ms without being refreshed.
+const OPEN_BUS_DECAY_SECONDS: f64 = 0.6;
 
 #[derive(Debug, Clone, Default)]
 pub struct Ppu {
+    region: Region,
     scanline: u16,
     cycle: u16,
 
-    bus: u8, // [TODO: explain]
+    /// Total number of dots run since power on
+    dots: u64,
+
+    /// The latch on the PPU's data bus.  Reading a write-only register (or
+    /// the unused bits of a readable one) returns whatever is left in it.
+    bus: u8,
+    /// The dot each bit of `bus` was last driven on, used to decay it.
+    bus_refreshed: [u64; 8],
 
     // status flags
     ppuctrl: u8,   // $2000
     ppumask: u8,   // $2001
     ppustatus: u8, // $2002
 
+    /// Set when $2002 is read on the dot before vblank starts, which stops
+    /// the flag from being set that frame
+    suppress_vblank: bool,
+
+    /// Number of frames finished since power on
+    frame_count: u64,
+    /// Set when a frame finishes, cleared by `take_frame_complete`
+    frame_complete: bool,
+
     // oam
     oamaddr: u8, // $2003
+    /// Sprite attribute memory, 64 sprites of 4 bytes each
+    oam: Box<[u8]>,
 
-    x_scroll: u8, // $2005 first write
-    y_scroll: u8, // $2005 second write
-    /// whether to write to x_scroll or y_scroll
-    scroll_bit: bool,
+    // scrolling and VRAM addressing, set through $2000, $2005 and $2006
+    /// current VRAM address
+    v: u16,
+    /// temporary VRAM address, the top left of the screen while rendering
+    t: u16,
+    /// fine x scroll
+    fine_x: u8,
+    /// whether the next $2005/$2006 write is the second one
+    write_toggle: bool,
+    /// $2007 reads return the previous read's value
+    read_buffer: u8,
+    /// `v` at the end of the previous scanline, where drawing the next one starts from
+    line_v: u16,
 
-    // ppu
-    ppuaddr: u16, // $2006
+    /// $3F00-$3F1F, 6 bits per entry
+    palette_ram: [u8; 32],
+    /// Used to turn the colour indices in `frame` into RGB
+    palette: Palette,
 
-    oamdma: u8, // $4014 ?
+    /// The palette address (0-31) of every pixel on the current scanline.
+    line: Box<[u8]>,
+    /// Where sprite 0 overlaps the background on the current scanline.
+    sprite0_hit_x: Option<usize>,
+
+    /// The colour index of every pixel of the frame being drawn.
+    frame: Box<[u8]>,
+    /// PPUMASK's emphasis bits at the start of every scanline of `frame`.
+    frame_emphasis: Box<[u8]>,
+    /// The last finished frame, swapped with `frame` at the start of vblank.
+    finished_frame: Box<[u8]>,
+    finished_frame_emphasis: Box<[u8]>,
 }
 
 impl Ppu {
     /// Construct a `Ppu` in its starting state.
     pub fn new() -> Self {
-        Self::default()
+        Self {
+            oam: vec![0; 0x100].into_boxed_slice(),
+            line: vec![0; SCREEN_WIDTH].into_boxed_slice(),
+            frame: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT].into_boxed_slice(),
+            frame_emphasis: vec![0; SCREEN_HEIGHT].into_boxed_slice(),
+            finished_frame: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT].into_boxed_slice(),
+            finished_frame_emphasis: vec![0; SCREEN_HEIGHT].into_boxed_slice(),
+            ..Self::default()
+        }
+    }
+
+    /// Number of frames finished since power on.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Whether a frame has been finished since the last call.
+    pub fn take_frame_complete(&mut self) -> bool {
+        std::mem::replace(&mut self.frame_complete, false)
+    }
+
+    /// The last finished frame, one colour index (0-63) per pixel.
+    pub fn finished_frame(&self) -> &[u8] {
+        &self.finished_frame
+    }
+
+    /// The scanline being drawn, 0-239 are visible.
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    /// The RGB colour of a pixel of the frame being drawn, or `None` if the
+    /// beam hasn't got to it yet or it's off the screen.
+    pub fn drawn_pixel(&self, x: usize, y: usize) -> Option<[u8; 3]> {
+        if x >= SCREEN_WIDTH || y >= SCREEN_HEIGHT {
+            return None;
+        }
+        let scanline = self.scanline as usize;
+        let vblank_scanline = self.region.vblank_scanline() as usize;
+        // at the start of vblank the frame is swapped into `finished_frame`
+        let (frame, emphasis) =
+            if scanline > vblank_scanline || (scanline == vblank_scanline && self.cycle > 1) {
+                (&self.finished_frame, &self.finished_frame_emphasis)
+            } else if y < scanline || (y == scanline && x + 1 < self.cycle as usize) {
+                (&self.frame, &self.frame_emphasis)
+            } else {
+                return None;
+            };
+        Some(self.palette.rgb(frame[y * SCREEN_WIDTH + x], emphasis[y]))
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    /// Replace the palette used for RGB conversion.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    pub fn read_palette(&self, addr: u16) -> u8 {
+        self.palette_ram[palette_index(addr)] & self.grayscale_mask()
+    }
+
+    pub fn write_palette(&mut self, addr: u16, value: u8) {
+        self.palette_ram[palette_index(addr)] = value & 0x3F;
+    }
+
+    /// $2004 read, doesn't increment OAMADDR.
+    fn read_oam_data(&self) -> u8 {
+        let value = self.oam[self.oamaddr as usize];
+        // the unused bits of the attribute byte don't exist
+        if self.oamaddr & 0b11 == 2 {
+            value & 0b1110_0011
+        } else {
+            value
+        }
+    }
+
+    /// $2004 write, also used by OAM DMA.
+    pub fn write_oam_data(&mut self, value: u8) {
+        self.oam[self.oamaddr as usize] = value;
+        self.oamaddr = self.oamaddr.wrapping_add(1);
+    }
+
+    /// Drive some bits of the data bus, `mask` selects which ones.
+    fn refresh_bus(&mut self, value: u8, mask: u8) {
+        self.bus = (self.bus & !mask) | (value & mask);
+        for (bit, refreshed) in self.bus_refreshed.iter_mut().enumerate() {
+            if mask & (1 << bit) != 0 {
+                *refreshed = self.dots;
+            }
+        }
+    }
+
+    /// `OPEN_BUS_DECAY_SECONDS` in dots, which run at different rates in
+    /// each region.
+    fn open_bus_decay_dots(&self) -> u64 {
+        let (dots, cycles) = self.region.ppu_dots_per_cpu_cycle();
+        let dots_per_second = self.region.cpu_clock_hz() * dots as f64 / cycles as f64;
+        (OPEN_BUS_DECAY_SECONDS * dots_per_second) as u64
+    }
+
+    /// Get the value of the data bus after letting stale bits decay.
+    fn open_bus(&mut self) -> u8 {
+        let decay_dots = self.open_bus_decay_dots();
+        for (bit, refreshed) in self.bus_refreshed.iter().enumerate() {
+            if self.dots - refreshed > decay_dots {
+                self.bus &= !(1 << bit);
+            }
+        }
+        self.bus
+    }
+
+    /// $2007 accesses increment the VRAM address by 1 or 32 depending on PPUCTRL.
+    fn increment_vram_addr(&mut self) {
+        let inc = if self.ppuctrl & 0b0100 != 0 { 32 } else { 1 };
+        self.v = self.v.wrapping_add(inc) & 0x3FFF;
+    }
+
+    /// With PPUMASK's grayscale bit set only the grey column of the palette is used.
+    fn grayscale_mask(&self) -> u8 {
+        if self.ppumask & 0b0000_0001 != 0 {
+            0x30
+        } else {
+            0x3F
+        }
+    }
+
+    fn rendering_enabled(&self) -> bool {
+        self.ppumask & 0b0001_1000 != 0
+    }
+
+    /// The changes rendering makes to `v` on the visible and pre-render
+    /// scanlines.  The coarse x increments done while fetching tiles aren't
+    /// emulated since scanlines are drawn all at once from `line_v`.
+    fn update_vram_addr(&mut self) {
+        if !self.rendering_enabled() {
+            return;
+        }
+        match self.cycle {
+            256 => self.increment_y(),
+            // copy the horizontal bits from t
+            257 => self.v = (self.v & !0x041F) | (self.t & 0x041F),
+            // copy the vertical bits from t
+            280..=304 if self.scanline == self.region.prerender_scanline() => {
+                self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
+            }
+            320 => self.line_v = self.v,
+            _ => {}
+        }
+    }
+
+    /// Move `v` down a pixel, wrapping into the next nametable down.
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !0x7000;
+        let mut coarse_y = (self.v & 0x03E0) >> 5;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            self.v ^= 0x0800;
+        } else if coarse_y == 31 {
+            // attribute rows wrap without switching nametable
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.v = (self.v & !0x03E0) | (coarse_y << 5);
+    }
+
+    /// Put the pixel for the current dot into the frame.
+    fn output_pixel(&mut self) {
+        let x = self.cycle as usize - 1;
+        let y = self.scanline as usize;
+        if x == 0 {
+            self.frame_emphasis[y] = self.ppumask >> 5;
+        }
+
+        let addr = if self.rendering_enabled() {
+            if self.sprite0_hit_x == Some(x) {
+                self.ppustatus |= 0b0100_0000;
+            }
+            0x3F00 | self.line[x] as u16
+        } else if self.v >= 0x3F00 {
+            // with rendering off the backdrop is drawn, unless the VRAM
+            // address points into the palette in which case that colour is drawn
+            self.v
+        } else {
+            0x3F00
+        };
+        self.frame[y * SCREEN_WIDTH + x] = self.read_palette(addr);
+    }
+
+    /// Called at the start of vblank to make the frame just drawn visible.
+    fn finish_frame(&mut self) {
+        std::mem::swap(&mut self.frame, &mut self.finished_frame);
+        std::mem::swap(&mut self.frame_emphasis, &mut self.finished_frame_emphasis);
+        self.frame_count += 1;
+        self.frame_complete = true;
+    }
+
+    /// Convert the last finished frame to 8 bit RGB, 3 bytes per pixel.
+    pub fn write_rgb(&self, screen: &mut [u8]) {
+        self.write_pixels(screen, 3);
+    }
+
+    /// Convert the last finished frame to 8 bit RGBA, 4 bytes per pixel.
+    #[cfg(target_arch = "wasm32")]
+    pub fn write_rgba(&self, screen: &mut [u8]) {
+        self.write_pixels(screen, 4);
+    }
+
+    fn write_pixels(&self, screen: &mut [u8], bytes_per_pixel: usize) {
+        for (y, (row, out_row)) in self
+            .finished_frame
+            .chunks_exact(SCREEN_WIDTH)
+            .zip(screen.chunks_exact_mut(SCREEN_WIDTH * bytes_per_pixel))
+            .enumerate()
+        {
+            let emphasis = self.finished_frame_emphasis[y];
+            for (color, out) in row.iter().zip(out_row.chunks_exact_mut(bytes_per_pixel)) {
+                out[..3].copy_from_slice(&self.palette.rgb(*color, emphasis));
+                if bytes_per_pixel == 4 {
+                    out[3] = 0xFF;
+                }
+            }
+        }
+    }
+}
+
+/// Palette RAM is 32 bytes mirrored through $3F00-$3FFF, and the first entry
+/// of each sprite palette ($3F10/$3F14/$3F18/$3F1C) is shared with the
+/// matching background entry.
+fn palette_index(addr: u16) -> usize {
+    let idx = addr as usize & 0x1F;
+    if idx & 0x13 == 0x10 {
+        idx & !0x10
+    } else {
+        idx
     }
 }
 
 impl Nes {
     /// Simulates a certain number of PPU cycles.
-    /// Returns vblank.
-    pub fn step_ppu(&mut self, cycles: u8) -> bool {
-        let mut out = false;
+    pub fn step_ppu(&mut self, cycles: u16) {
+        let vblank_scanline = self.ppu.region.vblank_scanline();
+        let prerender_scanline = self.ppu.region.prerender_scanline();
         for _ in 0..cycles {
             match (self.ppu.scanline, self.ppu.cycle) {
                 // scanlines 0-239 (render)
                 (0..=239, 0) => { /* Idle */ }
-                (0..=239, 1..=256) => { /* Draw */ }
-                (0..=239, 257..=320) => { /* Next SL sprites */ }
+                (0..=239, 1..=256) => {
+                    if self.ppu.cycle == 1 {
+                        self.render_scanline();
+                    }
+                    self.ppu.output_pixel();
+                    self.ppu.update_vram_addr();
+                }
+                (0..=239, 257..=320) => {
+                    /* Next SL sprites */
+                    self.ppu.update_vram_addr();
+                }
                 (0..=239, 321..=336) => { /* Next SL tiles */ }
                 (0..=239, 337..=340) => { /* Dummy fetches */ }
 
-                // scanline 240 (post-render)
-                (240, _) => { /* Do nothing */ }
+                // vblank, 241-260 on NTSC
+                (sl, 1) if sl == vblank_scanline => {
+                    if !self.ppu.suppress_vblank {
+                        self.ppu.ppustatus |= 0b1000_0000;
+                        if self.ppu.ppuctrl & 0b1000_0000 != 0 {
+                            self.cpu.nmi();
+                        }
+                    }
+                    self.ppu.suppress_vblank = false;
+                    self.ppu.finish_frame();
+                }
 
-                // scanlines 241-260 (vblank)
-                (241, 1) => {
-                    // vblank
-                    self.ppu.ppustatus |= 0b1000_0000;
-                    out = (self.ppu.ppuctrl & 0b1000_0000) > 0;
+                // pre-render, 261 on NTSC
+                (sl, 1) if sl == prerender_scanline => {
+                    // clear vblank, sprite 0 hit and sprite overflow
+                    self.ppu.ppustatus &= 0b0001_1111;
+                }
+                (sl, _) if sl == prerender_scanline => {
+                    /* Pre render scanline */
+                    self.ppu.update_vram_addr();
                 }
-                (241..=260, _) => { /* Do nothing */ }
 
-                // scanline 261 (pre-render)
-                (261, _) => { /* Pre render scanline */ }
-                _ => unreachable!(),
+                // post-render (240) and the rest of vblank
+                _ => { /* Do nothing */ }
             }
 
             // advance ppu/scanline
+            self.ppu.dots += 1;
             self.ppu.cycle += 1;
-            if self.ppu.cycle > 340 {
+            // with rendering on, the last dot of the pre-render line is
+            // skipped on odd frames
+            let last_cycle = if self.ppu.scanline == prerender_scanline
+                && self.ppu.region.skips_odd_frame_dot()
+                && self.ppu.frame_count & 1 == 1
+                && self.ppu.rendering_enabled()
+            {
+                339
+            } else {
+                340
+            };
+            if self.ppu.cycle > last_cycle {
                 self.ppu.cycle = 0;
                 self.ppu.scanline += 1;
-                if self.ppu.scanline > 261 {
+                if self.ppu.scanline > prerender_scanline {
                     self.ppu.scanline = 0;
                 }
             }
         }
-        return out;
+    }
+
+    /// Draw the background and sprites of the current scanline into `line`.
+    ///
+    /// Rather than emulating the PPU's fetch pipeline, each scanline is drawn
+    /// in one go at its first dot.
+    fn render_scanline(&mut self) {
+        let y = self.ppu.scanline as usize;
+        let ctrl = self.ppu.ppuctrl;
+        let mask = self.ppu.ppumask;
+        self.ppu.sprite0_hit_x = None;
+
+        // background, 0 for transparent pixels
+        let mut bg = [0u8; SCREEN_WIDTH];
+        if mask & 0b0000_1000 != 0 {
+            let v = self.ppu.line_v;
+            let fine_y = (v >> 12) & 0b111;
+            let coarse_y = (v >> 5) & 0x1F;
+            let mut coarse_x = v & 0x1F;
+            let mut nametable = (v >> 10) & 0b11;
+            let pattern_base = if ctrl & 0b0001_0000 != 0 { 0x1000 } else { 0 };
+
+            // 33 tiles so that the whole line is covered with any fine x
+            for tile in 0..33 {
+                let nametable_base = 0x2000 | (nametable << 10);
+                let tile_idx = self.ppu_read(nametable_base | (coarse_y << 5) | coarse_x);
+                let attribute = self
+                    .ppu_read(nametable_base | 0x3C0 | ((coarse_y >> 2) << 3) | (coarse_x >> 2));
+                let shift = ((coarse_y & 2) << 1) | (coarse_x & 2);
+                let palette = (attribute >> shift) & 0b11;
+
+                let addr = pattern_base + tile_idx as u16 * 16 + fine_y;
+                let lo = self.ppu_read(addr);
+                let hi = self.ppu_read(addr + 8);
+                for bit in 0..8 {
+                    let x = (tile * 8 + bit) as isize - self.ppu.fine_x as isize;
+                    if x < 0 || x >= SCREEN_WIDTH as isize {
+                        continue;
+                    }
+                    let color = ((lo >> (7 - bit)) & 1) | (((hi >> (7 - bit)) & 1) << 1);
+                    if color != 0 {
+                        bg[x as usize] = (palette << 2) | color;
+                    }
+                }
+
+                if coarse_x == 31 {
+                    coarse_x = 0;
+                    nametable ^= 1;
+                } else {
+                    coarse_x += 1;
+                }
+            }
+            if mask & 0b0000_0010 == 0 {
+                bg[..8].iter_mut().for_each(|px| *px = 0);
+            }
+        }
+
+        // sprites, lower OAM indices are drawn in front of higher ones
+        let mut sprites = [0u8; SCREEN_WIDTH];
+        let mut sprite_behind_bg = [false; SCREEN_WIDTH];
+        if mask & 0b0001_0000 != 0 {
+            let height = if ctrl & 0b0010_0000 != 0 { 16 } else { 8 };
+            let mut found = 0;
+            for sprite in 0..64 {
+                let oam = &self.ppu.oam[sprite * 4..sprite * 4 + 4];
+                let (sprite_y, tile, attributes, sprite_x) =
+                    (oam[0] as usize, oam[1] as u16, oam[2], oam[3] as usize);
+                // sprites are drawn one line below their y coordinate
+                if y < sprite_y + 1 || y >= sprite_y + 1 + height {
+                    continue;
+                }
+                found += 1;
+                if found > 8 {
+                    self.ppu.ppustatus |= 0b0010_0000;
+                    break;
+                }
+
+                let mut row = (y - sprite_y - 1) as u16;
+                if attributes & 0b1000_0000 != 0 {
+                    row = height as u16 - 1 - row;
+                }
+                let addr = if height == 16 {
+                    ((tile & 1) * 0x1000) + ((tile & 0xFE) + row / 8) * 16 + (row & 7)
+                } else {
+                    let pattern_base = if ctrl & 0b0000_1000 != 0 { 0x1000 } else { 0 };
+                    pattern_base + tile * 16 + row
+                };
+                let lo = self.ppu_read(addr);
+                let hi = self.ppu_read(addr + 8);
+
+                for bit in 0..8 {
+                    let x = sprite_x + bit;
+                    if x >= SCREEN_WIDTH {
+                        break;
+                    }
+                    let shift = if attributes & 0b0100_0000 != 0 {
+                        bit
+                    } else {
+                        7 - bit
+                    };
+                    let color = ((lo >> shift) & 1) | (((hi >> shift) & 1) << 1);
+                    if color == 0 || (x < 8 && mask & 0b0000_0100 == 0) {
+                        continue;
+                    }
+                    if sprite == 0
+                        && bg[x] != 0
+                        && x != 255
+                        && self.ppu.sprite0_hit_x.is_none()
+                        && self.ppu.ppustatus & 0b0100_0000 == 0
+                    {
+                        self.ppu.sprite0_hit_x = Some(x);
+                    }
+                    if sprites[x] == 0 {
+                        sprites[x] = 0x10 | ((attributes & 0b11) << 2) | color;
+                        sprite_behind_bg[x] = attributes & 0b0010_0000 != 0;
+                    }
+                }
+            }
+        }
+
+        for x in 0..SCREEN_WIDTH {
+            self.ppu.line[x] = if sprites[x] != 0 && (bg[x] == 0 || !sprite_behind_bg[x]) {
+                sprites[x]
+            } else {
+                bg[x]
+            };
+        }
+    }
+
+    /// Whether the next dot to run is `dot` dots after vblank is set.
+    fn is_vblank_start(&self, dot: u16) -> bool {
+        self.ppu.scanline == self.ppu.region.vblank_scanline() && self.ppu.cycle == 1 + dot
     }
 
     pub fn ppu_read_reg(&mut self, address: u16) -> u8 {
         match address & 0x7 {
-            0x0 => unreachable!(),
-            0x1 => unreachable!(),
             0x2 => {
-                // the first 5 bits are whatever was previously written in a PPU register
-                let r = self.ppu.ppustatus | self.ppu.bus;
-                self.ppu.ppustatus &= 0b0110_0000; // clear first bit (and first 5)
+                // reading on the dot before vblank is set reads it as clear
+                // and stops it from being set this frame
+                if self.is_vblank_start(0) {
+                    self.ppu.suppress_vblank = true;
+                }
+                // reading on the dot it's set, or the one after, still
+                // clears it but suppresses the NMI
+                if self.is_vblank_start(1) || self.is_vblank_start(2) {
+                    self.cpu.nmi = false;
+                }
+
+                // only the top 3 bits are driven, the rest are open bus
+                let r = self.ppu.ppustatus & 0b1110_0000;
+                self.ppu.refresh_bus(r, 0b1110_0000);
+                self.ppu.ppustatus &= 0b0110_0000; // clear vblank
+                self.ppu.write_toggle = false;
+                self.ppu.open_bus()
+            }
+            0x4 => {
+                let r = self.ppu.read_oam_data();
+                self.ppu.refresh_bus(r, 0xFF);
                 r
             }
-            0x3 => unreachable!(),
-            0x4 => todo!("OAM data read"),
-            0x5 => unreachable!("PPU scroll registers: CPU should not need to access these"),
-            0x6 => unreachable!(),
             0x7 => {
-                let value = self.ppu_ram[self.ppu.ppuaddr as usize];
-                let inc = (self.ppu.ppuctrl & 0b0100 > 0) as u16;
-                self.ppu.ppuaddr = self.ppu.ppuaddr.wrapping_add(inc) & 0x3FFF;
-                value
+                let addr = self.ppu.v;
+                let r = if addr >= 0x3F00 {
+                    // palette reads aren't buffered and the top 2 bits are
+                    // open bus, but the buffer is filled with the nametable
+                    // byte "under" the palette
+                    self.ppu.read_buffer = self.ppu_read(addr - 0x1000);
+                    let r = self.ppu_read(addr);
+                    self.ppu.refresh_bus(r, 0b0011_1111);
+                    self.ppu.open_bus()
+                } else {
+                    let r = self.ppu.read_buffer;
+                    self.ppu.read_buffer = self.ppu_read(addr);
+                    self.ppu.refresh_bus(r, 0xFF);
+                    r
+                };
+                self.ppu.increment_vram_addr();
+                r
             }
-            _ => unimplemented!(),
+            // $2000, $2001, $2003, $2005 and $2006 are write only
+            _ => self.ppu.open_bus(),
         }
     }
     /// It's undefined behavior to give an address that's not between
     /// 0x2000 and 0x3FFF inclusive.
     pub fn ppu_write_reg(&mut self, address: u16, value: u8) {
-        if address == 0x4014 {
-            todo!("OAM DMA")
-        }
-        self.ppu.bus = value;
+        self.ppu.refresh_bus(value, 0xFF);
         match address & 0x7 {
-            0x0 => self.ppu.ppuctrl = value,
+            0x0 => {
+                let nmi_was_enabled = self.ppu.ppuctrl & 0b1000_0000 != 0;
+                let nmi_enabled = value & 0b1000_0000 != 0;
+                if !nmi_was_enabled && nmi_enabled && self.ppu.ppustatus & 0b1000_0000 != 0 {
+                    // enabling NMI during vblank causes one immediately
+                    self.cpu.nmi();
+                } else if nmi_was_enabled
+                    && !nmi_enabled
+                    && (self.is_vblank_start(1) || self.is_vblank_start(2))
+                {
+                    // disabling it just as vblank starts means it never happens
+                    self.cpu.nmi = false;
+                }
+                self.ppu.ppuctrl = value;
+                // nametable select
+                self.ppu.t = (self.ppu.t & !0x0C00) | ((value as u16 & 0b11) << 10);
+            }
             0x1 => self.ppu.ppumask = value,
-            0x2 => unreachable!(),
+            0x2 => { /* read only */ }
             0x3 => self.ppu.oamaddr = value,
-            0x4 => todo!("OAM data write"),
+            0x4 => self.ppu.write_oam_data(value),
             0x5 => {
-                if !self.ppu.scroll_bit {
-                    self.ppu.x_scroll = value;
+                if !self.ppu.write_toggle {
+                    // coarse and fine x
+                    self.ppu.t = (self.ppu.t & !0x001F) | (value as u16 >> 3);
+                    self.ppu.fine_x = value & 0b111;
+                } else {
+                    // coarse and fine y
+                    self.ppu.t = (self.ppu.t & !0x73E0)
+                        | ((value as u16 & 0b111) << 12)
+                        | ((value as u16 >> 3) << 5);
+                }
+                self.ppu.write_toggle = !self.ppu.write_toggle;
+            }
+            0x6 => {
+                if !self.ppu.write_toggle {
+                    // high byte, bit 14 is cleared
+                    self.ppu.t = (self.ppu.t & 0x00FF) | ((value as u16 & 0x3F) << 8);
                 } else {
-                    self.ppu.y_scroll = value;
+                    self.ppu.t = (self.ppu.t & 0xFF00) | value as u16;
+                    self.ppu.v = self.ppu.t;
                 }
-                self.ppu.scroll_bit = !self.ppu.scroll_bit;
+                self.ppu.write_toggle = !self.ppu.write_toggle;
             }
-            0x6 => self.ppu.ppuaddr = (self.ppu.ppuaddr << 8) | (value as u16),
             0x7 => {
-                self.ppu_ram[self.ppu.ppuaddr as usize] = value;
-                let inc = (self.ppu.ppuctrl & 0b0100 > 0) as u16;
-                self.ppu.ppuaddr = self.ppu.ppuaddr.wrapping_add(inc) & 0x3FFF;
+                self.ppu_write(self.ppu.v, value);
+                self.ppu.increment_vram_addr();
             }
             _ => unreachable!(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::assembler::assemble;
+
+    /// Sets up the palettes, a background tile at `$2021` plus `offset`
+    /// in nametable 0 or 1 and a sprite at (80, 50), then turns rendering
+    /// on.
+    const PROGRAM: &str = "
+        PPUCTRL = $2000
+        PPUMASK = $2001
+        PPUSTATUS = $2002
+        OAMADDR = $2003
+        OAMDATA = $2004
+        PPUSCROLL = $2005
+        PPUADDR = $2006
+        PPUDATA = $2007
+
+        reset:  ldx #$FF
+                txs
+                bit PPUSTATUS
+        wait1:  bit PPUSTATUS
+                bpl wait1
+        wait2:  bit PPUSTATUS
+                bpl wait2
+
+                ; tile 1 is solid colour 1 and tile 2 solid colour 3
+                lda #$00
+                sta PPUADDR
+                lda #$10
+                sta PPUADDR
+                ldx #0
+        chr:    lda tiles,x
+                sta PPUDATA
+                inx
+                cpx #32
+                bne chr
+
+                lda #$3F
+                sta PPUADDR
+                lda #$00
+                sta PPUADDR
+                ldx #0
+        pal:    lda palettes,x
+                sta PPUDATA
+                inx
+                cpx #32
+                bne pal
+
+                lda #>(TILE_ADDR)
+                sta PPUADDR
+                lda #<(TILE_ADDR)
+                sta PPUADDR
+                lda #1
+                sta PPUDATA
+
+                lda #0
+                sta OAMADDR
+                ldx #0
+        oam:    lda sprite,x
+                sta OAMDATA
+                inx
+                cpx #4
+                bne oam
+
+                lda #0
+                sta PPUCTRL
+                sta PPUSCROLL
+                sta PPUSCROLL
+                lda #%00011110
+                sta PPUMASK
+        done:   jmp done
+
+        tiles:  .byte $FF, $FF, $FF, $FF, $FF, $FF, $FF, $FF
+                .byte 0, 0, 0, 0, 0, 0, 0, 0
+                .byte $FF, $FF, $FF, $FF, $FF, $FF, $FF, $FF
+                .byte $FF, $FF, $FF, $FF, $FF, $FF, $FF, $FF
+        palettes:
+                .byte $0F, $16, $27, $30, $0F, $01, $02, $03
+                .byte $0F, $04, $05, $06, $0F, $07, $08, $09
+                .byte $0F, $11, $12, $2A, $0F, $14, $15, $17
+                .byte $0F, $18, $19, $1A, $0F, $1B, $1C, $1D
+        ; y is a line above where it's drawn
+        sprite: .byte 49, 2, 0, 80
+    ";
+
+    fn render(tile_addr: u16) -> Nes {
+        let source = format!("TILE_ADDR = ${:04X}\n{}", tile_addr, PROGRAM);
+        let mut nes = assemble(&source).unwrap().boot();
+        for _ in 0..5 {
+            nes.run_frame();
+        }
+        nes
+    }
+
+    fn pixel(nes: &Nes, x: usize, y: usize) -> u8 {
+        nes.frame()[y * SCREEN_WIDTH + x]
+    }
+
+    #[test]
+    fn background_and_sprites() {
+        let nes = render(0x2021);
+        // backdrop
+        assert_eq!(pixel(&nes, 0, 0), 0x0F);
+        assert_eq!(pixel(&nes, 7, 8), 0x0F);
+        // the background tile at column 1, row 1
+        assert_eq!(pixel(&nes, 8, 8), 0x16);
+        assert_eq!(pixel(&nes, 15, 15), 0x16);
+        assert_eq!(pixel(&nes, 16, 15), 0x0F);
+        // the sprite, drawn a line below its y
+        assert_eq!(pixel(&nes, 80, 49), 0x0F);
+        assert_eq!(pixel(&nes, 80, 50), 0x2A);
+        assert_eq!(pixel(&nes, 87, 57), 0x2A);
+        assert_eq!(pixel(&nes, 88, 57), 0x0F);
+    }
+
+    #[test]
+    fn horizontal_mirroring() {
+        // NROM images from the assembler use horizontal mirroring, so
+        // nametable 1 is the same as nametable 0
+        let nes = render(0x2421);
+        assert_eq!(pixel(&nes, 8, 8), 0x16);
+        assert_eq!(pixel(&nes, 0, 0), 0x0F);
+    }
+}
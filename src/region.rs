@@ -0,0 +1,135 @@
+//! The timing differences between the NTSC, PAL and Dendy consoles.
+
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Region {
+    /// North America and Japan
+    #[default]
+    Ntsc,
+    /// Europe and Australia
+    Pal,
+    /// The Russian famiclone, PAL frame rate with NTSC-like CPU timing
+    Dendy,
+}
+
+/// Noise channel timer periods in CPU cycles.
+const NTSC_NOISE_PERIODS: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+const PAL_NOISE_PERIODS: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
+/// DMC timer periods in CPU cycles.
+const NTSC_DMC_RATES: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+const PAL_DMC_RATES: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+/// CPU cycles at which the APU frame counter clocks the channels, the last
+/// entry is only used in 5-step mode.
+const NTSC_FRAME_COUNTER_STEPS: [u32; 5] = [7457, 14913, 22371, 29829, 37281];
+const PAL_FRAME_COUNTER_STEPS: [u32; 5] = [8313, 16627, 24939, 33253, 41565];
+
+impl Region {
+    pub fn name(self) -> &'static str {
+        match self {
+            Region::Ntsc => "ntsc",
+            Region::Pal => "pal",
+            Region::Dendy => "dendy",
+        }
+    }
+
+    /// Number of scanlines per frame, including vblank and the pre-render line.
+    pub fn scanlines(self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    /// The scanline vblank starts on.
+    pub fn vblank_scanline(self) -> u16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            // Dendy keeps the 20 scanline vblank and puts the extra lines
+            // before it instead
+            Region::Dendy => 291,
+        }
+    }
+
+    /// The last scanline of the frame.
+    pub fn prerender_scanline(self) -> u16 {
+        self.scanlines() - 1
+    }
+
+    /// Only the NTSC PPU skips a dot on odd frames.
+    pub fn skips_odd_frame_dot(self) -> bool {
+        self == Region::Ntsc
+    }
+
+    /// PPU dots per CPU cycle as a fraction (numerator, denominator).
+    pub fn ppu_dots_per_cpu_cycle(self) -> (u16, u16) {
+        match self {
+            Region::Ntsc | Region::Dendy => (3, 1),
+            Region::Pal => (16, 5),
+        }
+    }
+
+    /// CPU cycles per second.
+    pub fn cpu_clock_hz(self) -> f64 {
+        match self {
+            Region::Ntsc => 1_789_773.0,
+            Region::Pal => 1_662_607.0,
+            Region::Dendy => 1_773_448.0,
+        }
+    }
+
+    /// Frames per second.
+    pub fn frame_rate(self) -> f64 {
+        match self {
+            Region::Ntsc => 60.0988,
+            Region::Pal | Region::Dendy => 50.0070,
+        }
+    }
+
+    pub fn noise_periods(self) -> &'static [u16; 16] {
+        match self {
+            Region::Ntsc | Region::Dendy => &NTSC_NOISE_PERIODS,
+            Region::Pal => &PAL_NOISE_PERIODS,
+        }
+    }
+
+    pub fn frame_counter_steps(self) -> &'static [u32; 5] {
+        match self {
+            Region::Ntsc | Region::Dendy => &NTSC_FRAME_COUNTER_STEPS,
+            Region::Pal => &PAL_FRAME_COUNTER_STEPS,
+        }
+    }
+
+    pub fn dmc_rates(self) -> &'static [u16; 16] {
+        match self {
+            Region::Ntsc | Region::Dendy => &NTSC_DMC_RATES,
+            Region::Pal => &PAL_DMC_RATES,
+        }
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ntsc" => Ok(Region::Ntsc),
+            "pal" => Ok(Region::Pal),
+            "dendy" => Ok(Region::Dendy),
+            _ => Err(format!(
+                "Unknown region {:?}, expected ntsc, pal or dendy",
+                s
+            )),
+        }
+    }
+}
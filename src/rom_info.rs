@@ -0,0 +1,292 @@
+//! Everything we can tell about a ROM without running it, for `info`.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::cartridge::Cartridge;
+use crate::checksum::{crc32, sha1};
+use crate::header::{INESHeader, Mirroring};
+use crate::input::device::InputDevices;
+use crate::mapper;
+use crate::region::Region;
+
+const TRAINER_SIZE: usize = 512;
+
+/// CRC-32 and SHA-1 of some ROM data.
+#[derive(Debug, Clone)]
+pub struct Hashes {
+    pub crc32: u32,
+    pub sha1: [u8; 20],
+}
+
+impl Hashes {
+    fn of(data: &[u8]) -> Self {
+        Self {
+            crc32: crc32(data),
+            sha1: sha1(data),
+        }
+    }
+
+    fn crc32_hex(&self) -> String {
+        format!("{:08X}", self.crc32)
+    }
+
+    fn sha1_hex(&self) -> String {
+        self.sha1
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RomInfo {
+    pub nes2: bool,
+    pub mapper: u16,
+    pub submapper: Option<u8>,
+    /// `None` for mappers we don't know the name of
+    pub board: Option<&'static str>,
+    /// Whether this emulator can run it
+    pub supported: bool,
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub prg_ram_size: usize,
+    pub chr_ram_size: usize,
+    pub mirroring: Mirroring,
+    pub battery: bool,
+    pub trainer: bool,
+    pub region: Region,
+    /// The NES 2.0 default expansion device number
+    pub expansion_device: Option<u8>,
+    pub prg: Hashes,
+    /// `None` for CHR RAM carts
+    pub chr: Option<Hashes>,
+    /// PRG and CHR together, which is what most ROM databases use
+    pub rom: Hashes,
+}
+
+impl RomInfo {
+    pub fn load_from_file(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let data = fs::read(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+        Self::from_bytes(&data)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        let (header, prg, chr) = split_rom(data)?;
+
+        let mapper = header.get_mapper_id();
+        Ok(Self {
+            nes2: header.is_nes2(),
+            mapper,
+            submapper: header.get_submapper_id(),
+            board: mapper::board_name(mapper),
+            supported: Cartridge::supports_mapper(mapper),
+            prg_rom_size: prg.len(),
+            chr_rom_size: chr.len(),
+            prg_ram_size: header.get_prg_ram_size(),
+            chr_ram_size: header.get_chr_ram_size(),
+            mirroring: header.get_mirroring(),
+            battery: header.has_battery(),
+            trainer: header.contains_trainer(),
+            region: header.get_region(),
+            expansion_device: header.get_expansion_device(),
+            prg: Hashes::of(prg),
+            chr: if chr.is_empty() {
+                None
+            } else {
+                Some(Hashes::of(chr))
+            },
+            rom: Hashes::of(&[prg, chr].concat()),
+        })
+    }
+
+    pub fn format(&self) -> &'static str {
+        if self.nes2 {
+            "NES 2.0"
+        } else {
+            "iNES"
+        }
+    }
+
+    /// What the header says to plug in, if it's emulated.
+    pub fn input_devices(&self) -> Option<InputDevices> {
+        self.expansion_device
+            .and_then(InputDevices::from_expansion_id)
+    }
+
+    /// One JSON object, for scripts.
+    pub fn to_json(&self) -> String {
+        let mut fields = vec![
+            ("format", json_string(self.format())),
+            ("mapper", self.mapper.to_string()),
+            (
+                "submapper",
+                self.submapper
+                    .map_or_else(|| "null".to_string(), |id| id.to_string()),
+            ),
+            (
+                "board",
+                self.board.map_or_else(|| "null".to_string(), json_string),
+            ),
+            ("supported", self.supported.to_string()),
+            ("prg_rom_size", self.prg_rom_size.to_string()),
+            ("chr_rom_size", self.chr_rom_size.to_string()),
+            ("prg_ram_size", self.prg_ram_size.to_string()),
+            ("chr_ram_size", self.chr_ram_size.to_string()),
+            ("mirroring", json_string(self.mirroring.name())),
+            ("battery", self.battery.to_string()),
+            ("trainer", self.trainer.to_string()),
+            ("region", json_string(self.region.name())),
+            (
+                "expansion_device",
+                self.expansion_device
+                    .map_or_else(|| "null".to_string(), |id| id.to_string()),
+            ),
+            (
+                "input_devices",
+                self.input_devices()
+                    .map_or_else(|| "null".to_string(), |devices| json_string(devices.name())),
+            ),
+            ("prg_crc32", json_string(&self.prg.crc32_hex())),
+            ("prg_sha1", json_string(&self.prg.sha1_hex())),
+        ];
+        match &self.chr {
+            Some(chr) => {
+                fields.push(("chr_crc32", json_string(&chr.crc32_hex())));
+                fields.push(("chr_sha1", json_string(&chr.sha1_hex())));
+            }
+            None => {
+                fields.push(("chr_crc32", "null".to_string()));
+                fields.push(("chr_sha1", "null".to_string()));
+            }
+        }
+        fields.push(("rom_crc32", json_string(&self.rom.crc32_hex())));
+        fields.push(("rom_sha1", json_string(&self.rom.sha1_hex())));
+
+        let fields: Vec<String> = fields
+            .iter()
+            .map(|(name, value)| format!("{}: {}", json_string(name), value))
+            .collect();
+        format!("{{{}}}", fields.join(", "))
+    }
+}
+
+impl fmt::Display for RomInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Format:     {}", self.format())?;
+        write!(f, "Mapper:     {}", self.mapper)?;
+        if let Some(submapper) = self.submapper {
+            write!(f, ".{}", submapper)?;
+        }
+        writeln!(
+            f,
+            " ({}, {})",
+            self.board.unwrap_or("unknown board"),
+            if self.supported {
+                "supported"
+            } else {
+                "not supported"
+            }
+        )?;
+        writeln!(f, "PRG ROM:    {}", size(self.prg_rom_size))?;
+        writeln!(f, "CHR ROM:    {}", size(self.chr_rom_size))?;
+        writeln!(f, "PRG RAM:    {}", size(self.prg_ram_size))?;
+        writeln!(f, "CHR RAM:    {}", size(self.chr_ram_size))?;
+        writeln!(f, "Mirroring:  {}", self.mirroring.name())?;
+        writeln!(f, "Battery:    {}", yes_no(self.battery))?;
+        writeln!(f, "Trainer:    {}", yes_no(self.trainer))?;
+        writeln!(f, "Region:     {}", self.region.name())?;
+        match (self.expansion_device, self.input_devices()) {
+            (Some(_), Some(devices)) => writeln!(f, "Input:      {}", devices.name())?,
+            (Some(id), None) => writeln!(f, "Input:      device {:#04X} (not supported)", id)?,
+            (None, _) => (),
+        }
+        writeln!(
+            f,
+            "PRG:        CRC32 {}  SHA-1 {}",
+            self.prg.crc32_hex(),
+            self.prg.sha1_hex()
+        )?;
+        if let Some(chr) = &self.chr {
+            writeln!(
+                f,
+                "CHR:        CRC32 {}  SHA-1 {}",
+                chr.crc32_hex(),
+                chr.sha1_hex()
+            )?;
+        }
+        write!(
+            f,
+            "PRG+CHR:    CRC32 {}  SHA-1 {}",
+            self.rom.crc32_hex(),
+            self.rom.sha1_hex()
+        )
+    }
+}
+
+/// Split an iNES or NES 2.0 file into its header, PRG ROM and CHR ROM.
+pub fn split_rom(data: &[u8]) -> Result<(INESHeader, &[u8], &[u8]), String> {
+    if data.len() < 16 || &data[0..4] != b"NES\x1A" {
+        return Err("Not an iNES or NES 2.0 ROM".to_string());
+    }
+    let mut header_bytes = [0; 16];
+    header_bytes.copy_from_slice(&data[0..16]);
+    let header = INESHeader::from(header_bytes);
+
+    let prg_start = 16
+        + if header.contains_trainer() {
+            TRAINER_SIZE
+        } else {
+            0
+        };
+    let chr_start = prg_start + header.get_prg_rom_size();
+    let chr_end = chr_start + header.get_chr_rom_size();
+    if data.len() < chr_end {
+        return Err(format!(
+            "The header says the ROM is {} bytes, but the file is only {}",
+            chr_end,
+            data.len()
+        ));
+    }
+    if data.len() > chr_end {
+        warn!("Ignoring {} bytes after the CHR ROM", data.len() - chr_end);
+    }
+    Ok((
+        header,
+        &data[prg_start..chr_start],
+        &data[chr_start..chr_end],
+    ))
+}
+
+fn size(bytes: usize) -> String {
+    if bytes.is_multiple_of(1024) {
+        format!("{} KiB", bytes / 1024)
+    } else {
+        format!("{} bytes", bytes)
+    }
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -1,4 +1,7 @@
+use crate::apu::mixer::Channel;
 use crate::cartridge::Cartridge;
+use crate::input::device::InputDevices;
+use crate::input::keyboard::{FamilyKey, KeyboardState};
 use crate::logging;
 use crate::nes::{Controller, Nes};
 
@@ -6,8 +9,12 @@ struct Emulator {
     nes: Nes,
     cpu_cyc: usize,
     ppu_cyc: usize,
-    /// The screen pixels in 8bit RGB.
+    /// The screen pixels in 8bit RGBA, ready for a canvas `ImageData`.
     screen: Vec<u8>,
+    /// Audio samples taken by the last `take_audio_samples`.
+    audio: Vec<f32>,
+    /// The Family BASIC keys held down
+    keyboard: KeyboardState,
 }
 
 extern "C" {
@@ -60,18 +67,27 @@ pub fn debug(message: &str) {
     }
 }
 
+/// Returns null if the ROM can't be loaded, the error is logged.
 #[no_mangle]
-unsafe extern "C" fn create_emulator(rom_bytes: *mut u8, num_bytes: usize) -> Box<Emulator> {
-    logging::attach_logger(::log::LevelFilter::Debug);
+unsafe extern "C" fn create_emulator(
+    rom_bytes: *mut u8,
+    num_bytes: usize,
+) -> Option<Box<Emulator>> {
+    logging::attach_logger(logging::LogConfig::new(::log::LevelFilter::Debug))
+        .expect("attach logger");
     let rom_bytes: &[u8] = std::slice::from_raw_parts(rom_bytes, num_bytes);
-    let emu = create_emulator_inner(rom_bytes);
-
-    Box::new(emu)
+    match create_emulator_inner(rom_bytes) {
+        Ok(emu) => Some(Box::new(emu)),
+        Err(e) => {
+            error!("{}", e);
+            None
+        }
+    }
 }
 
-fn create_emulator_inner(rom_bytes: &[u8]) -> Emulator {
+fn create_emulator_inner(rom_bytes: &[u8]) -> Result<Emulator, String> {
     let buf_reader = std::io::BufReader::new(rom_bytes);
-    let cartridge = Cartridge::load_from_bytes(buf_reader);
+    let cartridge = Cartridge::load_from_bytes(buf_reader)?;
     let nes = Nes::new(cartridge);
 
     info!("hello from wasm!");
@@ -80,12 +96,14 @@ fn create_emulator_inner(rom_bytes: &[u8]) -> Emulator {
     let mut cpu_cyc = 7;
     let mut ppu_cyc = 0;
 
-    Emulator {
+    Ok(Emulator {
         nes,
         cpu_cyc,
         ppu_cyc,
-        screen: vec![0; 256 * 240 * 3],
-    }
+        screen: vec![0; 256 * 240 * 4],
+        audio: Vec::new(),
+        keyboard: KeyboardState::default(),
+    })
 }
 
 /// controller state is a bitmap of all the buttons in this order
@@ -100,27 +118,120 @@ extern "C" fn run_frame(emulator: &mut Emulator, controller_state: u16) {
     emulator.nes.set_controller_bits(Controller::One, p1_bits);
     emulator.nes.set_controller_bits(Controller::Two, p2_bits);
 
-    for i in 0..10000 {
-        emulator.nes.step();
+    draw_frame(emulator);
+}
+
+/// Like `run_frame` for four players, a byte each from player 1 in the
+/// lowest byte to player 4 in the highest.  Players 3 and 4 need a 4 player
+/// adapter plugged in with `set_input_devices`.
+#[no_mangle]
+extern "C" fn run_frame_four_players(emulator: &mut Emulator, controller_state: u32) {
+    for (index, &controller) in Controller::ALL.iter().enumerate() {
+        let bits = (controller_state >> (index * 8)) as u8;
+        emulator.nes.set_controller_bits(controller, bits);
     }
 
-    /*
-    for y in 0..240 {
-        for x in 0..256 {
-            let idx = ((y * 256) + x) * 3;
-            //rustfmt: ignore
-            emulator.screen[idx + 0] = if x < 100 { 255 } else { 0 };
-            emulator.screen[idx + 1] = if x >= 100 && x < 200 { 255 } else { 0 };
-            emulator.screen[idx + 2] = if x >= 200 && x < 300 { 255 } else { 0 };
-        }
-    }*/
-    emulator.nes.draw_screen(&mut emulator.screen);
+    draw_frame(emulator);
+}
+
+fn draw_frame(emulator: &mut Emulator) {
+    emulator.nes.run_frame();
+    emulator.nes.frame_to_rgba(&mut emulator.screen);
 
     unsafe {
         draw_screen(emulator.screen.as_mut_ptr() as usize);
     }
 }
 
+/// Plug in the devices for an NES 2.0 default expansion device number, e.g.
+/// 1 for two controllers, 2 for the Four Score or 8 for a Zapper.
+#[no_mangle]
+extern "C" fn set_input_devices(emulator: &mut Emulator, expansion_id: u8) {
+    match InputDevices::from_expansion_id(expansion_id) {
+        Some(devices) => emulator.nes.set_input_devices(devices),
+        None => warn!("No input devices for expansion device {}", expansion_id),
+    }
+}
+
+/// Point the Zapper at a screen pixel, a negative or off screen `x` or `y`
+/// points it away from the screen.
+#[no_mangle]
+extern "C" fn set_zapper(emulator: &mut Emulator, x: i32, y: i32, trigger: bool) {
+    let aim = if (0..256).contains(&x) && (0..240).contains(&y) {
+        Some((x as u8, y as u8))
+    } else {
+        None
+    };
+    emulator.nes.set_zapper(aim, trigger);
+}
+
+/// Turn the Arkanoid controller's knob, from 98 at the far left to 242 at
+/// the far right, and press or release its button.
+#[no_mangle]
+extern "C" fn set_vaus(emulator: &mut Emulator, position: u8, button: bool) {
+    emulator.nes.set_vaus(position, button);
+}
+
+/// Press exactly the Power Pad or Family Trainer buttons in `buttons`, bit
+/// `n - 1` for button `n` from 1 to 12.
+#[no_mangle]
+extern "C" fn set_power_pad(emulator: &mut Emulator, buttons: u16) {
+    emulator.nes.set_power_pad(buttons);
+}
+
+/// Press or release a Family BASIC key, numbered by its place in the
+/// keyboard matrix: `row * 8 + column * 4 + line`, with line 0 for D1.
+#[no_mangle]
+extern "C" fn set_keyboard_key(emulator: &mut Emulator, key: u8, pressed: bool) {
+    match FamilyKey::all().nth(key as usize) {
+        Some(key) => {
+            emulator.keyboard.set(key, pressed);
+            emulator.nes.set_keyboard(emulator.keyboard);
+        }
+        None => warn!("No Family BASIC key {}", key),
+    }
+}
+
+/// Start producing audio at `sample_rate` Hz, usually the AudioContext's rate.
+#[no_mangle]
+extern "C" fn set_sample_rate(emulator: &mut Emulator, sample_rate: u32) {
+    emulator.nes.set_sample_rate(sample_rate);
+}
+
+/// Collect the audio produced since the last call, returning the number of
+/// samples.  They can then be read from `audio_buffer`.
+#[no_mangle]
+extern "C" fn take_audio_samples(emulator: &mut Emulator) -> usize {
+    emulator.audio.clear();
+    emulator.nes.take_audio_samples(&mut emulator.audio);
+    emulator.audio.len()
+}
+
+/// Pointer to the f32 samples collected by `take_audio_samples`.
+#[no_mangle]
+extern "C" fn audio_buffer(emulator: &Emulator) -> *const f32 {
+    emulator.audio.as_ptr()
+}
+
+/// Choose which APU channels are heard.  Bits 0-5 are pulse 1, pulse 2,
+/// triangle, noise, DMC and the cartridge's expansion audio.
+#[no_mangle]
+extern "C" fn set_channel_mask(emulator: &mut Emulator, mask: u8) {
+    let mixer = emulator.nes.apu.mixer_mut();
+    for &channel in &Channel::ALL {
+        mixer.set_enabled(channel, mask & (1 << channel.index()) != 0);
+    }
+}
+
+/// Scale an APU channel's volume, `channel` is numbered as in `set_channel_mask`.
+#[no_mangle]
+extern "C" fn set_channel_volume(emulator: &mut Emulator, channel: u8, volume: f32) {
+    match Channel::ALL.get(channel as usize) {
+        Some(&channel) => emulator.nes.apu.mixer_mut().set_volume(channel, volume),
+        None => warn!("No APU channel {}", channel),
+    }
+}
+
 #[no_mangle]
 extern "C" fn allocate_bytes(num_bytes: usize) -> *mut u8 {
     let mut bytes = vec![0; num_bytes];
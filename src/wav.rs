@@ -0,0 +1,131 @@
+//! Writing 16 bit mono PCM WAV files.
+//!
+//! The header's sizes are rewritten after every block of samples, so the
+//! file is valid even if the emulator is killed mid-recording.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Size of the RIFF, fmt and data chunk headers.
+const HEADER_SIZE: u32 = 44;
+
+pub struct WavWriter {
+    file: BufWriter<File>,
+    /// Bytes of sample data written
+    data_size: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: impl AsRef<Path>, sample_rate: u32) -> io::Result<Self> {
+        let mut writer = Self {
+            file: BufWriter::new(File::create(path)?),
+            data_size: 0,
+        };
+        writer.write_header(sample_rate)?;
+        Ok(writer)
+    }
+
+    fn write_header(&mut self, sample_rate: u32) -> io::Result<()> {
+        let channels: u16 = 1;
+        let bits_per_sample: u16 = 16;
+        let block_align = channels * bits_per_sample / 8;
+
+        let file = &mut self.file;
+        file.write_all(b"RIFF")?;
+        file.write_all(&(HEADER_SIZE - 8).to_le_bytes())?;
+        file.write_all(b"WAVE")?;
+
+        file.write_all(b"fmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        // PCM
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&channels.to_le_bytes())?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        file.write_all(&block_align.to_le_bytes())?;
+        file.write_all(&bits_per_sample.to_le_bytes())?;
+
+        file.write_all(b"data")?;
+        file.write_all(&0u32.to_le_bytes())
+    }
+
+    /// Append samples between -1.0 and 1.0, louder ones are clipped.
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        for &sample in samples {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.file.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_size += samples.len() as u32 * 2;
+        self.update_sizes()
+    }
+
+    /// Rewrite the chunk sizes in the header to match the data written.
+    fn update_sizes(&mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file
+            .write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(40))?;
+        self.file.write_all(&self.data_size.to_le_bytes())?;
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.flush()
+    }
+
+    /// Flush everything to disk.  Dropping the writer does the same but
+    /// can't report errors.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.update_sizes()
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.update_sizes() {
+            error!("Failed to finish WAV file: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn header_and_samples() {
+        let path = std::env::temp_dir().join(format!("nerust-test-{}.wav", std::process::id()));
+        let mut wav = WavWriter::create(&path, 44_100).unwrap();
+        wav.write_samples(&[0.0, 1.0]).unwrap();
+        // the sizes are kept up to date while recording
+        assert_eq!(fs::read(&path).unwrap()[40..44], 4u32.to_le_bytes());
+        wav.write_samples(&[-1.0, 2.0]).unwrap();
+        wav.finish().unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let u32_at = |at: usize| {
+            u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+        };
+        let u16_at = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32_at(4), 36 + 8);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(16), 16);
+        // PCM, mono, 44.1kHz, 88200 bytes per second, 2 bytes per frame, 16 bit
+        assert_eq!(u16_at(20), 1);
+        assert_eq!(u16_at(22), 1);
+        assert_eq!(u32_at(24), 44_100);
+        assert_eq!(u32_at(28), 88_200);
+        assert_eq!(u16_at(32), 2);
+        assert_eq!(u16_at(34), 16);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(u32_at(40), 8);
+
+        let samples: Vec<i16> = bytes[44..]
+            .chunks(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+            .collect();
+        assert_eq!(samples, [0, i16::MAX, -i16::MAX, i16::MAX]);
+    }
+}
@@ -1,74 +1,651 @@
-//use nes::{Button, Nes};
-use sdl2::{self, event::Event, keyboard::Keycode, render::Canvas, video::Window, Sdl};
+//! The desktop frontend: an SDL2 window, keyboard, gamepad and mouse input
+//! and an audio queue.
+
+use std::mem;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+use sdl2::controller::{Axis, Button as PadButton, GameController};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use sdl2::{EventPump, GameControllerSubsystem, Sdl};
+
+use crate::args::Settings;
+use crate::bindings::{Action, Bindings, Hotkey, Input};
+use crate::input::device::InputDevices;
+use crate::input::keyboard::{FamilyKey, KeyboardState};
+use crate::input::vaus::{self, VausState};
+use crate::input::zapper::ZapperState;
+use crate::input_macro::InputMacro;
+use crate::nes::{Button, Controller, Nes, SaveState};
+use crate::png;
+use crate::{numbered_path, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// How many frames of audio can be queued before we drop it to catch up.
+const MAX_QUEUED_FRAMES: u32 = 4;
+
+/// How far behind we can fall before giving up on catching up, e.g. after
+/// the window was dragged.
+const MAX_LAG_FRAMES: u32 = 3;
+
+/// How far a stick has to be pushed to count as pressed, out of 32767.
+const AXIS_THRESHOLD: i16 = 16_000;
+
+/// With the Family BASIC keyboard plugged in, switches the PC keyboard
+/// between typing on it and the usual bindings.
+const KEYBOARD_TOGGLE_KEY: Keycode = Keycode::ScrollLock;
+
+/// The keys for Power Pad buttons 1 to 12, in the same 3 rows of 4.
+const POWER_PAD_KEYS: [Keycode; 12] = [
+    Keycode::Num1,
+    Keycode::Num2,
+    Keycode::Num3,
+    Keycode::Num4,
+    Keycode::Q,
+    Keycode::W,
+    Keycode::E,
+    Keycode::R,
+    Keycode::A,
+    Keycode::S,
+    Keycode::D,
+    Keycode::F,
+];
+
+/// A key or gamepad input, resolved to SDL's types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Trigger {
+    Key(Keycode),
+    /// On the Nth gamepad, or any when bound to a hotkey
+    PadButton(Option<usize>, PadButton),
+    PadAxis(Option<usize>, Axis, bool),
+}
+
+impl Trigger {
+    fn resolve(action: Action, input: &Input) -> Result<Self, String> {
+        let pad = match action {
+            Action::Button(controller, _) | Action::Turbo(controller, _) => {
+                Some(controller.index())
+            }
+            Action::Hotkey(_) | Action::Macro(_) => None,
+        };
+        match input {
+            Input::Key(name) => Keycode::from_name(name)
+                .map(Trigger::Key)
+                .ok_or_else(|| format!("Unknown key {:?}", name)),
+            Input::PadButton(name) => PadButton::from_string(name)
+                .map(|button| Trigger::PadButton(pad, button))
+                .ok_or_else(|| format!("Unknown gamepad button {:?}", name)),
+            Input::PadAxis { axis, positive } => Axis::from_string(axis)
+                .map(|axis| Trigger::PadAxis(pad, axis, *positive))
+                .ok_or_else(|| format!("Unknown gamepad axis {:?}", axis)),
+        }
+    }
+
+    /// Whether this binding is triggered by `held`, which is on a
+    /// particular gamepad.
+    fn matches(self, held: Trigger) -> bool {
+        let pad_matches = |pad: Option<usize>, held_pad| pad.is_none() || pad == held_pad;
+        match (self, held) {
+            (Trigger::Key(key), Trigger::Key(held_key)) => key == held_key,
+            (Trigger::PadButton(pad, button), Trigger::PadButton(held_pad, held_button)) => {
+                pad_matches(pad, held_pad) && button == held_button
+            }
+            (
+                Trigger::PadAxis(pad, axis, positive),
+                Trigger::PadAxis(held_pad, held_axis, held_positive),
+            ) => pad_matches(pad, held_pad) && axis == held_axis && positive == held_positive,
+            _ => false,
+        }
+    }
+}
 
 pub struct EmuWindow {
-    sdl_context: Sdl,
+    _sdl_context: Sdl,
     canvas: Canvas<Window>,
+    event_pump: EventPump,
+    /// `None` if there's no audio device
+    audio: Option<AudioQueue<f32>>,
+    /// `None` if gamepads aren't available
+    controller_subsystem: Option<GameControllerSubsystem>,
+    /// In the order they were plugged in, the Nth is player N's
+    pads: Vec<GameController>,
+
+    bindings: Vec<(Trigger, Action)>,
+    /// The inputs being held, gamepad ones on a particular gamepad
+    held: Vec<Trigger>,
+    /// Hotkeys and macros pressed since they were last handled
+    pressed: Vec<Action>,
+    turbo_rates: Vec<(Controller, Button, u8)>,
+    /// The controller to play each `Action::Macro` on
+    macros: Vec<(Controller, InputMacro)>,
+    /// What the last recording got on each controller
+    recorded: Vec<(Controller, InputMacro)>,
+    /// Aimed with the mouse and fired with the left button
+    zapper: ZapperState,
+    /// Turned by moving the mouse across the window, with the left button
+    vaus: VausState,
+    /// Pressed with `POWER_PAD_KEYS`
+    power_pad: u16,
+    /// Typed on the keyboard
+    keyboard: KeyboardState,
+    /// Whether the Family BASIC keyboard is plugged in
+    family_keyboard: bool,
+    /// Whether the PC keyboard only types on the Family BASIC keyboard, so
+    /// that every key can be typed without setting off hotkeys
+    keyboard_captured: bool,
+
+    paused: bool,
+    /// For the save and load state hotkeys
+    state: Option<SaveState>,
+    /// Screenshots are numbered versions of this
+    screenshot_path: PathBuf,
+    /// The ROM, which recorded macros are named after
+    rom_file: PathBuf,
 }
 
 impl EmuWindow {
-    pub fn new() -> Result<Self, String> {
+    /// Open a window `settings.scale` times the size of the screen, and an
+    /// audio queue at the sample rate, which SDL resamples if the device
+    /// can't do it.
+    pub fn new(settings: &Settings) -> Result<Self, String> {
+        let bindings = resolve_bindings(&settings.bindings)?;
+
         let sdl_context = sdl2::init()?;
         let video_subsystem = sdl_context.video()?;
-        let window = match video_subsystem
-            .window("Rust NES emulator", 800, 600)
+        let scale = settings.scale;
+        let window = video_subsystem
+            .window("NEruSt", SCREEN_WIDTH * scale, SCREEN_HEIGHT * scale)
             .position_centered()
+            .resizable()
+            .build()
+            .map_err(|e| format!("Could not create window: {}", e))?;
+        let mut canvas = window
+            .into_canvas()
             .build()
-        {
-            Ok(v) => v,
-            _ => return Err("Could not create window".to_owned()),
+            .map_err(|e| format!("Could not create canvas: {}", e))?;
+        // letterbox to whole multiples of the screen size when resized
+        canvas
+            .set_logical_size(SCREEN_WIDTH, SCREEN_HEIGHT)
+            .map_err(|e| e.to_string())?;
+        canvas.set_integer_scale(true)?;
+
+        let audio = match open_audio(&sdl_context, settings.sample_rate) {
+            Ok(audio) => Some(audio),
+            Err(e) => {
+                warn!("Playing without sound: {}", e);
+                None
+            }
         };
-        let canvas = match window.into_canvas().software().build() {
-            Ok(v) => v,
-            _ => return Err("Could not create canvas".to_owned()),
+        // gamepads already plugged in show up as added events too
+        let controller_subsystem = match sdl_context.game_controller() {
+            Ok(subsystem) => Some(subsystem),
+            Err(e) => {
+                warn!("Playing without gamepads: {}", e);
+                None
+            }
         };
+        let event_pump = sdl_context.event_pump()?;
         Ok(Self {
-            sdl_context,
+            _sdl_context: sdl_context,
             canvas,
+            event_pump,
+            audio,
+            controller_subsystem,
+            pads: Vec::new(),
+
+            bindings,
+            held: Vec::new(),
+            pressed: Vec::new(),
+            turbo_rates: settings.bindings.turbo_rates().to_vec(),
+            macros: settings.bindings.macros().to_vec(),
+            recorded: Vec::new(),
+            zapper: ZapperState::default(),
+            vaus: VausState::default(),
+            power_pad: 0,
+            keyboard: KeyboardState::default(),
+            family_keyboard: false,
+            keyboard_captured: true,
+
+            paused: false,
+            state: None,
+            screenshot_path: settings.rom_file.with_extension("png"),
+            rom_file: settings.rom_file.clone(),
         })
     }
 
-    pub fn draw(&mut self) {
-        self.canvas.clear();
-        self.canvas.present();
+    /// Run `nes` at its region's frame rate until the window is closed.
+    pub fn run(&mut self, nes: &mut Nes) -> Result<(), String> {
+        let texture_creator = self.canvas.texture_creator();
+        let mut texture = texture_creator
+            .create_texture_streaming(PixelFormatEnum::RGB24, SCREEN_WIDTH, SCREEN_HEIGHT)
+            .map_err(|e| e.to_string())?;
+        let mut rgb = vec![0; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize * 3];
+        let mut samples = Vec::new();
+
+        let frame_time = Duration::from_secs_f64(1.0 / nes.region().frame_rate());
+        let mut next_frame = Instant::now();
+        if let Some(audio) = &self.audio {
+            audio.resume();
+        }
+
+        for &(controller, button, rate) in &self.turbo_rates {
+            nes.set_turbo_rate(controller, button, rate);
+        }
+
+        self.family_keyboard = nes.input_devices() == InputDevices::FamilyKeyboard;
+        while self.handle_events() {
+            for action in mem::take(&mut self.pressed) {
+                match action {
+                    Action::Hotkey(hotkey) => self.run_hotkey(hotkey, nes, &mut rgb),
+                    Action::Macro(index) => {
+                        let (controller, input_macro) = self.macros[index].clone();
+                        nes.play_macro(controller, input_macro);
+                    }
+                    _ => (),
+                }
+            }
+            let fast_forward = self.is_held(Action::Hotkey(Hotkey::FastForward));
+
+            if !self.paused {
+                for &controller in Controller::ALL.iter() {
+                    nes.set_controller_bits(controller, self.buttons(controller, false));
+                    nes.set_turbo_bits(controller, self.buttons(controller, true));
+                }
+                nes.set_zapper(self.zapper.aim, self.zapper.trigger);
+                nes.set_vaus(self.vaus.position, self.vaus.button);
+                nes.set_power_pad(self.power_pad);
+                nes.set_keyboard(self.keyboard);
+                nes.run_frame();
+
+                nes.take_audio_samples(&mut samples);
+                if let Some(audio) = &self.audio {
+                    let spec = audio.spec();
+                    let bytes_per_frame = (spec.freq as f64 * frame_time.as_secs_f64()) as u32 * 4;
+                    if fast_forward || audio.size() > bytes_per_frame * MAX_QUEUED_FRAMES {
+                        audio.clear();
+                    }
+                    if !fast_forward {
+                        audio.queue_audio(&samples)?;
+                    }
+                }
+                samples.clear();
+            }
+
+            nes.frame_to_rgb(&mut rgb);
+            texture
+                .update(None, &rgb, SCREEN_WIDTH as usize * 3)
+                .map_err(|e| e.to_string())?;
+            self.canvas.clear();
+            self.canvas.copy(&texture, None, None)?;
+            self.canvas.present();
+
+            next_frame += frame_time;
+            let now = Instant::now();
+            if fast_forward {
+                next_frame = now;
+            } else if next_frame > now {
+                thread::sleep(next_frame - now);
+            } else if now - next_frame > frame_time * MAX_LAG_FRAMES {
+                next_frame = now;
+            }
+        }
+        Ok(())
     }
 
-    /*pub fn handle_events(&mut self, nes: &mut Nes) {
-        for event in self.sdl_context.event_pump().unwrap().poll_iter() {
-            match event {
-                Event::Quit { .. } => ::std::process::exit(0),
+    fn run_hotkey(&mut self, hotkey: Hotkey, nes: &mut Nes, rgb: &mut [u8]) {
+        match hotkey {
+            Hotkey::Pause => {
+                self.paused = !self.paused;
+                info!("{}", if self.paused { "Paused" } else { "Resumed" });
+            }
+            Hotkey::Reset => {
+                nes.reset();
+                info!("Reset");
+            }
+            Hotkey::SaveState => {
+                self.state = Some(nes.save_state());
+                info!("Saved the state");
+            }
+            Hotkey::LoadState => match &self.state {
+                Some(state) => {
+                    nes.load_state(state);
+                    info!("Loaded the saved state");
+                }
+                None => warn!("There's no saved state to load"),
+            },
+            // only matters while it's held
+            Hotkey::FastForward => (),
+            Hotkey::RecordMacro => self.toggle_recording(nes),
+            Hotkey::PlayMacro => {
+                if self.recorded.is_empty() {
+                    warn!("There's no recorded macro to play");
+                }
+                for (controller, input_macro) in &self.recorded {
+                    nes.play_macro(*controller, input_macro.clone());
+                }
+            }
+            Hotkey::Screenshot => {
+                let path = numbered_path(&self.screenshot_path, nes.ppu.frame_count() as u32);
+                nes.frame_to_rgb(rgb);
+                match png::write_rgb(&path, SCREEN_WIDTH, SCREEN_HEIGHT, rgb) {
+                    Ok(()) => info!("Saved a screenshot to {:?}", path),
+                    Err(e) => warn!("Failed to write {:?}: {}", path, e),
+                }
+            }
+        }
+    }
+
+    /// Start recording every controller, or stop and save what was pressed
+    /// next to the ROM, e.g. `game-p1-000300.txt`.
+    fn toggle_recording(&mut self, nes: &mut Nes) {
+        if !nes.is_recording_macro(Controller::One) {
+            for &controller in Controller::ALL.iter() {
+                nes.start_macro_recording(controller);
+            }
+            info!("Recording a macro");
+            return;
+        }
 
+        self.recorded.clear();
+        let stem = self
+            .rom_file
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy();
+        for (player, &controller) in Controller::ALL.iter().enumerate() {
+            let input_macro = match nes.stop_macro_recording(controller) {
+                Some(input_macro) => input_macro,
+                None => continue,
+            };
+            // skip controllers nobody touched
+            if input_macro.frames().iter().all(|&bits| bits == 0) {
+                continue;
+            }
+            let path = self
+                .rom_file
+                .with_file_name(format!("{}-p{}.txt", stem, player + 1));
+            let path = numbered_path(&path, nes.ppu.frame_count() as u32);
+            match input_macro.save_to_file(&path) {
+                Ok(()) => info!("Saved a macro to {:?}", path),
+                Err(e) => warn!("{}", e),
+            }
+            self.recorded.push((controller, input_macro));
+        }
+        if self.recorded.is_empty() {
+            info!("Stopped recording, no buttons were pressed");
+        }
+    }
+
+    /// Track the inputs being held, returning false once the window is
+    /// closed or Escape is pressed.
+    fn handle_events(&mut self) -> bool {
+        let events: Vec<Event> = self.event_pump.poll_iter().collect();
+        for event in events {
+            match event {
+                Event::Quit { .. } => return false,
+                Event::KeyDown {
+                    keycode: Some(KEYBOARD_TOGGLE_KEY),
+                    repeat: false,
+                    ..
+                } if self.family_keyboard => self.toggle_keyboard_capture(),
                 Event::KeyDown {
                     keycode: Some(keycode),
+                    repeat: false,
                     ..
-                } => match keycode {
-                    Keycode::A => nes.set_button(Button::A, false, true),
-                    Keycode::B => nes.set_button(Button::B, false, true),
-                    Keycode::H => nes.set_button(Button::Left, false, true),
-                    Keycode::J => nes.set_button(Button::Down, false, true),
-                    Keycode::K => nes.set_button(Button::Up, false, true),
-                    Keycode::L => nes.set_button(Button::Right, false, true),
-                    Keycode::Num1 => nes.set_button(Button::Start, false, true),
-                    Keycode::Num2 => nes.set_button(Button::Select, false, true),
-                    Keycode::Escape => ::std::process::exit(0),
-                    _ => (),
-                },
+                } => {
+                    if self.is_keyboard_captured() {
+                        self.press_family_key(keycode, true);
+                    } else if keycode == Keycode::Escape {
+                        return false;
+                    } else {
+                        self.press(Trigger::Key(keycode));
+                        self.press_power_pad_key(keycode, true);
+                    }
+                }
                 Event::KeyUp {
                     keycode: Some(keycode),
                     ..
-                } => match keycode {
-                    Keycode::A => nes.set_button(Button::A, false, false),
-                    Keycode::B => nes.set_button(Button::B, false, false),
-                    Keycode::H => nes.set_button(Button::Left, false, false),
-                    Keycode::J => nes.set_button(Button::Down, false, false),
-                    Keycode::K => nes.set_button(Button::Up, false, false),
-                    Keycode::L => nes.set_button(Button::Right, false, false),
-                    Keycode::Num1 => nes.set_button(Button::Start, false, false),
-                    Keycode::Num2 => nes.set_button(Button::Select, false, false),
-                    _ => (),
-                },
+                } => {
+                    if self.is_keyboard_captured() {
+                        self.press_family_key(keycode, false);
+                    } else {
+                        self.release(Trigger::Key(keycode));
+                        self.press_power_pad_key(keycode, false);
+                    }
+                }
+
+                // the canvas's logical size means these are in screen pixels
+                Event::MouseMotion { x, y, .. } => {
+                    self.zapper.aim = screen_pixel(x, y);
+                    self.vaus.position = vaus_position(x);
+                }
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } => {
+                    self.zapper.aim = screen_pixel(x, y);
+                    self.zapper.trigger = true;
+                    self.vaus.button = true;
+                }
+                Event::MouseButtonUp {
+                    mouse_btn: MouseButton::Left,
+                    ..
+                } => {
+                    self.zapper.trigger = false;
+                    self.vaus.button = false;
+                }
+                Event::Window {
+                    win_event: WindowEvent::Leave,
+                    ..
+                } => self.zapper.aim = None,
+
+                Event::ControllerDeviceAdded { which, .. } => self.add_pad(which),
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    self.pads.retain(|pad| pad.instance_id() != which);
+                    // the other gamepads may have changed players
+                    self.held.retain(|held| matches!(held, Trigger::Key(_)));
+                }
+                Event::ControllerButtonDown { which, button, .. } => {
+                    if let Some(pad) = self.pad_index(which) {
+                        self.press(Trigger::PadButton(Some(pad), button));
+                    }
+                }
+                Event::ControllerButtonUp { which, button, .. } => {
+                    if let Some(pad) = self.pad_index(which) {
+                        self.release(Trigger::PadButton(Some(pad), button));
+                    }
+                }
+                Event::ControllerAxisMotion {
+                    which, axis, value, ..
+                } => {
+                    if let Some(pad) = self.pad_index(which) {
+                        for &(positive, pushed) in &[
+                            (true, value > AXIS_THRESHOLD),
+                            (false, value < -AXIS_THRESHOLD),
+                        ] {
+                            let trigger = Trigger::PadAxis(Some(pad), axis, positive);
+                            if pushed {
+                                self.press(trigger);
+                            } else {
+                                self.release(trigger);
+                            }
+                        }
+                    }
+                }
                 _ => (),
             }
         }
-    }*/
+        true
+    }
+
+    fn add_pad(&mut self, joystick_index: u32) {
+        let subsystem = match &self.controller_subsystem {
+            Some(subsystem) => subsystem,
+            None => return,
+        };
+        match subsystem.open(joystick_index) {
+            Ok(pad) => {
+                info!("Gamepad {} is {}", self.pads.len() + 1, pad.name());
+                self.pads.push(pad);
+            }
+            Err(e) => warn!("Failed to open gamepad {}: {}", joystick_index, e),
+        }
+    }
+
+    /// Which player's gamepad an SDL joystick instance is.
+    fn pad_index(&self, instance_id: u32) -> Option<usize> {
+        self.pads
+            .iter()
+            .position(|pad| pad.instance_id() == instance_id)
+    }
+
+    fn press(&mut self, trigger: Trigger) {
+        if self.held.contains(&trigger) {
+            return;
+        }
+        self.held.push(trigger);
+        for &(bound, action) in &self.bindings {
+            if let Action::Hotkey(_) | Action::Macro(_) = action {
+                if bound.matches(trigger) {
+                    self.pressed.push(action);
+                }
+            }
+        }
+    }
+
+    /// Keys also go to the Power Pad if it's plugged in.
+    fn press_power_pad_key(&mut self, keycode: Keycode, pressed: bool) {
+        if let Some(button) = POWER_PAD_KEYS.iter().position(|&key| key == keycode) {
+            if pressed {
+                self.power_pad |= 1 << button;
+            } else {
+                self.power_pad &= !(1 << button);
+            }
+        }
+    }
+
+    fn press_family_key(&mut self, keycode: Keycode, pressed: bool) {
+        if let Some(key) = family_key(keycode) {
+            self.keyboard.set(key, pressed);
+        }
+    }
+
+    fn is_keyboard_captured(&self) -> bool {
+        self.family_keyboard && self.keyboard_captured
+    }
+
+    /// Switch the PC keyboard between the Family BASIC keyboard and the
+    /// bindings, letting go of whatever it was holding.
+    fn toggle_keyboard_capture(&mut self) {
+        self.keyboard_captured = !self.keyboard_captured;
+        self.keyboard = KeyboardState::default();
+        self.power_pad = 0;
+        self.held.retain(|held| !matches!(held, Trigger::Key(_)));
+        if self.keyboard_captured {
+            info!("Typing on the Family BASIC keyboard");
+        } else {
+            info!("Using the key bindings");
+        }
+    }
+
+    fn release(&mut self, trigger: Trigger) {
+        self.held.retain(|&held| held != trigger);
+    }
+
+    fn is_held(&self, action: Action) -> bool {
+        self.bindings.iter().any(|&(bound, bound_action)| {
+            bound_action == action && self.held.iter().any(|&held| bound.matches(held))
+        })
+    }
+
+    /// The bits for a controller's buttons being held, or the ones held
+    /// with turbo.
+    fn buttons(&self, controller: Controller, turbo: bool) -> u8 {
+        self.bindings
+            .iter()
+            .filter_map(|&(bound, action)| {
+                let (bound_controller, button) = match action {
+                    Action::Button(bound_controller, button) if !turbo => {
+                        (bound_controller, button)
+                    }
+                    Action::Turbo(bound_controller, button) if turbo => (bound_controller, button),
+                    _ => return None,
+                };
+                if bound_controller == controller
+                    && self.held.iter().any(|&held| bound.matches(held))
+                {
+                    Some(button.bit())
+                } else {
+                    None
+                }
+            })
+            .fold(0, |bits, bit| bits | bit)
+    }
+}
+
+/// The screen pixel at a point on the canvas, `None` in the letterboxing.
+fn screen_pixel(x: i32, y: i32) -> Option<(u8, u8)> {
+    if (0..SCREEN_WIDTH as i32).contains(&x) && (0..SCREEN_HEIGHT as i32).contains(&y) {
+        Some((x as u8, y as u8))
+    } else {
+        None
+    }
+}
+
+/// The Arkanoid knob position for a point on the canvas, the paddle
+/// follows the mouse across the screen.
+fn vaus_position(x: i32) -> u8 {
+    let x = x.clamp(0, SCREEN_WIDTH as i32 - 1);
+    let range = (vaus::MAX_POSITION - vaus::MIN_POSITION) as i32;
+    vaus::MIN_POSITION + (x * range / (SCREEN_WIDTH as i32 - 1)) as u8
+}
+
+/// The Family BASIC key in the same place as a PC key, going by the
+/// labels.
+fn family_key(keycode: Keycode) -> Option<FamilyKey> {
+    let name = keycode.name().to_ascii_lowercase();
+    let name = match name.as_str() {
+        "left shift" => "left-shift",
+        "right shift" => "right-shift",
+        "left ctrl" | "right ctrl" => "ctr",
+        "left alt" => "grph",
+        "right alt" => "kana",
+        "escape" => "esc",
+        "home" => "clr-home",
+        "end" => "stop",
+        "insert" => "ins",
+        "delete" | "backspace" => "del",
+        "\\" => "yen",
+        "=" => "^",
+        "'" => ":",
+        "`" => "@",
+        "," => "comma",
+        name => name,
+    };
+    name.parse().ok()
+}
+
+fn resolve_bindings(bindings: &Bindings) -> Result<Vec<(Trigger, Action)>, String> {
+    bindings
+        .iter()
+        .map(|(action, input)| Ok((Trigger::resolve(action, input)?, action)))
+        .collect()
+}
+
+fn open_audio(sdl_context: &Sdl, sample_rate: u32) -> Result<AudioQueue<f32>, String> {
+    let audio_subsystem = sdl_context.audio()?;
+    let desired = AudioSpecDesired {
+        freq: Some(sample_rate as i32),
+        channels: Some(1),
+        samples: None,
+    };
+    audio_subsystem.open_queue(None, &desired)
 }
@@ -9,11 +9,8 @@ var currentVolume = 100;
 var audioContext;
 // used to control master volume
 var gainNode;
-var channel1;
-var channel2;
-var channel3;
-var channel4;
-var channel5;
+// when the next chunk of audio should start playing
+var nextAudioTime = 0;
 
 const buttons = {
     RIGHT:  0x01,
@@ -29,21 +26,34 @@ const buttons = {
 async function initSound() {
     audioContext = new window.AudioContext();
     gainNode = audioContext.createGain();
-    channel1 = audioContext.createOscillator();
-    channel1.type = "square";
-    channel1.connect(gainNode);
-
-    channel2 = audioContext.createOscillator();
-    channel2.type = "square";
-    channel2.connect(gainNode);
-
-    channel3 = audioContext.createOscillator();
-    channel3.type = "triangle";
-    channel3.connect(gainNode);
-
     gainNode.connect(audioContext.destination);
 }
 
+// plays the samples the emulator produced during the last frame
+function queueAudio() {
+    const exports = rustWasm.instance.exports;
+    const numSamples = exports.take_audio_samples(emulatorPtr);
+    if (numSamples == 0) {
+        return;
+    }
+    const ptr = exports.audio_buffer(emulatorPtr);
+    const samples = new Float32Array(exports.memory.buffer, ptr, numSamples);
+
+    const buffer = audioContext.createBuffer(1, numSamples, audioContext.sampleRate);
+    buffer.copyToChannel(samples, 0);
+    const source = audioContext.createBufferSource();
+    source.buffer = buffer;
+    source.connect(gainNode);
+
+    // start a little ahead if we've fallen behind, to avoid gaps
+    const now = audioContext.currentTime;
+    if (nextAudioTime < now) {
+        nextAudioTime = now + 0.05;
+    }
+    source.start(nextAudioTime);
+    nextAudioTime += buffer.duration;
+}
+
 async function start() {
     console.log("start");
     const canvas = document.getElementById('canvas');
@@ -54,26 +64,19 @@ async function start() {
 
     imageData = ctx.createImageData(256, 240);
 
+    // browsers only allow audio to start after user input
+    audioContext.resume();
+
     await loadWasm();
 }
 
 function draw_to_screen(ptr) {
     var imageData = ctx.getImageData(0, 0, 256, 240);
-    var data = imageData.data;
 
     const wasmMemory = new Uint8Array(rustWasm.instance.exports.memory.buffer);
 
-    for (var y = 0; y < 240; y++) {
-        for (var x = 0; x < 256; x++) {
-            const wasmIdx = ((y * 256) + x) * 3;
-            const screenIdx = ((y * 256) + x) * 4;
-            // RGB
-            for (var i = 0; i < 3; i++) {
-                data[screenIdx + i] = wasmMemory[ptr + wasmIdx + i];
-            }
-            data[screenIdx + 3] = 255;
-        }
-    }
+    // the emulator writes RGBA in the same layout as ImageData
+    imageData.data.set(wasmMemory.subarray(ptr, ptr + 256 * 240 * 4));
     ctx.putImageData(imageData, 0, 0);
 }
 
@@ -145,6 +148,11 @@ const loadWasm = async () => {
 
     emulatorPtr = rustWasm.instance.exports.create_emulator(bytePtr, romBytesLen);
     rustWasm.instance.exports.free_bytes(bytePtr, romBytesLen);
+    // the reason is in the console
+    if (emulatorPtr === 0) {
+        return;
+    }
+    rustWasm.instance.exports.set_sample_rate(emulatorPtr, audioContext.sampleRate);
 
     window.requestAnimationFrame(runFrame);
 };
@@ -153,6 +161,7 @@ const runFrame = () => {
     // TODO: handle endianness, ensure this is little endian on all platforms.
     const input = (player2Controller << 8) | player1Controller;
     rustWasm.instance.exports.run_frame(emulatorPtr, input);
+    queueAudio();
     window.requestAnimationFrame(runFrame);
 };
 